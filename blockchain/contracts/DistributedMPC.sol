@@ -1,12 +1,13 @@
-pragma solidity ^0.4.11;
+pragma solidity ^0.4.22;
 
 import "./MultiPartyProtocol.sol";
 
 contract DistributedMPC is MultiPartyProtocol {
-    function DistributedMPC(bytes r1cs) 
+    function DistributedMPC(bytes r1cs, uint timeoutBlocks, uint timeoutSeconds, address[] expected, uint maximumPlayers, bytes32 powersOfTauHash,
+        uint beaconDelayBlocks, uint beaconIterationsExponent) 
         public
         isNotEmptyBytes(r1cs) 
-        MultiPartyProtocol(r1cs)
+        MultiPartyProtocol(r1cs, timeoutBlocks, timeoutSeconds, expected, maximumPlayers, powersOfTauHash, beaconDelayBlocks, beaconIterationsExponent)
     {
         join();
     }
@@ -15,21 +16,53 @@ contract DistributedMPC is MultiPartyProtocol {
         public 
         isInState(State.Init) 
         isNewPlayer 
+        isExpectedPlayer 
+        isNotFull 
     {
         players.push(msg.sender);
         PlayerJoined(msg.sender);
     }
 
+    //makes the protocol a fork of a failed one, before the coordinator commits
+    function fork(address parent, uint stage, uint player, bytes provenance)
+        public
+        isSenderCoordinator
+        isInState(State.Init)
+        isNotEmptyBytes(provenance)
+    {
+        require(parentContract == address(0), "The protocol is already a fork");
+        require(parent != address(0) && parent != address(this), "Invalid parent protocol");
+        require(stage < protocol.stageTransformations.length, "There is no such stage");
+        require(protocol.stageCommit.playerData[msg.sender].commitment == "", "The coordinator has already committed");
+        //the parent reached the stage, and the player published its transformation of it
+        DistributedMPC parentProtocol = DistributedMPC(parent);
+        uint stageState = uint(State.Stage1) + stage;
+        require(uint(parentProtocol.currentState()) >= stageState, "The parent has not reached the stage");
+        require(player < parentProtocol.getNumberOfPlayers(), "There is no such player in the parent");
+        require(parentProtocol.powersOfTau() == powersOfTau, "The parent imports other powers of tau");
+        uint excludedIn = parentProtocol.getExclusionState(player);
+        require(excludedIn == 0 || excludedIn > stageState, "The player was excluded before its transformation");
+        if(uint(parentProtocol.currentState()) == stageState){
+            require(parentProtocol.currentTurn() > player, "The player has not published its transformation");
+        }
+        parentContract = parent;
+        forkStage = stage;
+        forkPlayer = player;
+        forkProvenance = provenance;
+        CeremonyForked(parent, stage, player);
+    }
+
     function commit(bytes32 commitment) 
         public
         isSenderPlayer
+        isSenderActivePlayer
         isNotEmptyBytes32(commitment)
         isEmptyBytes32(protocol.stageCommit.playerData[msg.sender].commitment)
     {
-        require(currentState == State.Commit || currentState == State.Init);
+        require(currentState == State.Commit || currentState == State.Init, "The commitments are closed");
         if(protocol.stageCommit.playerData[players[0]].commitment.length == 0){
             //We require coordinator to commit first. That implicitly starts the protocol.
-            require(msg.sender == players[0]);
+            require(msg.sender == players[0], "The coordinator has to commit first");
         }
 
         protocol.stageCommit.playerData[msg.sender].initialized = true;
@@ -49,6 +82,7 @@ contract DistributedMPC is MultiPartyProtocol {
         public
         isInState(State.Reveal)
         isSenderPlayer
+        isSenderActivePlayer
         isNotEmptyBytes(publicKey)
         isEmptyBytes(protocol.stageCommit.playerData[msg.sender].publicKey)
     {
@@ -62,6 +96,7 @@ contract DistributedMPC is MultiPartyProtocol {
         public
         isInState(State.Nizks)
         isSenderPlayer
+        isSenderActivePlayer
         isNotEmptyBytes(nizks)
         isEmptyBytes(protocol.stageCommit.playerData[msg.sender].nizks)
     {                                 
@@ -71,34 +106,93 @@ contract DistributedMPC is MultiPartyProtocol {
         }
     }
 
-    function setInitialStage(bytes stage, bytes stageTransformed) 
+    function setInitialStage(bytes stage, bytes stageTransformed, bytes contribution) 
         public
         isSenderCoordinator
         isInStageTransformationState
     {
         uint stateIndex = uint(currentState) - uint(State.Stage1); // 0 for stage 1, ... 2 for stage 3
-        require(isBytesEmpty(protocol.initialStages[stateIndex]));
+        require(isBytesEmpty(protocol.initialStages[stateIndex]), "The stage is already initialized");
+        require(isPreviousBeaconRecorded(), "The beacon of the previous stage is not recorded");
         protocol.initialStages[stateIndex] = stage;
         protocol.latestTransformation = stage;
         StagePrepared(uint(currentState), stage);
-        publishStageResults(stageTransformed);
+        publishStageResults(stageTransformed, contribution);
     }
 
-    function publishStageResults(bytes stageTransformed)
+    function publishStageResults(bytes stageTransformed, bytes contribution)
         public
         isInStageTransformationState
         isSenderPlayer
+        isSenderActivePlayer
         isNotEmptyBytes(stageTransformed)
-        previousPlayerCommitted
+        isNotEmptyBytes(contribution)
+        isSendersTurn
     {
         uint stateIndex = uint(currentState) - uint(State.Stage1);
-        require(isBytesEmpty(protocol.stageTransformations[stateIndex].playerData[msg.sender]));
+        require(isBytesEmpty(protocol.stageTransformations[stateIndex].playerData[msg.sender]), "You have already published your transformation");
         protocol.stageTransformations[stateIndex].playerData[msg.sender] = stageTransformed;
+        protocol.stageTransformations[stateIndex].contributions[msg.sender] = contribution;
         protocol.latestTransformation = stageTransformed;
         StageResultPublished(msg.sender, stageTransformed);
-        if(isLastPlayer()){
-            nextStage();
+        advance();
+    }
+
+    //excludes the players who missed the deadline of the current turn, so the protocol continues without them:
+    //in a stage, the next active player builds on the result of the last one who published
+    function excludeMissingPlayers()
+        public
+        isSenderPlayer
+        isSenderActivePlayer
+    {
+        require(isTurnExpired(), "The turn has not expired");
+        if(isInTransformationStage()){
+            uint turn = currentTurn();
+            require(turn > 0 && turn < players.length, "The coordinator cannot be excluded");   //the coordinator cannot be excluded
+            exclude(players[turn]);
+        } else {
+            require(!isMissing(0), "The coordinator cannot be excluded");
+            bool excluded = false;
+            for(uint i = 1; i < players.length; i++){
+                if(isMissing(i)){
+                    exclude(players[i]);
+                    excluded = true;
+                }
+            }
+            require(excluded, "No player is missing");
         }
+        advance();
+    }
+
+    function exclude(address player) internal {
+        exclusionState[player] = uint(currentState);
+        PlayerExcluded(player, uint(currentState));
+    }
+
+    //records the hash of the block beaconDelay blocks after the last transformation of the stage as its random beacon,
+    //which anyone can do once the block is mined. The contract only knows the hashes of the last 256 blocks: if it is
+    //recorded later, the last block a multiple of 256 blocks after it is used instead, so the protocol cannot get stuck
+    function recordBeacon(uint stage)
+        public
+    {
+        require(stage < protocol.stageTransformations.length, "There is no such stage");
+        require(stageEndBlocks[stage] != 0, "The stage has not ended");
+        require(beaconBlocks[stage] == 0, "The beacon of the stage is already recorded");
+        uint blockNumber = stageEndBlocks[stage] + beaconDelay;
+        require(blockNumber < block.number, "The beacon block has not been mined yet");
+        blockNumber += (block.number - blockNumber - 1) / 256 * 256;
+        beaconBlocks[stage] = blockNumber;
+        beaconHashes[stage] = blockhash(blockNumber);
+        BeaconRecorded(stage, blockNumber, beaconHashes[stage]);
+    }
+
+    function getExclusionState(uint playerIndex)
+        constant
+        public
+        returns (uint)
+    {
+        require(playerIndex < players.length, "There is no such player");
+        return exclusionState[players[playerIndex]];
     }
 
     function getCommitment(address player)
@@ -122,7 +216,7 @@ contract DistributedMPC is MultiPartyProtocol {
         public
         returns (bytes) 
     {
-        require(stage < protocol.stageTransformations.length);
+        require(stage < protocol.stageTransformations.length, "There is no such stage");
         return protocol.initialStages[stage];
     }
 
@@ -131,11 +225,21 @@ contract DistributedMPC is MultiPartyProtocol {
         public
         returns (bytes)
     {
-        require(stage < protocol.stageTransformations.length);
-        require(playerIndex < players.length);
+        require(stage < protocol.stageTransformations.length, "There is no such stage");
+        require(playerIndex < players.length, "There is no such player");
         return protocol.stageTransformations[stage].playerData[players[playerIndex]];
     }
 
+    function getContribution(uint stage, uint playerIndex)
+        constant
+        public
+        returns (bytes)
+    {
+        require(stage < protocol.stageTransformations.length, "There is no such stage");
+        require(playerIndex < players.length, "There is no such player");
+        return protocol.stageTransformations[stage].contributions[players[playerIndex]];
+    }
+
     function getLatestTransformation() 
         constant 
         public 
@@ -149,7 +253,7 @@ contract DistributedMPC is MultiPartyProtocol {
         public
         returns (bytes)
     {
-        require(playerIndex < players.length);
+        require(playerIndex < players.length, "There is no such player");
         return protocol.stageCommit.playerData[players[playerIndex]].nizks;
     }
 
@@ -161,12 +265,20 @@ contract DistributedMPC is MultiPartyProtocol {
         return players.length;
     }
 
+    function getNumberOfExpectedPlayers()
+        constant
+        public
+        returns (uint)
+    {
+        return expectedPlayers.length;
+    }
+
     function getPublicKey(uint playerIndex)
         constant
         public
         returns (bytes)
     {
-        require(playerIndex < players.length);
+        require(playerIndex < players.length, "There is no such player");
         return protocol.stageCommit.playerData[players[playerIndex]].publicKey;
     }
 }
\ No newline at end of file
@@ -1,4 +1,4 @@
-pragma solidity ^0.4.11;
+pragma solidity ^0.4.22;
 
 contract MultiPartyProtocol {
 
@@ -15,6 +15,7 @@ contract MultiPartyProtocol {
 
     struct StageTransform {
         mapping (address => bytes) playerData;
+        mapping (address => bytes) contributions;   //signed statement of the player about its transformation
     }
     
     struct Protocol {
@@ -30,49 +31,72 @@ contract MultiPartyProtocol {
     event NextStage(uint stage);  //called when a new stage begins
     event StagePrepared(uint stage, bytes result);  //called when the coordinator initialized a new stage (stage1, stage2, stage3)
     event StageResultPublished(address player, bytes result);
+    event PlayerExcluded(address player, uint stage);  //called when a player missed the deadline of its turn in the given stage
+    event CeremonyForked(address parent, uint stage, uint player);  //called when the protocol continues a failed one from one of its transformations
+    event BeaconRecorded(uint stage, uint blockNumber, bytes32 blockHash);  //called when the hash of a block is recorded as the random beacon of a stage
     
     modifier isSenderCoordinator(){
-        require(msg.sender == players[0]);
+        require(msg.sender == players[0], "Only the coordinator can do this");
         _;
     }
     
     modifier isEmptyBytes(bytes h){
-        require(h.length == 0);
+        require(h.length == 0, "Already published");
         _;
     }
 
     modifier isEmptyBytes32(bytes32 h){
-        require(h == "");
+        require(h == "", "Already committed");
         _;
     }
 
     modifier isNewPlayer (){
         for(uint i = 0; i < players.length; i++){
-            require(players[i] != msg.sender);
+            require(players[i] != msg.sender, "You have already joined");
         }
         _;
     }
     
+    //only the expected players may join, if the protocol was deployed with a list of them. The coordinator joins on deployment
+    modifier isExpectedPlayer (){
+        bool found = players.length == 0 || expectedPlayers.length == 0;
+        for(uint i = 0; i < expectedPlayers.length && !found; i++){
+            found = expectedPlayers[i] == msg.sender;
+        }
+        require(found, "You are not an expected player");
+        _;
+    }
+
+    modifier isNotFull (){
+        require(maxPlayers == 0 || players.length < maxPlayers, "The protocol is full");
+        _;
+    }
+    
     modifier isNotEmptyBytes(bytes h){
-        require(h.length > 0);
+        require(h.length > 0, "The data is empty");
         _;
     }
 
     modifier isNotEmptyBytes32(bytes32 h){
-        require(h.length > 0);
+        require(h != "", "The commitment is empty");
         _;
     }
     
     modifier isInState(State s){
-        require(currentState == s);
+        require(currentState == s, "Not possible in the current state of the protocol");
         _;
     }
 
     modifier isInStageTransformationState(){
-        require(isInTransformationStage());
+        require(isInTransformationStage(), "The protocol is not in a stage");
         _;
     }
     
+    modifier isSenderActivePlayer(){
+        require(isActive(msg.sender), "You have been excluded from the protocol");
+        _;
+    }
+
     modifier isSenderPlayer (){
         bool found = false;
         for(uint i = 0; i < players.length; i++){
@@ -81,25 +105,20 @@ contract MultiPartyProtocol {
                 break;
             }
         }
-        require(found);
+        require(found, "You are not a player");
         _;
     }
 
-    modifier previousPlayerCommitted() {
+    //the previous active player has published its result, or the coordinator initialized the stage
+    modifier isSendersTurn() {
         uint pIndex = getPlayerIndex();
-        require(isInTransformationStage());
+        require(isInTransformationStage(), "The protocol is not in a stage");
         uint stageIndex = uint(currentState) - uint(State.Stage1);
         if(pIndex == 0){
             bytes memory initialStage = protocol.initialStages[stageIndex];
-            require(!isBytesEmpty(initialStage));
-        } else {
-            require(
-                !isBytesEmpty(
-                    protocol.stageTransformations[stageIndex]
-                    .playerData[players[pIndex-1]]
-                )
-            );
+            require(!isBytesEmpty(initialStage), "The stage is not initialized");
         }
+        require(currentTurn() == pIndex, "It is not your turn");
         _;
     }
     
@@ -107,9 +126,51 @@ contract MultiPartyProtocol {
     State public currentState = State.Init;
     address[] public players;
     Protocol protocol;
+
+    //time a player has for its turn, in blocks and in seconds (0 = no limit)
+    uint public turnTimeoutBlocks;
+    uint public turnTimeoutSeconds;
+    uint public turnStartBlock;
+    uint public turnStartTime;
+    mapping (address => uint) exclusionState;   //state in which a player was excluded, 0 if active
+
+    //a fork continues the transformation of stage forkStage + 1 published by player forkPlayer of the parent protocol,
+    //the stages before are taken from the parent
+    address public parentContract;
+    uint public forkStage;
+    uint public forkPlayer;
+    bytes public forkProvenance;   //the verified contributions of the parent
+    uint public deploymentBlock;   //block the protocol was deployed in, from which its events are replayed
+
+    //the block in which the last transformation of each stage was published, and the block whose hash is the random beacon
+    //applied to the result of the stage (0 = not recorded yet), beaconDelay blocks after it. The hash is hashed
+    //2^beaconIterations times before it is used
+    uint[3] public stageEndBlocks;
+    uint[3] public beaconBlocks;
+    bytes32[3] public beaconHashes;
+    uint public beaconDelay;
+    uint public beaconIterations;
+
+    //the players who may join besides the coordinator (empty = anyone), and the maximum number of players including it (0 = no limit)
+    address[] public expectedPlayers;
+    uint public maxPlayers;
+
+    //Keccak-256 hash of the powers of tau file stage 1 is imported from (0 = none), so that all players import the same one
+    bytes32 public powersOfTau;
     
-    function MultiPartyProtocol(bytes r1cs) public {
+    function MultiPartyProtocol(bytes r1cs, uint timeoutBlocks, uint timeoutSeconds, address[] expected, uint maximumPlayers, bytes32 powersOfTauHash,
+        uint beaconDelayBlocks, uint beaconIterationsExponent) public {
+        require(beaconDelayBlocks > 0, "The beacon block has to come after the last transformation of the stage");
+        require(beaconIterationsExponent < 64, "The beacon can be hashed at most 2^63 times");
         protocol.r1cs = r1cs;
+        powersOfTau = powersOfTauHash;
+        deploymentBlock = block.number;
+        turnTimeoutBlocks = timeoutBlocks;
+        turnTimeoutSeconds = timeoutSeconds;
+        expectedPlayers = expected;
+        maxPlayers = maximumPlayers;
+        beaconDelay = beaconDelayBlocks;
+        beaconIterations = beaconIterationsExponent;
         protocol.initialStages = new bytes[](3);
         protocol.stageCommit = StageCommit();
         protocol.stageTransformations.push(StageTransform());
@@ -119,7 +180,15 @@ contract MultiPartyProtocol {
     
     function nextStage() internal returns (bool){
         if(currentState != State.Finished){
-            currentState = State(uint(currentState) + 1);
+            if(isInTransformationStage()){
+                stageEndBlocks[uint(currentState) - uint(State.Stage1)] = block.number;
+            }
+            if(currentState == State.Nizks){
+                currentState = State(uint(State.Stage1) + forkStage);   //a fork skips the stages taken from its parent
+            } else {
+                currentState = State(uint(currentState) + 1);
+            }
+            startTurn();
             NextStage(uint(currentState));
             return true;
         } else {
@@ -127,9 +196,81 @@ contract MultiPartyProtocol {
         }
     }
 
+    function startTurn() internal {
+        turnStartBlock = block.number;
+        turnStartTime = now;
+    }
+
+    //goes to the next stage if all active players are done with the current one, otherwise starts the next turn
+    function advance() internal {
+        require(isPreviousBeaconRecorded(), "The beacon of the previous stage is not recorded");
+        if((currentState == State.Commit && allCommitmentsReady()) ||
+        (currentState == State.Reveal && allCommitmentsRevealed()) ||
+        (currentState == State.Nizks && allNizksReady()) ||
+        (isInTransformationStage() && currentTurn() == players.length)){
+            nextStage();
+        } else {
+            startTurn();
+        }
+    }
+
+    //whether the beacon of the stage before the current one is recorded: the first stage has none, and a fork
+    //takes the stages before forkStage, with their beacons, from its parent
+    function isPreviousBeaconRecorded() constant internal returns (bool) {
+        if(!isInTransformationStage()){
+            return true;
+        }
+        uint stageIndex = uint(currentState) - uint(State.Stage1);
+        return stageIndex <= forkStage || beaconBlocks[stageIndex - 1] != 0;
+    }
+
+    function isActive(address player) constant internal returns (bool) {
+        return exclusionState[player] == 0;
+    }
+
+    //index of the active player whose turn it is in the current stage, or the number of players if all are done
+    function currentTurn() constant public returns (uint) {
+        if(!isInTransformationStage()){
+            return players.length;
+        }
+        uint stageIndex = uint(currentState) - uint(State.Stage1);
+        for(uint i = 0; i < players.length; i++){
+            if(isActive(players[i]) && isBytesEmpty(protocol.stageTransformations[stageIndex].playerData[players[i]])){
+                return i;
+            }
+        }
+        return players.length;
+    }
+
+    function isTurnExpired() constant public returns (bool) {
+        if(currentState == State.Init || currentState == State.Finished){
+            return false;
+        }
+        return (turnTimeoutBlocks > 0 && block.number >= turnStartBlock + turnTimeoutBlocks) ||
+        (turnTimeoutSeconds > 0 && now >= turnStartTime + turnTimeoutSeconds);
+    }
+
+    //whether the active player at the index has not done its part of the current state yet
+    function isMissing(uint playerIndex) constant public returns (bool) {
+        require(playerIndex < players.length, "There is no such player");
+        PlayerData storage data = protocol.stageCommit.playerData[players[playerIndex]];
+        if(!isActive(players[playerIndex])){
+            return false;
+        } else if(currentState == State.Commit){
+            return !data.initialized;
+        } else if(currentState == State.Reveal){
+            return isBytesEmpty(data.publicKey);
+        } else if(currentState == State.Nizks){
+            return isBytesEmpty(data.nizks);
+        } else if(isInTransformationStage()){
+            return currentTurn() == playerIndex;
+        }
+        return false;
+    }
+
     function allCommitmentsReady() constant internal returns (bool) {
         for(uint i = 0; i < players.length; i++){
-            if(!(protocol.stageCommit.playerData[players[i]].initialized &&
+            if(isActive(players[i]) && !(protocol.stageCommit.playerData[players[i]].initialized &&
             protocol.stageCommit.playerData[players[i]].commitment.length > 0)){
                 return false;
             }
@@ -139,7 +280,7 @@ contract MultiPartyProtocol {
 
     function allNizksReady() constant internal returns (bool) {
         for(uint i = 0; i < players.length; i++){
-            if(!(protocol.stageCommit.playerData[players[i]].initialized &&
+            if(isActive(players[i]) && !(protocol.stageCommit.playerData[players[i]].initialized &&
             protocol.stageCommit.playerData[players[i]].nizks.length > 0)){
                 return false;
             }
@@ -150,7 +291,7 @@ contract MultiPartyProtocol {
     function allCommitmentsRevealed() constant internal returns (bool) {
         for(uint i = 0; i < players.length; i++){
             bytes memory pubKey = protocol.stageCommit.playerData[players[i]].publicKey;
-            if(!(protocol.stageCommit.playerData[players[i]].initialized 
+            if(isActive(players[i]) && !(protocol.stageCommit.playerData[players[i]].initialized 
             && !isBytesEmpty(pubKey))){
                 return false;
             }
@@ -164,11 +305,7 @@ contract MultiPartyProtocol {
                 return i;
             }
         }
-        require(false);
-    }
-
-    function isLastPlayer() constant internal returns (bool) {
-        return players[players.length - 1] == msg.sender;
+        revert("You are not a player");
     }
 
     function isInTransformationStage() constant internal returns (bool){
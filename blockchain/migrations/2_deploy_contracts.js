@@ -7,5 +7,7 @@ var r1cs = fs.readFileSync('../r1cs', {
 console.log('r1cs: %s', r1cs);
 
 module.exports = function(deployer) {
-  deployer.deploy(DistributedMPC, r1cs);
+  //no limit in blocks, one hour per turn, anyone can join, no powers of tau,
+  //the beacon of each stage 10 blocks after its last transformation, hashed 2^20 times
+  deployer.deploy(DistributedMPC, r1cs, 0, 3600, [], 0, "0x" + "0".repeat(64), 10, 20);
 };
@@ -0,0 +1,41 @@
+var DistMpc = artifacts.require("./DistributedMPC.sol");
+var assert = require("assert");
+var fs = require("fs");
+var path = require("path");
+
+//the player is built from these copies of the compiled contract, so that it needs neither truffle nor node
+const ABI_FILE = path.join(__dirname, "..", "..", "mpc", "abi.json");
+const BYTECODE_FILE = path.join(__dirname, "..", "contracts", "DistributedMPC.bin");
+
+let signature = (entry) => {
+  let kinds = (params) => (params || []).map(param => param.type).join(",");
+  return entry.type + " " + (entry.name || "") + "(" + kinds(entry.inputs) + ") returns (" + kinds(entry.outputs) + ")"
+    + (entry.constant ? " constant" : "");
+}
+
+let signatures = (abi) => {
+  return abi.map(signature).sort();
+}
+
+contract('DistributedMPC build', accounts => {
+
+  describe('Copies of the compiled contract', () => {
+
+    it("abi.json should be the ABI of the contract", () => {
+      let committed = JSON.parse(fs.readFileSync(ABI_FILE, "utf8"));
+      assert.deepEqual(signatures(committed), signatures(DistMpc.abi), "Copy the ABI of the contract to mpc/abi.json");
+    });
+
+    //run with UPDATE_BYTECODE=1 to write the bytecode of the compiled contract to the file
+    it("DistributedMPC.bin should be the bytecode of the contract", () => {
+      let compiled = DistMpc.bytecode.replace(/^0x/, "");
+      if (process.env.UPDATE_BYTECODE) {
+        fs.writeFileSync(BYTECODE_FILE, compiled);
+      }
+      let committed = fs.readFileSync(BYTECODE_FILE, "utf8").trim();
+      assert.equal(committed, compiled, "Run UPDATE_BYTECODE=1 truffle test to copy the bytecode of the contract to DistributedMPC.bin");
+    });
+
+  });
+
+});
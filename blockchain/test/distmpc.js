@@ -1,10 +1,11 @@
 var DistMpc = artifacts.require("./DistributedMPC.sol");
 var assert = require("assert");
 
+//the failure of the assertion must not be caught as the expected failure
 let expectFailHandler = (promise, message) => {
   return promise.then(() => {
     assert.fail(message);
-  }).catch(_ => {
+  }, _ => {
     assert.ok(true);
   });
 }
@@ -25,10 +26,51 @@ let expectEqual = (promise, expectedValue, message) => {
   });
 }
 
+//the hash of the powers of tau of a ceremony that does not import any. The ceremonies are deployed with
+//their beacon 1 block after each stage, hashed 2^0 times, unless the test is about the beacon
+let noPowersOfTau = "0x" + "0".repeat(64);
+
 let getHash = (value) => {
   return "0x" + web3.sha3(value);
 }
 
+//runs the steps one after the other, each gets the result of the previous one
+let inSequence = (steps) => {
+  return steps.reduce((p, step) => p.then(step), Promise.resolve());
+}
+
+let commitment = (player) => {
+  return web3.sha3("commitment" + player);
+}
+
+//joins the players besides the coordinator, and lets all of them commit, reveal and publish their nizks,
+//which starts stage 1
+let startStages = (instance, players) => {
+  return inSequence(
+    players.slice(1).map(player => () => instance.join({from: player}))
+    .concat(players.map((player, i) => () => instance.commit(commitment(i), {from: player})))
+    .concat(players.map(player => () => instance.revealCommitment("publicKey", {from: player})))
+    .concat(players.map(player => () => instance.publishNizks("nizks", {from: player})))
+  ).then(() => instance);
+}
+
+//sends a request to the test node, e.g. to let time pass
+let sendRpc = (method, params) => {
+  return new Promise((resolve, reject) => {
+    web3.currentProvider.sendAsync({jsonrpc: "2.0", method: method, params: params, id: Date.now()}, (error, result) => {
+      return error ? reject(error) : resolve(result);
+    });
+  });
+}
+
+let increaseTime = (seconds) => {
+  return sendRpc("evm_increaseTime", [seconds]).then(() => sendRpc("evm_mine", []));
+}
+
+let mineBlocks = (count) => {
+  return inSequence(Array.from({length: count}, () => () => sendRpc("evm_mine", [])));
+}
+
 contract('DistributedMPC', accounts => {
 
   /***********************************************/
@@ -190,4 +232,437 @@ contract('DistributedMPC', accounts => {
     });
 
   });
+
+  /***********************************************/
+  /************** Expected players ***************/
+  /***********************************************/
+
+  describe('Expected players', () => {
+    it("should record the hash of the powers of tau", () => {
+      let p = DistMpc.new("r1cs", 0, 3600, [], 0, web3.sha3("powersoftau"), 1, 0).then(instance => {
+        return instance.powersOfTau();
+      });
+      return expectEqual(p, web3.sha3("powersoftau"), "The contract should record the hash of the powers of tau.");
+    });
+
+    it("should only let the expected players join", () => {
+      let p = DistMpc.new("r1cs", 0, 3600, [accounts[1]], 0, noPowersOfTau, 1, 0).then(instance => {
+        return instance.join({from: accounts[2]});
+      });
+      return expectFailHandler(p, "An unexpected player should not be able to join.");
+    });
+
+    it("should let an expected player join", () => {
+      let p = DistMpc.new("r1cs", 0, 3600, [accounts[1]], 0, noPowersOfTau, 1, 0).then(instance => {
+        return instance.join({from: accounts[1]});
+      });
+      return expectSuccessHandler(p, "An expected player should be able to join.");
+    });
+
+    it("should fail to join if the maximum number of players has joined", () => {
+      let p = DistMpc.new("r1cs", 0, 3600, [], 2, noPowersOfTau, 1, 0).then(instance => {
+        return instance.join({from: accounts[1]}).then(() => instance.join({from: accounts[2]}));
+      });
+      return expectFailHandler(p, "Joining should fail if the protocol is full.");
+    });
+  });
+
+  /***********************************************/
+  /************** Fork ***************************/
+  /***********************************************/
+
+  describe('Fork', () => {
+    let parent;
+
+    before(() => {
+      return DistMpc.new("r1cs", 0, 3600, [], 0, noPowersOfTau, 1, 0)
+        .then(instance => startStages(instance, [accounts[0], accounts[1]]))
+        .then(instance => { parent = instance; });
+    });
+
+    it("should not fork from a transformation that has not been published", () => {
+      let p = DistMpc.new("r1cs", 0, 3600, [], 0, noPowersOfTau, 1, 0).then(instance => {
+        return instance.fork(parent.address, 0, 0, "provenance");
+      });
+      return expectFailHandler(p, "The coordinator has not published its transformation of stage 1 yet.");
+    });
+
+    it("should fork from a published transformation", () => {
+      let p = parent.setInitialStage("stage1", "stage1_0", "contribution_0", {from: accounts[0]}).then(() => {
+        return DistMpc.new("r1cs", 0, 3600, [], 0, noPowersOfTau, 1, 0);
+      }).then(instance => {
+        return instance.fork(parent.address, 0, 0, "provenance").then(() => instance.parentContract());
+      });
+      return expectEqual(p, parent.address, "The fork should record its parent.");
+    });
+
+    it("should not fork from a stage the parent has not reached", () => {
+      let p = DistMpc.new("r1cs", 0, 3600, [], 0, noPowersOfTau, 1, 0).then(instance => {
+        return instance.fork(parent.address, 1, 0, "provenance");
+      });
+      return expectFailHandler(p, "The parent has not reached stage 2.");
+    });
+
+    it("should not fork from a stage that does not exist", () => {
+      let p = DistMpc.new("r1cs", 0, 3600, [], 0, noPowersOfTau, 1, 0).then(instance => {
+        return instance.fork(parent.address, 3, 0, "provenance");
+      });
+      return expectFailHandler(p, "There are only three stages.");
+    });
+
+    it("should not fork from a player of the parent whose turn has not come", () => {
+      let p = DistMpc.new("r1cs", 0, 3600, [], 0, noPowersOfTau, 1, 0).then(instance => {
+        return instance.fork(parent.address, 0, 1, "provenance");
+      });
+      return expectFailHandler(p, "Player 1 has not published its transformation of stage 1 yet.");
+    });
+
+    it("should not fork from a player who is not in the parent", () => {
+      let p = DistMpc.new("r1cs", 0, 3600, [], 0, noPowersOfTau, 1, 0).then(instance => {
+        return instance.fork(parent.address, 0, 2, "provenance");
+      });
+      return expectFailHandler(p, "The parent has only two players.");
+    });
+
+    it("should not fork from an address that is not a ceremony", () => {
+      let p = DistMpc.new("r1cs", 0, 3600, [], 0, noPowersOfTau, 1, 0).then(instance => {
+        return instance.fork(accounts[3], 0, 0, "provenance");
+      });
+      return expectFailHandler(p, "The parent has to be a ceremony.");
+    });
+
+    it("should not fork if not the coordinator", () => {
+      let p = DistMpc.new("r1cs", 0, 3600, [], 0, noPowersOfTau, 1, 0).then(instance => {
+        return instance.join({from: accounts[1]}).then(() => instance.fork(parent.address, 0, 0, "provenance", {from: accounts[1]}));
+      });
+      return expectFailHandler(p, "Only the coordinator can fork.");
+    });
+
+    it("should not fork twice", () => {
+      let p = DistMpc.new("r1cs", 0, 3600, [], 0, noPowersOfTau, 1, 0).then(instance => {
+        return instance.fork(parent.address, 0, 0, "provenance").then(() => instance.fork(parent.address, 0, 0, "provenance"));
+      });
+      return expectFailHandler(p, "A protocol can only continue one parent.");
+    });
+
+    it("should not fork a parent that imports other powers of tau", () => {
+      let p = DistMpc.new("r1cs", 0, 3600, [], 0, web3.sha3("powersoftau"), 1, 0).then(instance => {
+        return instance.fork(parent.address, 0, 0, "provenance");
+      });
+      return expectFailHandler(p, "A fork has to import the powers of tau of its parent.");
+    });
+
+    it("should not fork after the coordinator has committed", () => {
+      let p = DistMpc.new("r1cs", 0, 3600, [], 0, noPowersOfTau, 1, 0).then(instance => {
+        return instance.commit(commitment(0)).then(() => instance.fork(parent.address, 0, 0, "provenance"));
+      });
+      return expectFailHandler(p, "A protocol can only be forked before it starts.");
+    });
+  });
+
+  /***********************************************/
+  /************** Stages *************************/
+  /***********************************************/
+
+  describe('Stages', () => {
+    let instance;
+
+    before(() => {
+      return DistMpc.new("r1cs", 0, 3600, [], 0, noPowersOfTau, 1, 0)
+        .then(deployed => startStages(deployed, [accounts[0], accounts[1]]))
+        .then(deployed => { instance = deployed; });
+    });
+
+    it("should not let a player initialize the stage if not the coordinator", () => {
+      return expectFailHandler(instance.setInitialStage("stage1", "stage1_1", "contribution_1", {from: accounts[1]}), "Only the coordinator initializes the stages.");
+    });
+
+    it("should initialize the stage with the transformation of the coordinator", () => {
+      let p = instance.setInitialStage("stage1", "stage1_0", "contribution_0", {from: accounts[0]}).then(() => {
+        return Promise.all([instance.getInitialStage(0), instance.getTransformation(0, 0), instance.getContribution(0, 0), instance.getLatestTransformation()]);
+      });
+      return expectEqual(p.then(values => values.map(web3.toUtf8).join()), "stage1,stage1_0,contribution_0,stage1_0", "The stage, the transformation and the contribution should be recorded.");
+    });
+
+    it("should not initialize a stage twice", () => {
+      return expectFailHandler(instance.setInitialStage("stage1", "stage1_0", "contribution_0", {from: accounts[0]}), "The stage is already initialized.");
+    });
+
+    it("should have no contribution of a player before its turn", () => {
+      return expectEqual(instance.getContribution(0, 1).then(web3.toUtf8), "", "Player 1 has not published yet.");
+    });
+
+    it("should fail to publish a transformation without a contribution", () => {
+      return expectFailHandler(instance.publishStageResults("stage1_1", "", {from: accounts[1]}), "The contribution is required.");
+    });
+
+    it("should publish a transformation with its contribution", () => {
+      let p = instance.publishStageResults("stage1_1", "contribution_1", {from: accounts[1]}).then(() => {
+        return Promise.all([instance.getTransformation(0, 1), instance.getContribution(0, 1), instance.getLatestTransformation()]);
+      });
+      return expectEqual(p.then(values => values.map(web3.toUtf8).join()), "stage1_1,contribution_1,stage1_1", "The transformation and the contribution should be recorded.");
+    });
+
+    it("should go to the next stage after all transformations", () => {
+      return expectEqual(instance.currentState(), 5, "Should be in stage 2");
+    });
+
+    it("should not publish a transformation of the last stage again", () => {
+      return expectFailHandler(instance.publishStageResults("stage1_1", "contribution_1", {from: accounts[1]}), "It is the turn of the coordinator in stage 2.");
+    });
+
+    it("should fail to get the contribution of a stage that does not exist", () => {
+      return expectFailHandler(instance.getContribution(3, 0), "There are only three stages.");
+    });
+
+    it("should fail to get the contribution of a player who does not exist", () => {
+      return expectFailHandler(instance.getContribution(0, 2), "There are only two players.");
+    });
+  });
+
+  /***********************************************/
+  /************** Beacon *************************/
+  /***********************************************/
+
+  describe('Beacon', () => {
+    let instance;
+    let stageEnd;
+
+    before(() => {
+      return DistMpc.new("r1cs", 0, 3600, [], 0, noPowersOfTau, 2, 20)
+        .then(deployed => startStages(deployed, [accounts[0], accounts[1]]))
+        .then(deployed => { instance = deployed; });
+    });
+
+    it("should not deploy a ceremony whose beacon comes with the last transformation", () => {
+      return expectFailHandler(DistMpc.new("r1cs", 0, 3600, [], 0, noPowersOfTau, 0, 20), "The beacon block has to come after the stage.");
+    });
+
+    it("should record the delay and the iterations of the beacon", () => {
+      let p = Promise.all([instance.beaconDelay(), instance.beaconIterations()]);
+      return expectEqual(p.then(values => values.join()), "2,20", "The parameters of the beacon should be recorded.");
+    });
+
+    it("should not record a beacon before the stage ended", () => {
+      let p = mineBlocks(3).then(() => {
+        return instance.recordBeacon(0);
+      });
+      return expectFailHandler(p, "Stage 1 has not ended.");
+    });
+
+    it("should record the block of the last transformation of a stage", () => {
+      let p = instance.setInitialStage("stage1", "stage1_0", "contribution_0", {from: accounts[0]}).then(() => {
+        return instance.publishStageResults("stage1_1", "contribution_1", {from: accounts[1]});
+      }).then(result => {
+        stageEnd = result.receipt.blockNumber;
+        return instance.stageEndBlocks(0);
+      });
+      return p.then(block => assert.equal(block, stageEnd, "The end of stage 1 should be recorded."));
+    });
+
+    it("should not record a beacon whose block has not been mined", () => {
+      return expectFailHandler(instance.recordBeacon(0), "The beacon block is mined 2 blocks after the stage.");
+    });
+
+    it("should not start the next stage before the beacon is recorded", () => {
+      let p = instance.setInitialStage("stage2", "stage2_0", "contribution_0", {from: accounts[0]});
+      return expectFailHandler(p, "Stage 2 starts after the beacon of stage 1.");
+    });
+
+    it("should let any player record the hash of the beacon block", () => {
+      let p = mineBlocks(2).then(() => {
+        return instance.recordBeacon(0, {from: accounts[1]});
+      }).then(() => {
+        return Promise.all([instance.beaconBlocks(0), instance.beaconHashes(0)]);
+      });
+      return p.then(beacon => {
+        assert.equal(beacon[0], stageEnd + 2, "The beacon block should be recorded.");
+        assert.equal(beacon[1], web3.eth.getBlock(stageEnd + 2).hash, "The hash of the beacon block should be recorded.");
+      });
+    });
+
+    it("should not record the beacon of a stage twice", () => {
+      return expectFailHandler(instance.recordBeacon(0), "The beacon of stage 1 is already recorded.");
+    });
+
+    it("should start the next stage once the beacon is recorded", () => {
+      let p = instance.setInitialStage("stage2", "stage2_0", "contribution_0", {from: accounts[0]});
+      return expectSuccessHandler(p, "Stage 2 can start after the beacon of stage 1.");
+    });
+
+    it("should use a later block if the hash of the beacon block is no longer available", () => {
+      let p = instance.publishStageResults("stage2_1", "contribution_1", {from: accounts[1]}).then(result => {
+        stageEnd = result.receipt.blockNumber;
+        return mineBlocks(300);
+      }).then(() => {
+        return instance.recordBeacon(1);
+      }).then(result => {
+        //the beacon block stageEnd + 2 is 299 blocks old when it is recorded in block stageEnd + 301
+        assert.equal(result.receipt.blockNumber, stageEnd + 301, "No other block should have been mined.");
+        return Promise.all([instance.beaconBlocks(1), instance.beaconHashes(1)]);
+      });
+      return p.then(beacon => {
+        assert.equal(beacon[0], stageEnd + 2 + 256, "The beacon block should be moved by 256 blocks.");
+        assert.equal(beacon[1], web3.eth.getBlock(stageEnd + 2 + 256).hash, "The hash of the later block should be recorded.");
+      });
+    });
+  });
+
+  /***********************************************/
+  /************** Timeouts ***********************/
+  /***********************************************/
+
+  describe('Timeouts', () => {
+    let instance;
+
+    before(() => {
+      return DistMpc.new("r1cs", 0, 3600, [], 0, noPowersOfTau, 1, 0).then(deployed => {
+        instance = deployed;
+        return instance.join({from: accounts[1]});
+      });
+    });
+
+    it("should not expire a turn before the protocol started", () => {
+      return expectEqual(instance.isTurnExpired(), false, "There is no turn before the coordinator commits.");
+    });
+
+    it("should not expire the turn before the timeout in seconds", () => {
+      let p = instance.commit(commitment(0), {from: accounts[0]}).then(() => {
+        return instance.isTurnExpired();
+      });
+      return expectEqual(p, false, "The turn has just started.");
+    });
+
+    it("should name the players who are missing", () => {
+      let p = Promise.all([instance.isMissing(0), instance.isMissing(1)]);
+      return expectEqual(p.then(missing => missing.join()), "false,true", "Only player 1 has not committed.");
+    });
+
+    it("should not exclude players before the turn expired", () => {
+      return expectFailHandler(instance.excludeMissingPlayers({from: accounts[0]}), "The turn has not expired.");
+    });
+
+    it("should expire the turn after the timeout in seconds", () => {
+      let p = increaseTime(3600).then(() => {
+        return instance.isTurnExpired();
+      });
+      return expectEqual(p, true, "The turn should have expired.");
+    });
+
+    it("should exclude the players who missed the turn", () => {
+      let p = instance.excludeMissingPlayers({from: accounts[0]}).then(() => {
+        return instance.getExclusionState(1);
+      });
+      return expectEqual(p, 1, "Player 1 should have been excluded in the commitments.");
+    });
+
+    it("should continue without the excluded players", () => {
+      let p = instance.currentState().then(state => {
+        assert.equal(state, 2, "The commitments of the active players are complete.");
+        return Promise.all([instance.isMissing(0), instance.isMissing(1), instance.isTurnExpired()]);
+      });
+      return expectEqual(p.then(values => values.join()), "true,false,false", "The reveal starts with a new turn, excluded players are not missing.");
+    });
+
+    it("should not let an excluded player continue", () => {
+      return expectFailHandler(instance.revealCommitment("publicKey", {from: accounts[1]}), "Player 1 was excluded.");
+    });
+
+    it("should not exclude the coordinator", () => {
+      let p = increaseTime(3600).then(() => {
+        return instance.excludeMissingPlayers({from: accounts[0]});
+      });
+      return expectFailHandler(p, "The coordinator cannot be excluded.");
+    });
+
+    it("should expire the turn after the timeout in blocks", () => {
+      let p = DistMpc.new("r1cs", 2, 0, [], 0, noPowersOfTau, 1, 0).then(deployed => {
+        return deployed.commit(commitment(0), {from: accounts[0]}).then(() => deployed.isTurnExpired()).then(expired => {
+          assert.equal(expired, false, "The turn has just started.");
+          return mineBlocks(2);
+        }).then(() => deployed.isTurnExpired());
+      });
+      return expectEqual(p, true, "The turn should have expired after two blocks.");
+    });
+
+    it("should never expire a turn without timeouts", () => {
+      let p = DistMpc.new("r1cs", 0, 0, [], 0, noPowersOfTau, 1, 0).then(deployed => {
+        return deployed.commit(commitment(0), {from: accounts[0]})
+          .then(() => increaseTime(3600))
+          .then(() => mineBlocks(2))
+          .then(() => deployed.isTurnExpired());
+      });
+      return expectEqual(p, false, "A limit of 0 is disabled.");
+    });
+  });
+
+  /***********************************************/
+  /************** Turns **************************/
+  /***********************************************/
+
+  describe('Turns', () => {
+    let instance;
+
+    before(() => {
+      return DistMpc.new("r1cs", 0, 3600, [], 0, noPowersOfTau, 1, 0)
+        .then(deployed => startStages(deployed, [accounts[0], accounts[1], accounts[2]]))
+        .then(deployed => { instance = deployed; });
+    });
+
+    it("should start a stage with the turn of the coordinator", () => {
+      let p = instance.currentState().then(state => {
+        assert.equal(state, 4, "Should be in stage 1");
+        return instance.currentTurn();
+      });
+      return expectEqual(p, 0, "The coordinator initializes the stage.");
+    });
+
+    it("should not let a player publish before the stage is initialized", () => {
+      return expectFailHandler(instance.publishStageResults("stage1_1", "contribution_1", {from: accounts[1]}), "The coordinator has not initialized the stage.");
+    });
+
+    it("should pass the turn to the next player", () => {
+      let p = instance.setInitialStage("stage1", "stage1_0", "contribution_0", {from: accounts[0]}).then(() => {
+        return instance.currentTurn();
+      });
+      return expectEqual(p, 1, "Player 1 should be next.");
+    });
+
+    it("should not let a player skip the one before it", () => {
+      return expectFailHandler(instance.publishStageResults("stage1_2", "contribution_2", {from: accounts[2]}), "It is the turn of player 1.");
+    });
+
+    it("should only name the player whose turn it is as missing", () => {
+      let p = Promise.all([instance.isMissing(0), instance.isMissing(1), instance.isMissing(2)]);
+      return expectEqual(p.then(missing => missing.join()), "false,true,false", "Only player 1 has to publish now.");
+    });
+
+    it("should exclude the player whose turn expired", () => {
+      let p = increaseTime(3600).then(() => {
+        return instance.excludeMissingPlayers({from: accounts[2]});
+      }).then(() => {
+        return instance.getExclusionState(1);
+      });
+      return expectEqual(p, 4, "Player 1 should have been excluded in stage 1.");
+    });
+
+    it("should pass the turn of an excluded player to the next one", () => {
+      return expectEqual(instance.currentTurn(), 2, "Player 2 should be next.");
+    });
+
+    it("should not let the excluded player publish", () => {
+      return expectFailHandler(instance.publishStageResults("stage1_1", "contribution_1", {from: accounts[1]}), "Player 1 was excluded.");
+    });
+
+    it("should go to the next stage after the last active player", () => {
+      let p = instance.publishStageResults("stage1_2", "contribution_2", {from: accounts[2]}).then(() => {
+        return instance.currentState();
+      });
+      return expectEqual(p, 5, "Should be in stage 2");
+    });
+
+    it("should not exclude players in a stage before the turn expired", () => {
+      return expectFailHandler(instance.excludeMissingPlayers({from: accounts[2]}), "The turn of stage 2 has just started.");
+    });
+  });
 });
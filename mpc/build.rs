@@ -0,0 +1,308 @@
+//! Generates the typed bindings of the `DistributedMPC` contract from
+//! `abi.json`: a method of `DistributedMpc` for every function of the
+//! contract, a struct for every event and the encoding of the constructor.
+//! The bindings are included by `src/blockchain/contract.rs`, so a change of
+//! the ABI that the code does not follow fails to compile.
+//!
+//! Only the committed `abi.json` is read, so the build needs neither truffle
+//! nor node. The truffle tests check that `abi.json` is the ABI of the
+//! compiled contract, and the tests of the bindings check them against the
+//! ABI as parsed by ethabi and the selectors in the committed bytecode.
+
+extern crate serde_json;
+
+use serde_json::Value;
+
+use std::env;
+use std::fmt::Write as FmtWrite;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::Path;
+
+const ABI: &str = "abi.json";
+
+/// Methods of `DistributedMpc` that are not generated.
+const RESERVED: &[&str] = &["new", "query", "call", "try_call", "record_stage_beacon", "account", "signer", "submitter", "address"];
+
+const KEYWORDS: &[&str] = &[
+    "as", "box", "break", "const", "continue", "crate", "else", "enum", "extern", "false", "fn", "for", "if", "impl", "in",
+    "let", "loop", "match", "mod", "move", "mut", "pub", "ref", "return", "self", "static", "struct", "super", "trait",
+    "true", "type", "unsafe", "use", "where", "while"
+];
+
+/// A parameter of a function or event.
+struct Param {
+    /// The name in the ABI, which can be empty.
+    abi_name: String,
+    /// The name in Rust.
+    name: String,
+    kind: String
+}
+
+impl Param {
+    /// The Rust type of a value of the parameter.
+    fn rust_type(&self) -> &'static str {
+        match &self.kind[..] {
+            "address" => "Address",
+            "address[]" => "Vec<Address>",
+            "bool" => "bool",
+            "bytes" => "Vec<u8>",
+            "bytes32" => "[u8; 32]",
+            "string" => "String",
+            kind if kind.starts_with("uint") => "U256",
+            kind => panic!("The type {:?} is not supported by the bindings", kind)
+        }
+    }
+
+    /// The `ParamType` ethabi parses the type to, as printed by `Debug`.
+    fn ethabi_kind(&self) -> String {
+        match &self.kind[..] {
+            "address" => "Address".into(),
+            "address[]" => "Array(Address)".into(),
+            "bool" => "Bool".into(),
+            "bytes" => "Bytes".into(),
+            "bytes32" => "FixedBytes(32)".into(),
+            "string" => "String".into(),
+            "uint" => "Uint(256)".into(),
+            kind if kind.starts_with("uint") => format!("Uint({})", &kind[4..]),
+            kind => panic!("The type {:?} is not supported by the bindings", kind)
+        }
+    }
+
+    /// The canonical type, which the selector of a function is hashed from.
+    fn canonical_kind(&self) -> String {
+        match &self.kind[..] {
+            "uint" => "uint256".into(),
+            kind => kind.into()
+        }
+    }
+
+    /// The token of the argument with the name.
+    fn to_token(&self) -> String {
+        match &self.kind[..] {
+            "address" => format!("Token::Address({})", self.name),
+            "address[]" => format!("Token::Array({}.into_iter().map(Token::Address).collect())", self.name),
+            "bool" => format!("Token::Bool({})", self.name),
+            "bytes" => format!("Token::Bytes({})", self.name),
+            "bytes32" => format!("Token::FixedBytes({}.to_vec())", self.name),
+            "string" => format!("Token::String({})", self.name),
+            kind if kind.starts_with("uint") => format!("Token::Uint({})", self.name),
+            kind => panic!("The type {:?} is not supported by the bindings", kind)
+        }
+    }
+
+    /// The helper of `contract.rs` that converts a token to the Rust type.
+    fn from_token(&self) -> &'static str {
+        match &self.kind[..] {
+            "address" => "token_address",
+            "bool" => "token_bool",
+            "bytes" => "token_bytes",
+            "bytes32" => "token_bytes32",
+            "string" => "token_string",
+            kind if kind.starts_with("uint") => "token_uint",
+            kind => panic!("The type {:?} is not supported by the bindings", kind)
+        }
+    }
+}
+
+/// Converts a name in camel case to snake case, e.g. `getNumberOfPlayers` to
+/// `get_number_of_players`.
+fn snake_case(name: &str) -> String {
+    let chars: Vec<char> = name.chars().collect();
+    let mut snake = String::new();
+    for (i, c) in chars.iter().enumerate() {
+        if c.is_uppercase() {
+            let after_lower = i > 0 && (chars[i - 1].is_lowercase() || chars[i - 1].is_numeric());
+            let ends_acronym = i > 0 && chars[i - 1].is_uppercase() && chars.get(i + 1).map_or(false, |next| next.is_lowercase());
+            if after_lower || ends_acronym {
+                snake.push('_');
+            }
+            snake.extend(c.to_lowercase());
+        } else {
+            snake.push(*c);
+        }
+    }
+    if KEYWORDS.contains(&&snake[..]) {
+        snake.push('_');
+    }
+    snake
+}
+
+fn params(entry: &Value, field: &str) -> Vec<Param> {
+    entry[field].as_array().map_or(vec![], |params| params.iter().enumerate().map(|(i, param)| Param {
+        abi_name: param["name"].as_str().unwrap_or("").into(),
+        name: match param["name"].as_str() {
+            Some(name) if !name.is_empty() => snake_case(name),
+            _ => format!("arg{}", i)
+        },
+        kind: param["type"].as_str().expect("Parameter should have a type!").into()
+    }).collect())
+}
+
+fn is_constant(entry: &Value) -> bool {
+    entry["constant"].as_bool().unwrap_or(false)
+        || entry["stateMutability"].as_str().map_or(false, |mutability| mutability == "view" || mutability == "pure")
+}
+
+fn read_json(path: &str) -> Value {
+    let mut content = String::new();
+    File::open(path).and_then(|mut file| file.read_to_string(&mut content)).unwrap_or_else(|e| panic!("Error reading {}: {}", path, e));
+    serde_json::from_str(&content).unwrap_or_else(|e| panic!("Error parsing {}: {}", path, e))
+}
+
+fn generics(params: &[Param]) -> String {
+    let generics: Vec<String> = params.iter().enumerate()
+        .filter(|&(_, param)| param.rust_type() == "U256")
+        .map(|(i, _)| format!("P{}: Into<U256>", i))
+        .collect();
+    if generics.is_empty() { String::new() } else { format!("<{}>", generics.join(", ")) }
+}
+
+fn arguments(params: &[Param]) -> String {
+    params.iter().enumerate().map(|(i, param)| if param.rust_type() == "U256" {
+        format!(", {}: P{}", param.name, i)
+    } else {
+        format!(", {}: {}", param.name, param.rust_type())
+    }).collect()
+}
+
+/// Converts the integer arguments to `U256`.
+fn conversions(out: &mut String, params: &[Param], indent: &str) {
+    for param in params.iter().filter(|param| param.rust_type() == "U256") {
+        writeln!(out, "{}let {}: U256 = {}.into();", indent, param.name, param.name).unwrap();
+    }
+}
+
+/// The arguments as a value that implements `Tokenize`.
+fn tokens(params: &[Param]) -> String {
+    match params.len() {
+        1 => params[0].name.clone(),
+        _ => format!("({})", params.iter().map(|param| param.name.clone()).collect::<Vec<_>>().join(", "))
+    }
+}
+
+fn declaration(entry: &Value, params: &[Param]) -> String {
+    let params: Vec<String> = params.iter().map(|param| if param.abi_name.is_empty() { param.kind.clone() } else { format!("{} {}", param.kind, param.abi_name) }).collect();
+    format!("{}({})", entry["name"].as_str().unwrap_or(""), params.join(", "))
+}
+
+fn function(out: &mut String, entry: &Value) {
+    let name = entry["name"].as_str().expect("Function should have a name!");
+    let method = snake_case(name);
+    if RESERVED.contains(&&method[..]) {
+        panic!("The function {:?} of the contract collides with the method {:?} of DistributedMpc", name, method);
+    }
+    let inputs = params(entry, "inputs");
+    let outputs = params(entry, "outputs");
+    let result = if is_constant(entry) {
+        match outputs.len() {
+            0 => panic!("The constant function {:?} has no outputs", name),
+            1 => outputs[0].rust_type().to_string(),
+            _ => format!("({})", outputs.iter().map(|output| output.rust_type()).collect::<Vec<_>>().join(", "))
+        }
+    } else {
+        "CallReceipt".to_string()
+    };
+    if is_constant(entry) {
+        writeln!(out, "    /// Queries `{}`.", declaration(entry, &inputs)).unwrap();
+    } else {
+        writeln!(out, "    /// Calls `{}` in a transaction and waits until it is confirmed.", declaration(entry, &inputs)).unwrap();
+    }
+    writeln!(out, "    pub fn {}{}(&self{}) -> {} {{", method, generics(&inputs), arguments(&inputs), result).unwrap();
+    conversions(out, &inputs, "        ");
+    writeln!(out, "        self.{}({:?}, {})", if is_constant(entry) { "query" } else { "call" }, name, tokens(&inputs)).unwrap();
+    writeln!(out, "    }}").unwrap();
+}
+
+fn event(out: &mut String, entry: &Value) {
+    let name = entry["name"].as_str().expect("Event should have a name!");
+    let fields = params(entry, "inputs");
+    writeln!(out, "/// The event `{}`.", declaration(entry, &fields)).unwrap();
+    writeln!(out, "#[allow(dead_code)]").unwrap();
+    writeln!(out, "#[derive(Clone, Debug, PartialEq)]").unwrap();
+    writeln!(out, "pub struct {} {{", name).unwrap();
+    let declarations: Vec<String> = fields.iter().map(|field| format!("    pub {}: {}", field.name, field.rust_type())).collect();
+    writeln!(out, "{}", declarations.join(",\n")).unwrap();
+    writeln!(out, "}}\n").unwrap();
+
+    let values: Vec<String> = fields.iter().map(|field| format!("{}(tokens.next())", field.from_token())).collect();
+    let patterns: Vec<String> = fields.iter().map(|field| format!("Some({})", field.name)).collect();
+    let assignments: Vec<String> = fields.iter().map(|field| format!("{}: {}", field.name, field.name)).collect();
+    let tuple = |items: &[String]| if items.len() == 1 { format!("({},)", items[0]) } else { format!("({})", items.join(", ")) };
+    writeln!(out, "impl ContractEvent for {} {{", name).unwrap();
+    writeln!(out, "    fn name() -> &'static str {{\n        {:?}\n    }}\n", name).unwrap();
+    writeln!(out, "    fn from_tokens(tokens: Vec<Token>) -> Option<Self> {{").unwrap();
+    writeln!(out, "        let mut tokens = tokens.into_iter();").unwrap();
+    writeln!(out, "        match {} {{", tuple(&values)).unwrap();
+    writeln!(out, "            {} => Some({} {{ {} }}),", tuple(&patterns), name, assignments.join(", ")).unwrap();
+    writeln!(out, "            _ => None\n        }}\n    }}\n}}\n").unwrap();
+}
+
+fn constructor(out: &mut String, entry: &Value) {
+    let inputs = params(entry, "inputs");
+    writeln!(out, "/// The data of a transaction that deploys the contract with the code and").unwrap();
+    writeln!(out, "/// the arguments of `constructor{}`.", declaration(entry, &inputs)).unwrap();
+    writeln!(out, "pub fn deployment_data{}(code: Vec<u8>{}) -> Vec<u8> {{", generics(&inputs), arguments(&inputs)).unwrap();
+    conversions(out, &inputs, "    ");
+    writeln!(out, "    let abi = contract_abi();").unwrap();
+    writeln!(out, "    let constructor = abi.constructor.as_ref().expect(\"Contract should have a constructor!\");").unwrap();
+    let tokens: Vec<String> = inputs.iter().map(|input| input.to_token()).collect();
+    writeln!(out, "    constructor.encode_input(code, &[{}]).expect(\"Parameters should match the abi!\")", tokens.join(", ")).unwrap();
+    writeln!(out, "}}\n").unwrap();
+}
+
+/// The names of the functions or events with the `ParamType`s of their inputs.
+fn bound(out: &mut String, name: &str, entries: &[&Value]) {
+    writeln!(out, "#[cfg(test)]").unwrap();
+    writeln!(out, "const {}: &'static [(&'static str, &'static [&'static str])] = &[", name).unwrap();
+    let lines: Vec<String> = entries.iter().map(|entry| {
+        let kinds: Vec<String> = params(entry, "inputs").iter().map(|param| format!("{:?}", param.ethabi_kind())).collect();
+        format!("    ({:?}, &[{}])", entry["name"].as_str().unwrap_or(""), kinds.join(", "))
+    }).collect();
+    writeln!(out, "{}\n];\n", lines.join(",\n")).unwrap();
+}
+
+/// The canonical signatures of the functions, e.g. `getNizks(uint256)`,
+/// which the tests hash to the selectors in the bytecode.
+fn bound_signatures(out: &mut String, functions: &[&Value]) {
+    writeln!(out, "#[cfg(test)]").unwrap();
+    writeln!(out, "const BOUND_SIGNATURES: &'static [&'static str] = &[").unwrap();
+    let lines: Vec<String> = functions.iter().map(|entry| {
+        let kinds: Vec<String> = params(entry, "inputs").iter().map(Param::canonical_kind).collect();
+        format!("    {:?}", format!("{}({})", entry["name"].as_str().unwrap_or(""), kinds.join(",")))
+    }).collect();
+    writeln!(out, "{}\n];\n", lines.join(",\n")).unwrap();
+}
+
+fn main() {
+    println!("cargo:rerun-if-changed={}", ABI);
+
+    let abi = read_json(ABI);
+    let entries = abi.as_array().expect("The ABI should be an array!");
+    let of_type = |kind: &str| entries.iter().filter(|entry| entry["type"].as_str() == Some(kind)).collect::<Vec<_>>();
+
+    let mut out = format!("// Generated by build.rs from {}, do not edit.\n\n", ABI);
+    // Not every function is called by every binary.
+    out.push_str("#[allow(dead_code)]\nimpl<T: Transport> DistributedMpc<T> {\n");
+    let functions = of_type("function");
+    for (i, entry) in functions.iter().enumerate() {
+        if i > 0 {
+            out.push('\n');
+        }
+        function(&mut out, entry);
+    }
+    out.push_str("}\n\n");
+    for entry in &of_type("event") {
+        event(&mut out, entry);
+    }
+    match of_type("constructor").first() {
+        Some(entry) => constructor(&mut out, entry),
+        None => panic!("The ABI has no constructor")
+    }
+    bound(&mut out, "BOUND_FUNCTIONS", &functions);
+    bound(&mut out, "BOUND_EVENTS", &of_type("event"));
+    bound_signatures(&mut out, &functions);
+
+    let path = Path::new(&env::var("OUT_DIR").expect("Cargo should set OUT_DIR!")).join("distributed_mpc.rs");
+    File::create(&path).and_then(|mut file| file.write_all(out.as_bytes())).unwrap_or_else(|e| panic!("Error writing {:?}: {}", path, e));
+}
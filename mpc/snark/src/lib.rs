@@ -4,16 +4,73 @@ extern crate libc;
 extern crate lazy_static;
 
 use std::sync::Mutex;
+use std::ffi::CString;
+use std::fs::File;
+use std::io;
 
 use bn::*;
 
 extern "C" {
     fn libsnarkwrap_init();
     fn libsnarkwrap_getcs_dummy(d: *mut libc::uint64_t, vars: *mut libc::uint64_t, inputs: *mut libc::uint64_t, omega: *mut Fr) -> *mut libc::c_void;
-    fn libsnarkwrap_getcs_file(d: *mut libc::uint64_t, vars: *mut libc::uint64_t, inputs: *mut libc::uint64_t, omega: *mut Fr) -> *mut libc::c_void;
+    fn libsnarkwrap_getcs_file(path: *const libc::c_char, d: *mut libc::uint64_t, vars: *mut libc::uint64_t, inputs: *mut libc::uint64_t, omega: *mut Fr) -> *mut libc::c_void;
+    fn libsnarkwrap_writecs(
+        path: *const libc::c_char,
+        num_inputs: libc::uint64_t,
+        num_aux: libc::uint64_t,
+        num_constraints: libc::uint64_t,
+        num_terms: libc::uint64_t,
+        matrices: *const libc::uint8_t,
+        constraints: *const libc::uint64_t,
+        variables: *const libc::uint64_t,
+        coeffs: *const Fr
+    );
     fn libsnarkwrap_dropcs(cs: *mut libc::c_void);
     fn libsnarkwrap_dropkeypair(kp: *mut libc::c_void);
-    fn libsnarkwrap_keypair_write(kp: *const libc::c_void);
+    fn libsnarkwrap_keypair_write(kp: *const libc::c_void, pk_path: *const libc::c_char, vk_path: *const libc::c_char);
+    fn libsnarkwrap_keypair_pk(kp: *const libc::c_void) -> *const libc::c_void;
+    fn libsnarkwrap_keypair_vk(kp: *const libc::c_void) -> *const libc::c_void;
+    fn libsnarkwrap_pk_read(path: *const libc::c_char) -> *mut libc::c_void;
+    fn libsnarkwrap_vk_read(path: *const libc::c_char) -> *mut libc::c_void;
+    fn libsnarkwrap_droppk(pk: *mut libc::c_void);
+    fn libsnarkwrap_dropvk(vk: *mut libc::c_void);
+    fn libsnarkwrap_is_satisfied(
+        cs: *const libc::c_void,
+        primary: *const Fr,
+        primary_size: libc::uint64_t,
+        aux: *const Fr,
+        aux_size: libc::uint64_t
+    ) -> bool;
+    fn libsnarkwrap_prove(
+        cs: *const libc::c_void,
+        pk: *const libc::c_void,
+        primary: *const Fr,
+        primary_size: libc::uint64_t,
+        aux: *const Fr,
+        aux_size: libc::uint64_t
+    ) -> *mut libc::c_void;
+    fn libsnarkwrap_verify(
+        vk: *const libc::c_void,
+        primary: *const Fr,
+        primary_size: libc::uint64_t,
+        proof: *const libc::c_void
+    ) -> bool;
+    fn libsnarkwrap_proof_read(path: *const libc::c_char) -> *mut libc::c_void;
+    fn libsnarkwrap_proof_write(proof: *const libc::c_void, path: *const libc::c_char);
+    fn libsnarkwrap_proof_points(
+        proof: *const libc::c_void,
+        a: *mut G1,
+        a_p: *mut G1,
+        b: *mut G2,
+        b_p: *mut G1,
+        c: *mut G1,
+        c_p: *mut G1,
+        h: *mut G1,
+        k: *mut G1
+    );
+    fn libsnarkwrap_dropproof(proof: *mut libc::c_void);
+    fn libsnarkwrap_keypair_write_zokrates(kp: *const libc::c_void, cs: *const libc::c_void, path: *const libc::c_char);
+    fn libsnarkwrap_test_zokrates_pk(kp: *const libc::c_void, cs: *const libc::c_void, path: *const libc::c_char) -> libc::c_int;
     fn libsnarkwrap_eval(
         cs: *const libc::c_void,
         lc1: *const G1,
@@ -109,6 +166,65 @@ pub struct Keypair {
     ptr: *mut libc::c_void
 }
 
+pub struct ProvingKey {
+    ptr: *mut libc::c_void
+}
+
+pub struct VerificationKey {
+    ptr: *mut libc::c_void
+}
+
+pub struct Proof {
+    ptr: *mut libc::c_void
+}
+
+fn c_path(path: &str) -> CString {
+    CString::new(path).expect("Paths must not contain null bytes")
+}
+
+/// Reads an object with one of the readers of libsnarkwrap, which return a
+/// null pointer if the file does not contain a valid object.
+fn read_file(
+    path: &str,
+    reader: unsafe extern "C" fn(*const libc::c_char) -> *mut libc::c_void
+) -> io::Result<*mut libc::c_void>
+{
+    initialize();
+
+    try!(File::open(path));
+    let c_path = c_path(path);
+    let ptr = unsafe { reader(c_path.as_ptr()) };
+    if ptr.is_null() {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "not in the format of libsnark"));
+    }
+
+    Ok(ptr)
+}
+
+fn prove(cs: &CS, pk: *const libc::c_void, primary: &[Fr], aux: &[Fr]) -> Proof {
+    assert_eq!(primary.len(), cs.num_inputs);
+    assert_eq!(primary.len() + aux.len() + 1, cs.num_vars);
+
+    Proof {
+        ptr: unsafe {
+            libsnarkwrap_prove(
+                cs.ptr,
+                pk,
+                primary.as_ptr(),
+                primary.len() as u64,
+                aux.as_ptr(),
+                aux.len() as u64
+            )
+        }
+    }
+}
+
+fn verify(vk: *const libc::c_void, primary: &[Fr], proof: &Proof) -> bool {
+    unsafe {
+        libsnarkwrap_verify(vk, primary.as_ptr(), primary.len() as u64, proof.ptr)
+    }
+}
+
 impl PartialEq for Keypair {
     fn eq(&self, other: &Keypair) -> bool {
         initialize();
@@ -121,13 +237,57 @@ impl PartialEq for Keypair {
 
 impl Keypair {
     pub fn write_to_disk(&self) {
+        self.write_to("pk", "vk");
+    }
+
+    pub fn write_to(&self, pk_path: &str, vk_path: &str) {
         initialize();
 
+        let pk_path = c_path(pk_path);
+        let vk_path = c_path(vk_path);
         unsafe {
-            libsnarkwrap_keypair_write(self.ptr);
+            libsnarkwrap_keypair_write(self.ptr, pk_path.as_ptr(), vk_path.as_ptr());
         }
     }
 
+    /// Writes the proving key in the format of ZoKrates, which includes
+    /// the constraint system the keypair was created for.
+    pub fn write_zokrates_proving_key(&self, cs: &CS, path: &str) {
+        initialize();
+
+        let path = c_path(path);
+        unsafe {
+            libsnarkwrap_keypair_write_zokrates(self.ptr, cs.ptr, path.as_ptr());
+        }
+    }
+
+    /// Checks whether the proving key written in the format of ZoKrates to
+    /// `path` is the one of the keypair for the constraint system.
+    pub fn test_zokrates_proving_key(&self, cs: &CS, path: &str) -> io::Result<bool> {
+        initialize();
+
+        try!(File::open(path));
+        let c_path = c_path(path);
+        match unsafe { libsnarkwrap_test_zokrates_pk(self.ptr, cs.ptr, c_path.as_ptr()) } {
+            -1 => Err(io::Error::new(io::ErrorKind::InvalidData, "not in the format of ZoKrates")),
+            result => Ok(result == 1)
+        }
+    }
+
+    /// Proves that the primary (public) and auxiliary inputs satisfy the
+    /// constraint system the keypair was created for.
+    pub fn prove(&self, cs: &CS, primary: &[Fr], aux: &[Fr]) -> Proof {
+        initialize();
+
+        prove(cs, unsafe { libsnarkwrap_keypair_pk(self.ptr) }, primary, aux)
+    }
+
+    pub fn verify(&self, primary: &[Fr], proof: &Proof) -> bool {
+        initialize();
+
+        verify(unsafe { libsnarkwrap_keypair_vk(self.ptr) }, primary, proof)
+    }
+
     pub fn from(
         cs: &CS,
         pk_a: &[G1],
@@ -205,8 +365,135 @@ impl Keypair {
     }
 }
 
+impl ProvingKey {
+    pub fn read(path: &str) -> io::Result<ProvingKey> {
+        Ok(ProvingKey {
+            ptr: try!(read_file(path, libsnarkwrap_pk_read))
+        })
+    }
+
+    pub fn prove(&self, cs: &CS, primary: &[Fr], aux: &[Fr]) -> Proof {
+        initialize();
+
+        prove(cs, self.ptr, primary, aux)
+    }
+}
+
+impl VerificationKey {
+    pub fn read(path: &str) -> io::Result<VerificationKey> {
+        Ok(VerificationKey {
+            ptr: try!(read_file(path, libsnarkwrap_vk_read))
+        })
+    }
+
+    /// Verifies the proof for the primary inputs, which must match the
+    /// number of inputs of the constraint system.
+    pub fn verify(&self, primary: &[Fr], proof: &Proof) -> bool {
+        initialize();
+
+        verify(self.ptr, primary, proof)
+    }
+}
+
+impl Proof {
+    pub fn read(path: &str) -> io::Result<Proof> {
+        Ok(Proof {
+            ptr: try!(read_file(path, libsnarkwrap_proof_read))
+        })
+    }
+
+    pub fn write(&self, path: &str) {
+        initialize();
+
+        let path = c_path(path);
+        unsafe { libsnarkwrap_proof_write(self.ptr, path.as_ptr()) }
+    }
+
+    /// The points of the proof as (A, A', B, B', C, C', H, K).
+    pub fn points(&self) -> (G1, G1, G2, G1, G1, G1, G1, G1) {
+        initialize();
+
+        let (mut a, mut a_p, mut b, mut b_p) = (G1::zero(), G1::zero(), G2::zero(), G1::zero());
+        let (mut c, mut c_p, mut h, mut k) = (G1::zero(), G1::zero(), G1::zero(), G1::zero());
+        unsafe {
+            libsnarkwrap_proof_points(self.ptr, &mut a, &mut a_p, &mut b, &mut b_p, &mut c, &mut c_p, &mut h, &mut k);
+        }
+
+        (a, a_p, b, b_p, c, c_p, h, k)
+    }
+}
+
 impl CS {
+    /// Writes a constraint system in libsnark's format, so that it can be
+    /// loaded with `from_path`. The linear combinations of each constraint
+    /// are given as (variable, coefficient) terms, where variable 0 is the
+    /// constant one, followed by the primary and the auxiliary inputs.
+    pub fn write(
+        path: &str,
+        num_inputs: usize,
+        num_aux: usize,
+        a: &[Vec<(usize, Fr)>],
+        b: &[Vec<(usize, Fr)>],
+        c: &[Vec<(usize, Fr)>]
+    )
+    {
+        initialize();
+
+        assert_eq!(a.len(), b.len());
+        assert_eq!(b.len(), c.len());
+
+        let mut matrices = vec![];
+        let mut constraints = vec![];
+        let mut variables = vec![];
+        let mut coeffs = vec![];
+        for (matrix, lcs) in [a, b, c].iter().enumerate() {
+            for (constraint, lc) in lcs.iter().enumerate() {
+                for &(var, coeff) in lc {
+                    assert!(var <= num_inputs + num_aux);
+
+                    matrices.push(matrix as u8);
+                    constraints.push(constraint as u64);
+                    variables.push(var as u64);
+                    coeffs.push(coeff);
+                }
+            }
+        }
+
+        let path = c_path(path);
+        unsafe {
+            libsnarkwrap_writecs(
+                path.as_ptr(),
+                num_inputs as u64,
+                num_aux as u64,
+                a.len() as u64,
+                matrices.len() as u64,
+                matrices.as_ptr(),
+                constraints.as_ptr(),
+                variables.as_ptr(),
+                coeffs.as_ptr()
+            );
+        }
+    }
+
+    pub fn is_satisfied(&self, primary: &[Fr], aux: &[Fr]) -> bool {
+        initialize();
+
+        unsafe {
+            libsnarkwrap_is_satisfied(
+                self.ptr,
+                primary.as_ptr(),
+                primary.len() as u64,
+                aux.as_ptr(),
+                aux.len() as u64
+            )
+        }
+    }
+
     pub fn from_file() -> Self {
+        CS::from_path("r1cs")
+    }
+
+    pub fn from_path(path: &str) -> Self {
         initialize();
 
         let mut d = 0;
@@ -214,7 +501,8 @@ impl CS {
         let mut num_inputs = 0;
         let mut o = Fr::zero();
 
-        let cs = unsafe { libsnarkwrap_getcs_file(&mut d, &mut vars, &mut num_inputs, &mut o) };
+        let path = c_path(path);
+        let cs = unsafe { libsnarkwrap_getcs_file(path.as_ptr(), &mut d, &mut vars, &mut num_inputs, &mut o) };
 
         CS {
             ptr: cs,
@@ -315,3 +603,27 @@ impl Drop for Keypair {
         unsafe { libsnarkwrap_dropkeypair(self.ptr) }
     }
 }
+
+impl Drop for ProvingKey {
+    fn drop(&mut self) {
+        initialize();
+
+        unsafe { libsnarkwrap_droppk(self.ptr) }
+    }
+}
+
+impl Drop for VerificationKey {
+    fn drop(&mut self) {
+        initialize();
+
+        unsafe { libsnarkwrap_dropvk(self.ptr) }
+    }
+}
+
+impl Drop for Proof {
+    fn drop(&mut self) {
+        initialize();
+
+        unsafe { libsnarkwrap_dropproof(self.ptr) }
+    }
+}
@@ -65,6 +65,28 @@ void loadFromFile(std::string path, T& objIn) {
     objIn = std::move(obj);
 }
 
+// Like loadFromFile, but returns a null pointer instead of aborting if the
+// file cannot be opened or does not contain a valid object.
+template<typename T>
+T* tryLoadFromFile(std::string path) {
+    std::stringstream ss;
+    std::ifstream fh(path, std::ios::binary);
+    if (!fh.is_open()) {
+        return nullptr;
+    }
+    ss << fh.rdbuf();
+    fh.close();
+    ss.rdbuf()->pubseekpos(0, std::ios_base::in);
+    auto obj = new T();
+    ss >> *obj;
+    if (ss.fail()) {
+        delete obj;
+        return nullptr;
+    }
+
+    return obj;
+}
+
 void* libsnark_cs_return(
     uint64_t *d, uint64_t *vars, uint64_t *num_inputs, curve_Fr *omega,
     r1cs_constraint_system<curve_Fr> cs
@@ -95,11 +117,11 @@ void* libsnark_cs_return(
     return new r1cs_constraint_system<curve_Fr>(cs);
 }
 
-extern "C" void* libsnarkwrap_getcs_file(uint64_t *d, uint64_t *vars, uint64_t *num_inputs, curve_Fr *omega)
+extern "C" void* libsnarkwrap_getcs_file(const char *path, uint64_t *d, uint64_t *vars, uint64_t *num_inputs, curve_Fr *omega)
 {
     r1cs_constraint_system<curve_Fr> cs;
 
-    loadFromFile("r1cs", cs);
+    loadFromFile(path, cs);
 
     return libsnark_cs_return(
         d,
@@ -124,6 +146,42 @@ extern "C" void* libsnarkwrap_getcs_dummy(uint64_t *d, uint64_t *vars, uint64_t
     );
 }
 
+// Writes a constraint system given by the terms of its linear combinations.
+// The matrix of a term is 0 for A, 1 for B and 2 for C.
+extern "C" void libsnarkwrap_writecs(
+    const char *path,
+    uint64_t num_inputs,
+    uint64_t num_aux,
+    uint64_t num_constraints,
+    uint64_t num_terms,
+    const uint8_t *matrices,
+    const uint64_t *constraints,
+    const uint64_t *variables,
+    const curve_Fr *coeffs
+)
+{
+    r1cs_constraint_system<curve_Fr> cs;
+    cs.primary_input_size = num_inputs;
+    cs.auxiliary_input_size = num_aux;
+    cs.constraints.resize(num_constraints);
+
+    for (uint64_t i = 0; i < num_terms; i++) {
+        assert(constraints[i] < num_constraints);
+        assert(variables[i] <= num_inputs + num_aux);
+
+        auto &constraint = cs.constraints[constraints[i]];
+        variable<curve_Fr> var(variables[i]);
+        switch (matrices[i]) {
+            case 0: constraint.a.add_term(var, coeffs[i]); break;
+            case 1: constraint.b.add_term(var, coeffs[i]); break;
+            case 2: constraint.c.add_term(var, coeffs[i]); break;
+            default: assert(false);
+        }
+    }
+
+    saveToFile(path, cs);
+}
+
 extern "C" void libsnarkwrap_dropcs(r1cs_constraint_system<curve_Fr> *cs)
 {
     delete cs;
@@ -278,11 +336,13 @@ extern "C" void* libsnarkwrap_test_keygen(
 }
 
 extern "C" void libsnarkwrap_keypair_write(
-    const r1cs_ppzksnark_keypair<curve_pp> *kp
+    const r1cs_ppzksnark_keypair<curve_pp> *kp,
+    const char *pk_path,
+    const char *vk_path
 )
 {
-    saveToFile("pk", kp->pk);
-    saveToFile("vk", kp->vk);
+    saveToFile(pk_path, kp->pk);
+    saveToFile(vk_path, kp->vk);
 }
 
 extern "C" bool libsnarkwrap_keypair_eq(
@@ -361,3 +421,181 @@ extern "C" bool libsnarkwrap_test_eval(
 
     return res;
 }
+
+// Proving and verifying
+
+r1cs_primary_input<curve_Fr> input_vector(const curve_Fr *values, uint64_t size)
+{
+    r1cs_primary_input<curve_Fr> v;
+    for (uint64_t i = 0; i < size; i++) {
+        v.push_back(values[i]);
+    }
+
+    return v;
+}
+
+extern "C" const void* libsnarkwrap_keypair_pk(const r1cs_ppzksnark_keypair<curve_pp> *kp)
+{
+    return &kp->pk;
+}
+
+extern "C" const void* libsnarkwrap_keypair_vk(const r1cs_ppzksnark_keypair<curve_pp> *kp)
+{
+    return &kp->vk;
+}
+
+extern "C" void* libsnarkwrap_pk_read(const char *path)
+{
+    return tryLoadFromFile<r1cs_ppzksnark_proving_key<curve_pp>>(path);
+}
+
+extern "C" void* libsnarkwrap_vk_read(const char *path)
+{
+    return tryLoadFromFile<r1cs_ppzksnark_verification_key<curve_pp>>(path);
+}
+
+extern "C" void libsnarkwrap_droppk(r1cs_ppzksnark_proving_key<curve_pp> *pk)
+{
+    delete pk;
+}
+
+extern "C" void libsnarkwrap_dropvk(r1cs_ppzksnark_verification_key<curve_pp> *vk)
+{
+    delete vk;
+}
+
+extern "C" bool libsnarkwrap_is_satisfied(
+    const r1cs_constraint_system<curve_Fr> *cs,
+    const curve_Fr *primary,
+    uint64_t primary_size,
+    const curve_Fr *aux,
+    uint64_t aux_size
+)
+{
+    return cs->is_satisfied(
+        input_vector(primary, primary_size),
+        input_vector(aux, aux_size)
+    );
+}
+
+extern "C" void* libsnarkwrap_prove(
+    const r1cs_constraint_system<curve_Fr> *cs,
+    const r1cs_ppzksnark_proving_key<curve_pp> *pk,
+    const curve_Fr *primary,
+    uint64_t primary_size,
+    const curve_Fr *aux,
+    uint64_t aux_size
+)
+{
+    return new r1cs_ppzksnark_proof<curve_pp>(
+        r1cs_ppzksnark_prover<curve_pp>(
+            *pk,
+            input_vector(primary, primary_size),
+            input_vector(aux, aux_size),
+            *cs
+        )
+    );
+}
+
+extern "C" bool libsnarkwrap_verify(
+    const r1cs_ppzksnark_verification_key<curve_pp> *vk,
+    const curve_Fr *primary,
+    uint64_t primary_size,
+    const r1cs_ppzksnark_proof<curve_pp> *proof
+)
+{
+    return r1cs_ppzksnark_verifier_strong_IC<curve_pp>(
+        *vk,
+        input_vector(primary, primary_size),
+        *proof
+    );
+}
+
+extern "C" void* libsnarkwrap_proof_read(const char *path)
+{
+    return tryLoadFromFile<r1cs_ppzksnark_proof<curve_pp>>(path);
+}
+
+extern "C" void libsnarkwrap_proof_write(
+    const r1cs_ppzksnark_proof<curve_pp> *proof,
+    const char *path
+)
+{
+    saveToFile(path, *proof);
+}
+
+extern "C" void libsnarkwrap_proof_points(
+    const r1cs_ppzksnark_proof<curve_pp> *proof,
+    curve_G1 *a,
+    curve_G1 *a_p,
+    curve_G2 *b,
+    curve_G1 *b_p,
+    curve_G1 *c,
+    curve_G1 *c_p,
+    curve_G1 *h,
+    curve_G1 *k
+)
+{
+    *a = proof->g_A.g;
+    *a_p = proof->g_A.h;
+    *b = proof->g_B.g;
+    *b_p = proof->g_B.h;
+    *c = proof->g_C.g;
+    *c_p = proof->g_C.h;
+    *h = proof->g_H;
+    *k = proof->g_K;
+}
+
+extern "C" void libsnarkwrap_dropproof(r1cs_ppzksnark_proof<curve_pp> *proof)
+{
+    delete proof;
+}
+
+// ZoKrates
+
+// Newer versions of libsnark, which ZoKrates uses, store the constraint
+// system in the proving key and serialize it after the queries.
+extern "C" void libsnarkwrap_keypair_write_zokrates(
+    const r1cs_ppzksnark_keypair<curve_pp> *kp,
+    const r1cs_constraint_system<curve_Fr> *cs,
+    const char *path
+)
+{
+    std::stringstream ss;
+    ss << kp->pk;
+    ss << *cs;
+    std::ofstream fh;
+    fh.open(path, std::ios::binary);
+    ss.rdbuf()->pubseekpos(0, std::ios_base::out);
+    fh << ss.rdbuf();
+    fh.flush();
+    fh.close();
+}
+
+// 1 if the file holds the proving key of the keypair and the constraint system, 0 if it holds others,
+// -1 if it cannot be read
+extern "C" int libsnarkwrap_test_zokrates_pk(
+    const r1cs_ppzksnark_keypair<curve_pp> *kp,
+    const r1cs_constraint_system<curve_Fr> *cs,
+    const char *path
+)
+{
+    std::stringstream ss;
+    std::ifstream fh(path, std::ios::binary);
+    if (!fh.is_open()) {
+        return -1;
+    }
+    ss << fh.rdbuf();
+    fh.close();
+    ss.rdbuf()->pubseekpos(0, std::ios_base::in);
+
+    r1cs_ppzksnark_proving_key<curve_pp> pk;
+    r1cs_constraint_system<curve_Fr> read_cs;
+    ss >> pk;
+    ss >> read_cs;
+    if (ss.fail()) {
+        return -1;
+    }
+
+    return pk == kp->pk && read_cs == *cs;
+}
@@ -0,0 +1,51 @@
+use web3::futures::Future;
+use web3::rpc::Value;
+use web3::types::{BlockId, BlockNumber, H256};
+use web3::{Transport, Web3};
+
+use spinner::{self, SpinnerBuilder};
+
+use std::time::Duration;
+use std::thread;
+
+/// Returns the hash of the block with the given number, waiting until the
+/// block has been mined.
+pub fn await_block_hash<T: Transport>(web3: &Web3<T>, number: u64, poll_interval: &Duration) -> H256 {
+    let spinner = SpinnerBuilder::new(format!("Waiting for block {}...", number)).spinner(spinner::DANCING_KIRBY.to_vec()).step(Duration::from_millis(500)).start();
+    while web3.eth().block_number().wait().expect("Error querying block number!").low_u64() < number {
+        thread::sleep(*poll_interval);
+    }
+    let block = web3.eth().block(BlockId::Number(BlockNumber::Number(number))).wait().expect("Error querying block!");
+    spinner.close();
+    block.hash.expect("Mined block should have a hash!")
+}
+
+/// Returns the id of the chain the node is connected to, from `eth_chainId`
+/// (EIP-695). Transactions are signed for it and the nizks are bound to it.
+/// The network id is not used instead, since it differs from the chain id on
+/// some chains.
+pub fn chain_id<T: Transport>(web3: &Web3<T>) -> u64 {
+    let id = web3.transport().execute("eth_chainId", vec![]).wait()
+        .unwrap_or_else(|e| panic!("\u{274c} Error querying the chain id, the node has to support eth_chainId: {:?}", e));
+    parse_chain_id(&id).unwrap_or_else(|| panic!("\u{274c} The chain id {:?} is not a hex number!", id))
+}
+
+fn parse_chain_id(id: &Value) -> Option<u64> {
+    match *id {
+        Value::String(ref id) if id.starts_with("0x") => u64::from_str_radix(&id[2..], 16).ok(),
+        _ => None
+    }
+}
+
+/// Returns the number of the latest block.
+pub fn block_number<T: Transport>(web3: &Web3<T>) -> u64 {
+    web3.eth().block_number().wait().expect("Error querying block number!").low_u64()
+}
+
+#[test]
+fn chain_ids() {
+    assert_eq!(parse_chain_id(&Value::String("0x1".into())), Some(1));
+    assert_eq!(parse_chain_id(&Value::String("0x539".into())), Some(1337));
+    assert_eq!(parse_chain_id(&Value::String("1".into())), None);
+    assert_eq!(parse_chain_id(&Value::Null), None);
+}
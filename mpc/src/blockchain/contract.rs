@@ -2,56 +2,194 @@ use web3::contract::*;
 use web3::contract::tokens::{Tokenize, Detokenize};
 use web3::futures::Future;
 use web3::{Transport};
-use web3::types::{Address, BlockNumber, H256, U256};
+use web3::types::{Address, BlockNumber, U256};
 
-pub struct ContractWrapper<T:Transport>{
+use ethabi::Token;
+#[cfg(test)]
+use hex;
+#[cfg(test)]
+use sha3::{Digest, Keccak256};
+
+use super::{contract_abi, CallReceipt, ContractEvent, LocalSigner, Submitter, TransactionError};
+
+/// The time each player has for its turn in a state of the protocol, in
+/// blocks and in seconds. A limit of 0 is disabled.
+#[derive(Clone, Copy, Default, Debug)]
+pub struct TurnTimeout {
+    pub blocks: u64,
+    pub seconds: u64
+}
+
+/// The parameters a ceremony is deployed with. Only the expected players
+/// can join if there are any, and at most `max_players` if it is not 0.
+/// Stage 1 is imported from the powers of tau file with the hash
+/// `powers_of_tau`, unless it is 0. The random beacon of each stage is the
+/// hash of the block `beacon_delay` blocks after its last transformation,
+/// hashed `2^beacon_iterations` times.
+#[derive(Clone, Default, Debug)]
+pub struct CeremonyParameters {
+    pub timeout: TurnTimeout,
+    pub expected_players: Vec<Address>,
+    pub max_players: u64,
+    pub powers_of_tau: [u8; 32],
+    pub beacon_delay: u64,
+    pub beacon_iterations: u8
+}
+
+/// The `DistributedMPC` contract. Its functions are called through the typed
+/// methods generated from `abi.json` by `build.rs`.
+pub struct DistributedMpc<T:Transport>{
     contract: Contract<T>,
-    account: Address
+    submitter: Submitter<T>
 }
 
-impl <T: Transport> ContractWrapper<T>{
-    pub fn new(contract: Contract<T>, account: Address) -> ContractWrapper<T>{
-        ContractWrapper{
+impl <T: Transport> DistributedMpc<T>{
+    /// The contract, whose transactions are sent by the submitter.
+    pub fn new(contract: Contract<T>, submitter: Submitter<T>) -> DistributedMpc<T>{
+        DistributedMpc{
             contract: contract,
-            account: account
+            submitter: submitter
         }
     }
 
-    pub fn call<P: Tokenize>(&self, method: &str, params: P) -> H256 {
-        let tokens = params.into_tokens();
-        
-        let gas = self.contract.estimate_gas(
-            method, 
-            tokens.clone().as_slice(), 
-            self.account, 
-            Options::default())
-        .wait().expect("Gas estimation should not fail!");
-        self.contract.call(
-            method, 
-            tokens.as_slice(), 
-            self.account, 
-            Options::with(|opt|{
-                //FIXME!
-                opt.gas = Some(U256::from(gas.low_u64()*3))
-            }))
-        .wait().expect(format!("Error calling contract method {:?}", method).as_str())
+    /// Calls the method in a transaction and waits until it is confirmed.
+    fn try_call<P: Tokenize>(&self, method: &str, params: P) -> Result<CallReceipt, TransactionError> {
+        let abi = contract_abi();
+        let function = abi.function(method).expect("Method should be in the abi!");
+        let data = function.encode_input(&params.into_tokens()).expect("Parameters should match the abi!");
+        self.submitter.submit(Some(self.address()), data)
+    }
+
+    /// Calls the method in a transaction and waits until it is confirmed.
+    /// Panics if the transaction fails, e.g. because it was reverted.
+    fn call<P: Tokenize>(&self, method: &str, params: P) -> CallReceipt {
+        self.try_call(method, params).unwrap_or_else(|e| panic!("\u{274c} Error calling contract method {:?}: {}", method, e))
+    }
+
+    /// Records the random beacon of the stage. Anyone can record it, so the
+    /// transaction may fail because another player recorded it first, which
+    /// is not an error.
+    pub fn record_stage_beacon(&self, stage_index: u64) {
+        if let Err(e) = self.try_call("recordBeacon", U256::from(stage_index)) {
+            if self.beacon_blocks(stage_index).is_zero() {
+                panic!("\u{274c} Error recording the random beacon of stage {}: {}", stage_index + 1, e);
+            }
+        }
     }
 
-    pub fn query<P: Tokenize, R: Detokenize>(&self, method: &str, params: P) -> R {
+    fn query<P: Tokenize, R: Detokenize>(&self, method: &str, params: P) -> R {
         self.contract.query(
             method, 
             params, 
-            self.account, 
+            self.account(), 
             Options::default(), 
             BlockNumber::Latest)
         .wait().expect(format!("Error querying contract method {:?}", method).as_str())
     }
 
     pub fn account(&self) -> Address {
-        self.account
+        self.submitter.account()
+    }
+
+    /// The signer of the player's transactions, if they are signed locally.
+    pub fn signer(&self) -> Option<&LocalSigner<T>> {
+        self.submitter.signer()
+    }
+
+    pub fn submitter(&self) -> &Submitter<T> {
+        &self.submitter
     }
 
     pub fn address(&self) -> Address {
         self.contract.address()
     }
-}
\ No newline at end of file
+}
+
+/*
+ *  CONVERSIONS OF THE PARAMETERS OF EVENTS, USED BY THE BINDINGS
+ */
+
+fn token_address(token: Option<Token>) -> Option<Address> {
+    match token {
+        Some(Token::Address(address)) => Some(address),
+        _ => None
+    }
+}
+
+fn token_bool(token: Option<Token>) -> Option<bool> {
+    match token {
+        Some(Token::Bool(value)) => Some(value),
+        _ => None
+    }
+}
+
+fn token_bytes(token: Option<Token>) -> Option<Vec<u8>> {
+    match token {
+        Some(Token::Bytes(bytes)) => Some(bytes),
+        _ => None
+    }
+}
+
+fn token_bytes32(token: Option<Token>) -> Option<[u8; 32]> {
+    match token {
+        Some(Token::FixedBytes(ref bytes)) if bytes.len() == 32 => {
+            let mut fixed = [0u8; 32];
+            fixed.copy_from_slice(bytes);
+            Some(fixed)
+        },
+        _ => None
+    }
+}
+
+fn token_string(token: Option<Token>) -> Option<String> {
+    match token {
+        Some(Token::String(string)) => Some(string),
+        _ => None
+    }
+}
+
+fn token_uint(token: Option<Token>) -> Option<U256> {
+    match token {
+        Some(Token::Uint(value)) => Some(value),
+        _ => None
+    }
+}
+
+include!(concat!(env!("OUT_DIR"), "/distributed_mpc.rs"));
+
+/// The bytecode the contract is deployed with, as hex. It is committed with
+/// the source of the contract, which the truffle tests compile and check it
+/// against.
+pub const BYTECODE: &str = include_str!("../../../blockchain/contracts/DistributedMPC.bin");
+
+#[test]
+fn bindings_match_abi() {
+    // The bindings are generated from abi.json with their own parser of the
+    // types, so they are checked against the ABI as parsed by ethabi.
+    let abi = ::ethabi::Contract::load(&include_bytes!("../../abi.json")[..]).expect("Abi should be well-formed!");
+    for &(name, inputs) in BOUND_FUNCTIONS {
+        let function = abi.function(name).unwrap_or_else(|_| panic!("The binding of {:?} is not in the abi", name));
+        assert_eq!(function.inputs.iter().map(|param| format!("{:?}", param.kind)).collect::<Vec<_>>(), inputs.to_vec(), "Inputs of {:?}", name);
+    }
+    for &(name, inputs) in BOUND_EVENTS {
+        let event = abi.event(name).unwrap_or_else(|_| panic!("The binding of {:?} is not in the abi", name));
+        assert_eq!(event.inputs.iter().map(|param| format!("{:?}", param.kind)).collect::<Vec<_>>(), inputs.to_vec(), "Inputs of {:?}", name);
+    }
+    assert_eq!(abi.functions().count(), BOUND_FUNCTIONS.len());
+    assert_eq!(abi.events().count(), BOUND_EVENTS.len());
+
+    // The bytecode dispatches on the selector of every bound function, which
+    // it pushes with PUSH4 (0x63).
+    assert_eq!(BOUND_SIGNATURES.len(), BOUND_FUNCTIONS.len());
+    for signature in BOUND_SIGNATURES {
+        let selector = hex::encode(&Keccak256::digest(signature.as_bytes())[..4]);
+        assert!(BYTECODE.contains(&format!("63{}", selector)), "The bytecode has no function {}", signature);
+    }
+}
+
+#[test]
+fn token_conversions() {
+    assert_eq!(token_bytes32(Some(Token::FixedBytes(vec![7u8; 32]))), Some([7u8; 32]));
+    assert_eq!(token_bytes32(Some(Token::FixedBytes(vec![7u8; 20]))), None);
+    assert_eq!(token_uint(Some(Token::Address(Address::zero()))), None);
+}
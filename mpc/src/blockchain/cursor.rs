@@ -0,0 +1,68 @@
+//! The block from which the events of the contract are replayed. The player
+//! stores the last block in which it queried the state of the protocol in
+//! `cursor.json`, so that after a restart it replays only the events it may
+//! have missed. Without a cursor for the contract, the events are replayed
+//! from the block the contract was deployed in.
+
+use hex;
+use serde_json;
+use web3::Transport;
+use web3::types::Address;
+
+use std::fs::File;
+use std::path::Path;
+
+use super::DistributedMpc;
+
+pub const CURSOR_FILE: &str = "cursor.json";
+
+#[derive(Serialize, Deserialize)]
+struct Cursor {
+    contract: String,
+    block: u64
+}
+
+fn contract_id(contract: Address) -> String {
+    format!("0x{}", hex::encode(&contract.0[..]))
+}
+
+/// The block stored for the contract, if any.
+pub fn read_cursor(path: &str, contract: Address) -> Option<u64> {
+    if !Path::new(path).is_file() {
+        return None;
+    }
+    let file = File::open(path).unwrap_or_else(|e| panic!("\u{274c} Error opening {:?}: {}", path, e));
+    let cursor: Cursor = serde_json::from_reader(file).unwrap_or_else(|e| panic!("\u{274c} Error parsing {:?}: {}", path, e));
+    if cursor.contract == contract_id(contract) {
+        Some(cursor.block)
+    } else {
+        None
+    }
+}
+
+pub fn write_cursor(path: &str, contract: Address, block: u64) {
+    let file = File::create(path).unwrap_or_else(|e| panic!("\u{274c} Error creating {:?}: {}", path, e));
+    serde_json::to_writer_pretty(file, &Cursor {
+        contract: contract_id(contract),
+        block: block
+    }).expect("Error writing cursor!");
+}
+
+/// The block from which the events of the contract are replayed.
+pub fn replay_start<T: Transport>(contract: &DistributedMpc<T>, path: &str) -> u64 {
+    read_cursor(path, contract.address()).unwrap_or_else(|| contract.deployment_block().low_u64())
+}
+
+#[test]
+fn cursor_roundtrip() {
+    let path = ::std::env::temp_dir().join(format!("cursor-{}.json", ::std::process::id()));
+    let path = path.to_str().unwrap();
+    let contract = Address::from(1);
+    assert_eq!(read_cursor(path, contract), None);
+
+    write_cursor(path, contract, 42);
+    assert_eq!(read_cursor(path, contract), Some(42));
+    // A cursor of another contract is ignored.
+    assert_eq!(read_cursor(path, Address::from(2)), None);
+    ::std::fs::remove_file(path).unwrap();
+}
@@ -2,90 +2,164 @@ extern crate spinner;
 
 use consts::*;
 
-use web3::api::BaseFilter;
+use ethabi::{self, RawLog, Token};
 use web3::contract::tokens::Detokenize;
-use web3::futures::Future;
-use web3::types::{Address, Filter, FilterBuilder, Log, H256};
+use web3::types::{Address, Log, U256};
 use web3::{Transport, Web3};
 
-use sha3::{Digest, Keccak256};
-
 use spinner::SpinnerBuilder;
 
-use std::fmt::{Write};
-use std::str::FromStr;
+use std::collections::VecDeque;
+use std::marker::PhantomData;
 use std::time::{Duration, Instant};
-use std::thread;
 
 use time::{Duration as MDuration};
 
+use super::{EventTransport, LogStream};
+
+/// An event of the contract, decoded from a log with the ABI of the contract.
+/// It is implemented by the structs `build.rs` generates for the events.
+pub trait ContractEvent: Sized {
+    /// The name of the event in the ABI.
+    fn name() -> &'static str;
+
+    /// The event from the values of its parameters, or `None` if they do not
+    /// have the expected types.
+    fn from_tokens(tokens: Vec<Token>) -> Option<Self>;
+}
+
+/// The ABI of the contract the events are decoded with.
+pub fn contract_abi() -> ethabi::Contract {
+    ethabi::Contract::load(&include_bytes!("../../abi.json")[..]).expect("Abi should be well-formed!")
+}
+
+/// Decodes a log of the event with the ABI of the event. Logs with another
+/// signature or malformed data are `None`.
+pub fn decode_event<E: ContractEvent>(event: &ethabi::Event, log: RawLog) -> Option<E> {
+    event.parse_log(log).ok().and_then(|log| E::from_tokens(log.params.into_iter().map(|param| param.value).collect()))
+}
+
+/// The position of a log in the chain, as block number and index of the log
+/// in the block.
+fn log_position(log: &Log) -> Option<(U256, U256)> {
+    match (log.block_number, log.log_index) {
+        (Some(block), Some(index)) => Some((block, index)),
+        _ => None
+    }
+}
+
+/// Creates filters for the events of one contract. The filters replay the
+/// events from `from_block` on before they wait for new ones, so events that
+/// happened before the player started are not missed.
 #[derive(Clone)]
 pub struct EventFilterBuilder<T: Transport>{
-    web3: Web3<T>
+    web3: Web3<T>,
+    address: Address,
+    from_block: u64,
+    abi: ethabi::Contract
 }
 
-impl<T: Transport> EventFilterBuilder<T> {
-    pub fn new(web3: Web3<T>) -> Self{
+impl<T: EventTransport> EventFilterBuilder<T> {
+    pub fn new(web3: Web3<T>, address: Address, from_block: u64) -> Self{
         EventFilterBuilder {
-            web3: web3
+            web3: web3,
+            address: address,
+            from_block: from_block,
+            abi: contract_abi()
         }
     }
 
-    pub fn create_filter<F, S>(
+    /// Creates a filter for the event `E` of the contract. The callback is
+    /// called with every event in the order of the chain until it returns a
+    /// result, and every event is passed to it only once.
+    pub fn create_filter<E, F, S>(
         &self, 
-        topic: &str, 
         msg: String, 
         cb: F, 
         extra_data: Option<Address>
-        ) -> EventFilter<T, F, S> where
-        F: Fn(Vec<Log>, Option<Address>) -> Option<S>,
+        ) -> EventFilter<T, E, F, S> where
+        E: ContractEvent,
+        F: Fn(E, Option<Address>) -> Option<S>,
         S: Detokenize
     {
-        let mut filter_builder: FilterBuilder = FilterBuilder::default();
-        let topic_hash = Keccak256::digest(topic.as_bytes());
-        let hex_str = self.get_hex_string(&topic_hash.as_slice().to_owned());
-        filter_builder = filter_builder.topics(Some(vec![H256::from_str(hex_str.as_str()).expect("Error parsing topic from string!")]), None, None, None);
-        let filter: Filter = filter_builder.build();
-        let create_filter = self.web3.eth_filter().create_logs_filter(filter);
-        let event_filter = create_filter.wait().expect("Filter should be registerable!");
-        EventFilter { 
-            filter: event_filter,
+        let event = self.abi.event(E::name()).expect("Event should be in the abi!").clone();
+        let (replayed, logs) = T::watch_logs(&self.web3, self.address, event.signature(), self.from_block);
+        let mut filter = EventFilter { 
+            logs: logs,
+            transport: PhantomData,
+            event: event,
             wait_message: msg,
             callback: cb,
-            parameter: extra_data
-        }
-    }
-
-    fn get_hex_string(&self, bytes: &Vec<u8>) -> String {
-        let mut s = String::new();
-        for byte in bytes {
-            write!(s, "{:02x}", byte).expect("Failed to write byte as hex");
-        }
-        s 
+            parameter: extra_data,
+            pending: VecDeque::new(),
+            last: None
+        };
+        filter.enqueue(replayed);
+        filter
     }
 }
 
-pub struct EventFilter<T: Transport, F: Fn(Vec<Log>, Option<Address>) -> Option<S>, S: Detokenize> {
-    filter: BaseFilter<T, Log>,
+pub struct EventFilter<T: Transport, E: ContractEvent, F: Fn(E, Option<Address>) -> Option<S>, S: Detokenize> {
+    logs: Box<LogStream>,
+    transport: PhantomData<T>,
+    event: ethabi::Event,
     wait_message: String,
     callback: F,
-    parameter: Option<Address>
+    parameter: Option<Address>,
+    pending: VecDeque<E>,
+    last: Option<(U256, U256)>
 }
 
-impl<T, F, S> EventFilter<T, F, S> where 
+impl<T, E, F, S> EventFilter<T, E, F, S> where 
     T: Transport,
-    F: Fn(Vec<Log>, Option<Address>) -> Option<S>,
+    E: ContractEvent,
+    F: Fn(E, Option<Address>) -> Option<S>,
     S: Detokenize
 {
+    /// Decodes the logs and queues the events, skipping logs that were
+    /// queued before. The replayed logs and the new ones can overlap.
+    fn enqueue(&mut self, logs: Vec<Log>) {
+        for log in logs {
+            let position = log_position(&log);
+            if position.is_some() && position <= self.last {
+                continue;
+            }
+            if position.is_some() {
+                self.last = position;
+            }
+            if let Some(event) = decode_event(&self.event, RawLog {
+                topics: log.topics,
+                data: log.data.0
+            }) {
+                self.pending.push_back(event);
+            }
+        }
+    }
+
     pub fn await(&mut self, duration: &Duration) -> Option<S> where
     S: Detokenize
+    {
+        self.await_until(duration, || false)
+    }
+
+    /// Like `await`, but gives up and returns `None` as soon as `stop`
+    /// returns true, e.g. when the deadline of a turn has passed. `stop` is
+    /// checked at least once per `duration`.
+    pub fn await_until<D>(&mut self, duration: &Duration, stop: D) -> Option<S> where
+    S: Detokenize,
+    D: Fn() -> bool
     {
         let wait_start = Instant::now();
         let spinner = SpinnerBuilder::new(String::from(&*self.wait_message)).spinner(spinner::DANCING_KIRBY.to_vec()).step(Duration::from_millis(500)).start();
         loop {
-            let result = self.filter.poll().wait().expect("New Stage Filter should return result!").expect("Polling result should be valid!");
-            let cb_result = (self.callback)(result, self.parameter);
-            if cb_result.is_some() {
+            let mut cb_result = None;
+            while cb_result.is_none() {
+                match self.pending.pop_front() {
+                    Some(event) => cb_result = (self.callback)(event, self.parameter),
+                    None => break
+                }
+            }
+            if cb_result.is_some() || stop() {
                 spinner.close();
                 if PERFORM_MEASUREMENTS {
                     let duration = MDuration::from_std(wait_start.elapsed());
@@ -99,8 +173,40 @@ impl<T, F, S> EventFilter<T, F, S> where
                 }
                 return cb_result;
             }
-            thread::sleep(*duration);
+            let logs = self.logs.next_logs(duration);
+            self.enqueue(logs);
         }
 
     }
-}
\ No newline at end of file
+}
+
+#[test]
+fn decode_events() {
+    use web3::types::H256;
+    use super::{NextStage, PlayerJoined, StageResultPublished};
+
+    let abi = contract_abi();
+    let log = |event: &ethabi::Event, tokens: &[Token]| RawLog {
+        topics: vec![event.signature()],
+        data: ethabi::encode(tokens)
+    };
+
+    // Results of any length are decoded, e.g. a CIDv1 instead of a CIDv0.
+    let player = Address::from(&[2u8; 20][..]);
+    let cid = b"bafybeigdyrzt5sfp7udm7hu76uh7y26nf3efuylqabf3oclgtqy55fbzdi".to_vec();
+    let event = abi.event("StageResultPublished").unwrap();
+    let published: Option<StageResultPublished> = decode_event(event, log(event, &[Token::Address(player), Token::Bytes(cid.clone())]));
+    assert_eq!(published, Some(StageResultPublished { player: player, result: cid }));
+
+    let event = abi.event("NextStage").unwrap();
+    let next: Option<NextStage> = decode_event(event, log(event, &[Token::Uint(U256::from(5))]));
+    assert_eq!(next, Some(NextStage { stage: U256::from(5) }));
+
+    // Logs of another event are not decoded.
+    let joined = abi.event("PlayerJoined").unwrap();
+    assert_eq!(decode_event::<PlayerJoined>(joined, log(event, &[Token::Uint(U256::from(5))])), None);
+    let mut forged = log(joined, &[Token::Address(player)]);
+    forged.topics = vec![H256::zero()];
+    assert_eq!(decode_event::<PlayerJoined>(joined, forged), None);
+    assert_eq!(decode_event::<PlayerJoined>(joined, log(joined, &[Token::Address(player)])), Some(PlayerJoined { player: player }));
+}
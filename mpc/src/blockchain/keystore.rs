@@ -0,0 +1,164 @@
+//! Keys of players who sign their transactions locally instead of with an
+//! account of the node. A key is read from an encrypted JSON keystore in the
+//! Web3 Secret Storage format (version 3, as written by geth or parity, with
+//! scrypt or PBKDF2 and AES-128-CTR), or from a file holding the private key
+//! as hex. The content of the file and the password are overwritten with
+//! zeros once the key is in protected memory.
+
+use crypto::aes::{self, KeySize};
+use crypto::hmac::Hmac;
+use crypto::pbkdf2::pbkdf2;
+use crypto::scrypt::{scrypt, ScryptParams};
+use crypto::sha2::Sha256;
+use crypto::symmetriccipher::SynchronousStreamCipher;
+use hex;
+use protocol::{zeroize_string, zeroize_vec, Protected};
+use rpassword;
+use serde_json::{self, Value};
+use sha3::{Digest, Keccak256};
+
+use std::fs::File;
+use std::io::Read;
+
+fn hex_field(object: &Value, field: &str) -> Result<Vec<u8>, String> {
+    object[field].as_str()
+        .ok_or_else(|| format!("The keystore has no {:?}", field))
+        .and_then(|value| hex::decode(value).map_err(|e| format!("The {:?} of the keystore is not hex: {}", field, e)))
+}
+
+fn number_field(object: &Value, field: &str) -> Result<u64, String> {
+    object[field].as_u64().ok_or_else(|| format!("The keystore has no {:?}", field))
+}
+
+/// Derives the key that encrypts the private key from the password.
+fn derive_key(crypto: &Value, password: &str) -> Result<Protected<[u8; 32]>, String> {
+    let params = &crypto["kdfparams"];
+    if try!(number_field(params, "dklen")) != 32 {
+        return Err("Only keystores with a derived key of 32 bytes are supported".into());
+    }
+    let salt = try!(hex_field(params, "salt"));
+    let mut derived = Protected::new([0u8; 32]);
+    match crypto["kdf"].as_str() {
+        Some("scrypt") => {
+            let n = try!(number_field(params, "n"));
+            if !n.is_power_of_two() {
+                return Err(format!("The scrypt parameter n = {} is not a power of two", n));
+            }
+            let params = ScryptParams::new(n.trailing_zeros() as u8, try!(number_field(params, "r")) as u32, try!(number_field(params, "p")) as u32);
+            scrypt(password.as_bytes(), &salt, &params, &mut derived[..]);
+        },
+        Some("pbkdf2") => {
+            if params["prf"].as_str() != Some("hmac-sha256") {
+                return Err("Only PBKDF2 with hmac-sha256 is supported".into());
+            }
+            let mut mac = Hmac::new(Sha256::new(), password.as_bytes());
+            pbkdf2(&mut mac, &salt, try!(number_field(params, "c")) as u32, &mut derived[..]);
+        },
+        kdf => return Err(format!("Unsupported key derivation function {:?}", kdf))
+    }
+    Ok(derived)
+}
+
+/// Decrypts the private key of a version 3 keystore.
+pub fn decrypt_keystore(keystore: &str, password: &str) -> Result<Protected<[u8; 32]>, String> {
+    let keystore: Value = try!(serde_json::from_str(keystore).map_err(|e| format!("The keystore is not valid JSON: {}", e)));
+    if keystore["version"].as_u64() != Some(3) {
+        return Err("Only keystores of version 3 are supported".into());
+    }
+    // geth writes "crypto", older clients "Crypto".
+    let crypto = if keystore["crypto"].is_object() { &keystore["crypto"] } else { &keystore["Crypto"] };
+    if crypto["cipher"].as_str() != Some("aes-128-ctr") {
+        return Err(format!("Unsupported cipher {:?}", crypto["cipher"]));
+    }
+    let ciphertext = try!(hex_field(crypto, "ciphertext"));
+    let iv = try!(hex_field(&crypto["cipherparams"], "iv"));
+    let mac = try!(hex_field(crypto, "mac"));
+    if ciphertext.len() != 32 || iv.len() != 16 {
+        return Err("The keystore does not hold a secp256k1 key".into());
+    }
+
+    let derived = try!(derive_key(crypto, password));
+    let mut hasher = Keccak256::new();
+    hasher.input(&derived[16..]);
+    hasher.input(&ciphertext);
+    if hasher.result().as_slice() != &mac[..] {
+        return Err("Wrong password, or the keystore is corrupt".into());
+    }
+
+    let mut key = Protected::new([0u8; 32]);
+    aes::ctr(KeySize::KeySize128, &derived[..16], &iv).process(&ciphertext, &mut key[..]);
+    Ok(key)
+}
+
+/// Parses a private key given as 64 hex digits, with or without `0x`.
+pub fn parse_raw_key(s: &str) -> Result<Protected<[u8; 32]>, String> {
+    let s = s.trim();
+    let s = if s.starts_with("0x") { &s[2..] } else { s };
+    let mut bytes = try!(hex::decode(s).map_err(|e| format!("The key is not hex: {}", e)));
+    if bytes.len() != 32 {
+        let len = bytes.len();
+        zeroize_vec(&mut bytes);
+        return Err(format!("The key has {} bytes instead of 32", len));
+    }
+    let mut key = Protected::new([0u8; 32]);
+    key.copy_from_slice(&bytes);
+    zeroize_vec(&mut bytes);
+    Ok(key)
+}
+
+/// Asks for a password on the terminal without echoing it. The password is
+/// always asked for, even if the player runs without other prompts.
+pub fn read_password(message: &str) -> String {
+    rpassword::read_password_from_tty(Some(message)).unwrap_or_else(|e| panic!("\u{274c} Error reading the password: {}", e))
+}
+
+/// Reads the key in the file at the path. A keystore is decrypted with the
+/// password, which is only asked for if the file is a keystore.
+pub fn load_key<P: FnOnce() -> String>(path: &str, password: P) -> Protected<[u8; 32]> {
+    let mut file = File::open(path).unwrap_or_else(|e| panic!("\u{274c} Error opening the key file {:?}: {}", path, e));
+    // The string is allocated at its final size, so reading leaves no
+    // copies of the key in reallocated memory.
+    let size = file.metadata().map(|metadata| metadata.len() as usize).unwrap_or(0);
+    let mut content = String::with_capacity(size + 1);
+    file.read_to_string(&mut content).unwrap_or_else(|e| panic!("\u{274c} Error reading the key file {:?}: {}", path, e));
+    let key = if content.trim_left().starts_with('{') {
+        let mut password = password();
+        let key = decrypt_keystore(&content, &password);
+        zeroize_string(&mut password);
+        key
+    } else {
+        parse_raw_key(&content)
+    };
+    zeroize_string(&mut content);
+    key.unwrap_or_else(|e| panic!("\u{274c} Error loading the key from {:?}: {}", path, e))
+}
+
+#[test]
+fn decrypt_keystores() {
+    let key = [0x42u8; 32];
+    let salt = [7u8; 32];
+    let iv = [9u8; 16];
+    let keystore = |kdf: &str, kdfparams: &str, derived: &[u8]| {
+        let mut ciphertext = [0u8; 32];
+        aes::ctr(KeySize::KeySize128, &derived[..16], &iv).process(&key, &mut ciphertext);
+        let mac = Keccak256::digest(&[&derived[16..], &ciphertext[..]].concat());
+        format!(
+            r#"{{"version": 3, "crypto": {{"cipher": "aes-128-ctr", "cipherparams": {{"iv": "{}"}}, "ciphertext": "{}", "kdf": "{}", "kdfparams": {}, "mac": "{}"}}}}"#,
+            hex::encode(&iv), hex::encode(&ciphertext), kdf, kdfparams, hex::encode(mac.as_slice())
+        )
+    };
+
+    let mut derived = [0u8; 32];
+    scrypt(b"secret", &salt, &ScryptParams::new(4, 8, 1), &mut derived);
+    let scrypted = keystore("scrypt", &format!(r#"{{"dklen": 32, "n": 16, "r": 8, "p": 1, "salt": "{}"}}"#, hex::encode(&salt)), &derived);
+    assert_eq!(*decrypt_keystore(&scrypted, "secret").unwrap(), key);
+    assert!(decrypt_keystore(&scrypted, "wrong").is_err());
+
+    pbkdf2(&mut Hmac::new(Sha256::new(), b"secret"), &salt, 16, &mut derived);
+    let pbkdf2ed = keystore("pbkdf2", &format!(r#"{{"dklen": 32, "c": 16, "prf": "hmac-sha256", "salt": "{}"}}"#, hex::encode(&salt)), &derived);
+    assert_eq!(*decrypt_keystore(&pbkdf2ed, "secret").unwrap(), key);
+    assert!(decrypt_keystore(&pbkdf2ed.replace("\"version\": 3", "\"version\": 2"), "secret").is_err());
+
+    assert_eq!(*parse_raw_key(&format!("0x{}\n", hex::encode(&key))).unwrap(), key);
+    assert!(parse_raw_key("0x4242").is_err());
+}
@@ -2,4 +2,27 @@ mod event;
 pub use self::event::*;
 
 mod contract;
-pub use self::contract::*;
\ No newline at end of file
+pub use self::contract::*;
+
+mod block;
+pub use self::block::*;
+
+mod sign;
+pub use self::sign::*;
+
+mod keystore;
+pub use self::keystore::*;
+
+mod transaction;
+pub use self::transaction::*;
+
+mod submission;
+pub use self::submission::*;
+
+mod cursor;
+pub use self::cursor::*;
+
+
+#[macro_use]
+mod transport;
+pub use self::transport::*;
@@ -0,0 +1,97 @@
+use secp256k1::{Message, Secp256k1};
+use secp256k1::key::{PublicKey, SecretKey};
+use secp256k1::{RecoverableSignature, RecoveryId};
+use sha3::{Digest, Keccak256};
+use web3::futures::Future;
+use web3::types::{Address, Bytes};
+use web3::{Transport, Web3};
+
+use super::DistributedMpc;
+
+/// The hash `eth_sign` signs for a message, which is prefixed so that a
+/// signature can never be mistaken for a transaction.
+pub fn signed_message_hash(message: &[u8]) -> Vec<u8> {
+    let mut prefixed = format!("\x19Ethereum Signed Message:\n{}", message.len()).into_bytes();
+    prefixed.extend_from_slice(message);
+    Keccak256::digest(&prefixed).as_slice().to_owned()
+}
+
+/// The address of an uncompressed secp256k1 public key.
+pub fn public_key_address(pubkey: &PublicKey) -> Address {
+    let serialized = pubkey.serialize_uncompressed();
+    Address::from(&Keccak256::digest(&serialized[1..]).as_slice()[12..])
+}
+
+/// Signs the hash with the key. Returns the recovery id and the compact
+/// signature `r || s`.
+pub fn sign_hash(key: &[u8; 32], hash: &[u8]) -> (u8, [u8; 64]) {
+    let secp = Secp256k1::new();
+    let secret = SecretKey::from_slice(&secp, &key[..]).expect("\u{274c} The key is not a valid secp256k1 key!");
+    let message = Message::from_slice(hash).expect("Hash should have 32 bytes!");
+    let (recovery_id, signature) = secp.sign_recoverable(&message, &secret).expect("Error signing!").serialize_compact(&secp);
+    (recovery_id.to_i32() as u8, signature)
+}
+
+/// Signs the message with the key of the player's account: locally if the
+/// player signs with its own key, otherwise on the node, where the account
+/// has to be unlocked. Returns the 65 byte signature `r || s || v`.
+pub fn sign<T: Transport>(web3: &Web3<T>, contract: &DistributedMpc<T>, message: &[u8]) -> Vec<u8> {
+    if let Some(signer) = contract.signer() {
+        return signer.sign_message(message);
+    }
+    let signature = web3.eth().sign(contract.account(), Bytes(message.to_vec())).wait().expect("Error signing message!");
+    signature.to_vec()
+}
+
+/// Recovers the address that signed the message with `eth_sign`. Returns
+/// `None` if the signature is malformed.
+pub fn recover(message: &[u8], signature: &[u8]) -> Option<Address> {
+    if signature.len() != 65 {
+        return None;
+    }
+    // Nodes differ in whether v is 0/1 or 27/28.
+    let v = match signature[64] {
+        v @ 0...1 => v,
+        v @ 27...28 => v - 27,
+        _ => return None
+    };
+
+    let secp = Secp256k1::new();
+    let message = match Message::from_slice(&signed_message_hash(message)) {
+        Ok(message) => message,
+        Err(_) => return None
+    };
+    let recovery_id = match RecoveryId::from_i32(v as i32) {
+        Ok(id) => id,
+        Err(_) => return None
+    };
+    let signature = match RecoverableSignature::from_compact(&secp, &signature[..64], recovery_id) {
+        Ok(signature) => signature,
+        Err(_) => return None
+    };
+
+    secp.recover(&message, &signature).ok().map(|pubkey| public_key_address(&pubkey))
+}
+
+#[test]
+fn recover_signer() {
+    let secp = Secp256k1::new();
+    let secret = SecretKey::from_slice(&secp, &[0x42; 32]).unwrap();
+    let address = public_key_address(&PublicKey::from_secret_key(&secp, &secret).unwrap());
+
+    // Sign as eth_sign does
+    let message = b"a contribution";
+    let hash = Message::from_slice(&signed_message_hash(message)).unwrap();
+    let (recovery_id, compact) = secp.sign_recoverable(&hash, &secret).unwrap().serialize_compact(&secp);
+    let mut signature = compact.to_vec();
+    signature.push(recovery_id.to_i32() as u8 + 27);
+
+    assert_eq!(recover(message, &signature), Some(address));
+    assert!(recover(b"another contribution", &signature) != Some(address));
+
+    signature[64] -= 27;
+    assert_eq!(recover(message, &signature), Some(address));
+    signature[64] = 5;
+    assert_eq!(recover(message, &signature), None);
+    assert_eq!(recover(message, &signature[..64]), None);
+}
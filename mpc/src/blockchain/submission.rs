@@ -0,0 +1,678 @@
+//! How the transactions of the player are submitted. A transaction is sent
+//! with a gas limit derived from the node's estimate and a gas price derived
+//! from the node's price, and then awaited until it has the configured
+//! number of confirmations. A transaction the node rejects as underpriced,
+//! that is dropped from the pool or that is not mined in time is sent again
+//! with the same nonce and a higher gas price. If the node answers that the
+//! nonce is too low, one of the transactions sent before has to be mined,
+//! otherwise another transaction took the nonce and the submission fails. A
+//! transaction the node knows already counts as sent. A reverted transaction
+//! is an error that carries the reason the contract gave in its `require`,
+//! while a node that cannot be queried is an error of its own.
+
+use ethabi::{self, ParamType, Token};
+use hex;
+use web3::futures::Future;
+use web3::rpc::Value;
+use web3::types::{Address, H256, U256};
+use web3::{Transport, Web3};
+
+use std::fmt;
+use std::rc::Rc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use time::Duration as MDuration;
+
+use consts::*;
+use super::{block_number, LocalSigner};
+
+/// The selector of `Error(string)`, with which a revert reason is encoded.
+const REVERT_SELECTOR: &str = "08c379a0";
+
+/// The gas limit of a transaction.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum GasLimit {
+    /// The percentage of the node's estimate.
+    Estimate(u64),
+    Fixed(u64)
+}
+
+/// The gas price of a transaction.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum GasPrice {
+    /// The percentage of the node's gas price.
+    Node(u64),
+    /// The price in wei.
+    Fixed(u64)
+}
+
+impl GasLimit {
+    /// Parses a gas limit given as a percentage of the estimate, e.g.
+    /// `150%`, or as an amount of gas.
+    pub fn parse(s: &str) -> Result<GasLimit, String> {
+        if s.ends_with('%') {
+            s[..s.len() - 1].parse().map(GasLimit::Estimate).map_err(|_| format!("Invalid percentage {:?}", s))
+        } else {
+            s.parse().map(GasLimit::Fixed).map_err(|_| format!("Invalid gas limit {:?}, expected an amount of gas or a percentage of the estimate", s))
+        }
+    }
+}
+
+impl GasPrice {
+    /// Parses a gas price given as a percentage of the node's price, e.g.
+    /// `120%`, or in wei.
+    pub fn parse(s: &str) -> Result<GasPrice, String> {
+        if s.ends_with('%') {
+            s[..s.len() - 1].parse().map(GasPrice::Node).map_err(|_| format!("Invalid percentage {:?}", s))
+        } else {
+            s.parse().map(GasPrice::Fixed).map_err(|_| format!("Invalid gas price {:?}, expected wei or a percentage of the node's price", s))
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct TransactionPolicy {
+    pub gas_limit: GasLimit,
+    pub gas_price: GasPrice,
+    /// The number of blocks, including the one the transaction is mined in,
+    /// after which a transaction is final.
+    pub confirmations: u64,
+    /// How often a transaction is sent again before giving up.
+    pub retries: u32,
+    /// The time after which a pending transaction is sent again.
+    pub resend_after: Duration,
+    /// The percentage of the last gas price a transaction is sent again with.
+    /// Nodes only replace a pending transaction for at least 110%.
+    pub price_bump: u64,
+    pub poll_interval: Duration
+}
+
+impl Default for TransactionPolicy {
+    fn default() -> TransactionPolicy {
+        TransactionPolicy {
+            gas_limit: GasLimit::Estimate(150),
+            gas_price: GasPrice::Node(100),
+            confirmations: 1,
+            retries: 3,
+            resend_after: Duration::from_secs(300),
+            price_bump: 125,
+            poll_interval: Duration::new(1, 0)
+        }
+    }
+}
+
+/// A mined transaction with enough confirmations.
+#[derive(Clone, Debug)]
+pub struct CallReceipt {
+    pub transaction_hash: H256,
+    pub block_number: u64,
+    pub gas_used: U256,
+    /// The address of a deployed contract.
+    pub contract_address: Option<Address>
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum TransactionError {
+    /// The transaction reverts, with the reason of the contract if it gave
+    /// one. Reverts are detected when the gas is estimated or in the receipt.
+    Reverted(Option<String>),
+    /// The node did not accept the transaction.
+    Rejected(String),
+    /// The transaction was not mined after all retries.
+    NotMined(u32),
+    /// The node could not be queried, or gave an invalid answer.
+    Node(String)
+}
+
+impl fmt::Display for TransactionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            TransactionError::Reverted(Some(ref reason)) => write!(f, "The transaction was reverted: {}", reason),
+            TransactionError::Reverted(None) => write!(f, "The transaction was reverted"),
+            TransactionError::Rejected(ref error) => write!(f, "The node rejected the transaction: {}", error),
+            TransactionError::NotMined(attempts) => write!(f, "The transaction was not mined after {} attempts", attempts),
+            TransactionError::Node(ref error) => write!(f, "Error querying the node: {}", error)
+        }
+    }
+}
+
+fn hex_value(bytes: &[u8]) -> Value {
+    Value::String(format!("0x{}", hex::encode(bytes)))
+}
+
+fn quantity(value: U256) -> Value {
+    Value::String(format!("0x{:x}", value))
+}
+
+fn parse_quantity(value: &Value) -> Option<U256> {
+    value.as_str().and_then(|s| if s.starts_with("0x") { Some(&s[2..]) } else { None }).and_then(|s| {
+        if s.is_empty() { Some(U256::zero()) } else { s.parse().ok() }
+    })
+}
+
+fn rpc<T: Transport>(web3: &Web3<T>, method: &str, params: Vec<Value>) -> Result<Value, String> {
+    web3.transport().execute(method, params).wait().map_err(|e| format!("{}", e))
+}
+
+/// The reason a contract gave for a revert, found in the revert data
+/// (`Error(string)`) or in the message of the node's error.
+pub fn revert_reason(error: &str) -> Option<String> {
+    if let Some(start) = error.find(REVERT_SELECTOR) {
+        let data: String = error[start + REVERT_SELECTOR.len()..].chars().take_while(|c| c.is_digit(16)).collect();
+        let decoded = hex::decode(&data).ok().and_then(|data| ethabi::decode(&[ParamType::String], &data).ok());
+        if let Some(Token::String(reason)) = decoded.and_then(|tokens| tokens.into_iter().next()) {
+            return Some(reason);
+        }
+    }
+    // e.g. "VM Exception while processing transaction: revert Not your turn"
+    error.find("revert ").map(|start| {
+        error[start + 7..].split(|c| c == '"' || c == '\n').next().unwrap_or("").trim().to_string()
+    }).and_then(|reason| if reason.is_empty() { None } else { Some(reason) })
+}
+
+/// Whether the error of `eth_estimateGas` means that the transaction
+/// reverts, rather than that the node failed.
+fn is_revert(error: &str) -> bool {
+    let error = error.to_lowercase();
+    ["revert", "always failing transaction", "invalid opcode"].iter().any(|e| error.contains(e))
+}
+
+/// Whether the transaction may be accepted when it is sent again with a
+/// higher gas price.
+fn is_retryable(error: &str) -> bool {
+    error.to_lowercase().contains("underpriced")
+}
+
+/// Whether the node has the transaction in its pool already.
+fn is_known_transaction(error: &str) -> bool {
+    let error = error.to_lowercase();
+    ["already known", "known transaction"].iter().any(|e| error.contains(e))
+}
+
+/// Whether the nonce of the transaction was used by a mined transaction.
+fn is_nonce_used(error: &str) -> bool {
+    error.to_lowercase().contains("nonce too low")
+}
+
+/// What the node answered to a transaction that was sent.
+enum Sent {
+    /// The node accepted the transaction, which has the hash and nonce.
+    Accepted(H256, U256),
+    /// The node has the transaction already. Its hash is only known if it
+    /// was signed locally.
+    Known(Option<(H256, U256)>, String),
+    /// The node did not accept the transaction.
+    Refused(String)
+}
+
+/// Sends transactions from the account of the player according to the
+/// policy, signed by the node or locally by the signer.
+#[derive(Clone)]
+pub struct Submitter<T: Transport> {
+    web3: Web3<T>,
+    account: Address,
+    signer: Option<Rc<LocalSigner<T>>>,
+    policy: TransactionPolicy
+}
+
+impl<T: Transport> Submitter<T> {
+    pub fn new(web3: Web3<T>, account: Address, signer: Option<Rc<LocalSigner<T>>>, policy: TransactionPolicy) -> Submitter<T> {
+        Submitter {
+            web3: web3,
+            account: account,
+            signer: signer,
+            policy: policy
+        }
+    }
+
+    pub fn account(&self) -> Address {
+        self.account
+    }
+
+    pub fn signer(&self) -> Option<&LocalSigner<T>> {
+        self.signer.as_ref().map(|signer| &**signer)
+    }
+
+    fn call_object(&self, to: Option<Address>, data: &[u8]) -> Value {
+        let mut object = Value::Object(Default::default());
+        object["from"] = hex_value(&self.account.0[..]);
+        object["data"] = hex_value(data);
+        if let Some(to) = to {
+            object["to"] = hex_value(&to.0[..]);
+        }
+        object
+    }
+
+    fn gas_limit(&self, to: Option<Address>, data: &[u8]) -> Result<U256, TransactionError> {
+        let estimate = match rpc(&self.web3, "eth_estimateGas", vec![self.call_object(to, data)]) {
+            Ok(estimate) => try!(parse_quantity(&estimate).ok_or_else(|| TransactionError::Node(format!("Invalid gas estimate {}", estimate)))),
+            Err(ref e) if is_revert(e) => return Err(TransactionError::Reverted(revert_reason(e))),
+            Err(e) => return Err(TransactionError::Node(e))
+        };
+        Ok(match self.policy.gas_limit {
+            GasLimit::Estimate(percent) => estimate * U256::from(percent) / U256::from(100),
+            GasLimit::Fixed(gas) => U256::from(gas)
+        })
+    }
+
+    fn gas_price(&self) -> Result<U256, TransactionError> {
+        Ok(match self.policy.gas_price {
+            GasPrice::Node(percent) => {
+                let price = try!(rpc(&self.web3, "eth_gasPrice", vec![]).map_err(TransactionError::Node));
+                let price = try!(parse_quantity(&price).ok_or_else(|| TransactionError::Node(format!("Invalid gas price {}", price))));
+                price * U256::from(percent) / U256::from(100)
+            },
+            GasPrice::Fixed(price) => U256::from(price)
+        })
+    }
+
+    /// The nonce of the next transaction of the account.
+    fn next_nonce(&self) -> Result<U256, TransactionError> {
+        if let Some(ref signer) = self.signer {
+            return signer.next_nonce().map_err(TransactionError::Node);
+        }
+        let nonce = try!(rpc(&self.web3, "eth_getTransactionCount", vec![hex_value(&self.account.0[..]), "pending".into()]).map_err(TransactionError::Node));
+        parse_quantity(&nonce).ok_or_else(|| TransactionError::Node(format!("Invalid nonce {}", nonce)))
+    }
+
+    /// Sends the transaction with the nonce, or the next nonce of the
+    /// account.
+    fn send(&self, to: Option<Address>, data: &[u8], gas: U256, gas_price: U256, nonce: Option<U256>) -> Result<Sent, TransactionError> {
+        let nonce = match nonce {
+            Some(nonce) => nonce,
+            None => try!(self.next_nonce())
+        };
+        if let Some(ref signer) = self.signer {
+            let (raw, hash) = signer.sign_transaction(to, data.to_vec(), gas, gas_price, nonce);
+            return Ok(match signer.send_raw_transaction(raw, nonce) {
+                Ok(_) => Sent::Accepted(hash, nonce),
+                Err(e) => if is_known_transaction(&e) { Sent::Known(Some((hash, nonce)), e) } else { Sent::Refused(e) }
+            });
+        }
+        let mut transaction = self.call_object(to, data);
+        transaction["gas"] = quantity(gas);
+        transaction["gasPrice"] = quantity(gas_price);
+        transaction["nonce"] = quantity(nonce);
+        let hash = match rpc(&self.web3, "eth_sendTransaction", vec![transaction]) {
+            Ok(hash) => hash,
+            Err(e) => return Ok(if is_known_transaction(&e) { Sent::Known(None, e) } else { Sent::Refused(e) })
+        };
+        let hash = try!(hash.as_str().and_then(|hash| if hash.starts_with("0x") { hash[2..].parse().ok() } else { None })
+            .ok_or_else(|| TransactionError::Node(format!("Invalid transaction hash {}", hash))));
+        Ok(Sent::Accepted(hash, nonce))
+    }
+
+    /// The receipt of the transaction, if it is mined.
+    fn receipt(&self, hash: H256) -> Option<Value> {
+        rpc(&self.web3, "eth_getTransactionReceipt", vec![hex_value(&hash.0[..])]).ok()
+            .and_then(|receipt| if receipt.is_null() || receipt["blockNumber"].is_null() { None } else { Some(receipt) })
+    }
+
+    fn is_known(&self, hash: H256) -> bool {
+        rpc(&self.web3, "eth_getTransactionByHash", vec![hex_value(&hash.0[..])]).map(|transaction| !transaction.is_null()).unwrap_or(true)
+    }
+
+    /// Waits until one of the transactions, which share a nonce, is mined
+    /// and confirmed. Returns `None` if they were dropped, or if none was
+    /// mined in time.
+    fn await_inclusion(&self, hashes: &[H256]) -> Option<(H256, Value)> {
+        let wait_start = Instant::now();
+        let mut included = None;
+        while included.is_none() && wait_start.elapsed() < self.policy.resend_after {
+            for hash in hashes {
+                if let Some(receipt) = self.receipt(*hash) {
+                    let block = parse_quantity(&receipt["blockNumber"]).expect("Block number should be a quantity!").low_u64();
+                    // A reorganization can remove the transaction from its
+                    // block again, so its receipt is queried once more.
+                    if block_number(&self.web3) + 1 >= block + self.policy.confirmations {
+                        included = self.receipt(*hash).map(|receipt| (*hash, receipt));
+                    }
+                }
+            }
+            if included.is_none() {
+                if !hashes.iter().any(|hash| self.is_known(*hash)) {
+                    break;
+                }
+                thread::sleep(self.policy.poll_interval);
+            }
+        }
+        if PERFORM_MEASUREMENTS {
+            let duration = MDuration::from_std(wait_start.elapsed());
+            if duration.is_ok() {
+                unsafe {
+                    FILTER_OVERHEAD_MS += duration.unwrap().num_milliseconds();
+                }
+            } else {
+                println!("Error in time measurement: Overflow in duration");
+            }
+        }
+        included
+    }
+
+    /// Executes the transaction again with `eth_call` on the state before
+    /// the block it was mined in. Returns the reason the contract gave, if
+    /// any, as the error if it reverts.
+    fn replay(&self, to: Option<Address>, data: &[u8], gas: U256, block: U256) -> Result<(), Option<String>> {
+        let mut call = self.call_object(to, data);
+        call["gas"] = quantity(gas);
+        match rpc(&self.web3, "eth_call", vec![call, quantity(block - U256::one())]) {
+            Ok(Value::String(ref output)) => match revert_reason(output) {
+                Some(reason) => Err(Some(reason)),
+                None => Ok(())
+            },
+            Ok(_) => Ok(()),
+            Err(e) => Err(revert_reason(&e))
+        }
+    }
+
+    /// Sends the transaction and waits until it is confirmed.
+    pub fn submit(&self, to: Option<Address>, data: Vec<u8>) -> Result<CallReceipt, TransactionError> {
+        let gas = try!(self.gas_limit(to, &data));
+        let mut gas_price = try!(self.gas_price());
+        let mut nonce = None;
+        let mut sent: Vec<H256> = vec![];
+        let mut attempts = 0;
+        loop {
+            attempts += 1;
+            match try!(self.send(to, &data, gas, gas_price, nonce)) {
+                Sent::Accepted(hash, used_nonce) | Sent::Known(Some((hash, used_nonce)), _) => {
+                    nonce = Some(used_nonce);
+                    if !sent.contains(&hash) {
+                        sent.push(hash);
+                    }
+                },
+                // The node only knows a transaction it signed if it was
+                // sent before, so one of the sent ones is awaited.
+                Sent::Known(None, ref e) => {
+                    if sent.is_empty() {
+                        return Err(TransactionError::Rejected(e.clone()));
+                    }
+                    println!("The node knows the transaction already, waiting for it...");
+                },
+                Sent::Refused(ref e) if is_retryable(e) => println!("The node did not accept the transaction: {}", e),
+                Sent::Refused(ref e) if is_nonce_used(e) => {
+                    if !sent.iter().any(|hash| self.receipt(*hash).is_some()) {
+                        return Err(TransactionError::Rejected(e.clone()));
+                    }
+                    println!("A transaction sent before was mined, waiting for its confirmations...");
+                },
+                Sent::Refused(e) => return Err(TransactionError::Rejected(e))
+            }
+
+            if let Some((hash, receipt)) = self.await_inclusion(&sent) {
+                let block = parse_quantity(&receipt["blockNumber"]).expect("Block number should be a quantity!");
+                let gas_used = parse_quantity(&receipt["gasUsed"]).expect("Gas used should be a quantity!");
+                // Receipts before Byzantium have no status. A transaction that
+                // used all of its gas may have reverted, which is confirmed by
+                // replaying it.
+                let reverted = match parse_quantity(&receipt["status"]) {
+                    Some(status) if status.is_zero() => Some(self.replay(to, &data, gas, block).err().unwrap_or(None)),
+                    Some(_) => None,
+                    None if gas_used == gas => self.replay(to, &data, gas, block).err(),
+                    None => None
+                };
+                if let Some(reason) = reverted {
+                    return Err(TransactionError::Reverted(reason));
+                }
+                return Ok(CallReceipt {
+                    transaction_hash: hash,
+                    block_number: block.low_u64(),
+                    gas_used: gas_used,
+                    contract_address: receipt["contractAddress"].as_str().and_then(|address| address[2..].parse().ok())
+                });
+            }
+            if attempts > self.policy.retries {
+                return Err(TransactionError::NotMined(attempts));
+            }
+            gas_price = gas_price * U256::from(self.policy.price_bump) / U256::from(100);
+            println!("The transaction was not mined, sending it again with a gas price of {} wei...", gas_price);
+        }
+    }
+}
+
+#[test]
+fn parse_policy() {
+    assert_eq!(GasLimit::parse("150%"), Ok(GasLimit::Estimate(150)));
+    assert_eq!(GasLimit::parse("4000000"), Ok(GasLimit::Fixed(4000000)));
+    assert!(GasLimit::parse("much").is_err());
+    assert_eq!(GasPrice::parse("120%"), Ok(GasPrice::Node(120)));
+    assert_eq!(GasPrice::parse("20000000000"), Ok(GasPrice::Fixed(20000000000)));
+    assert!(GasPrice::parse("%").is_err());
+}
+
+#[test]
+fn revert_reasons() {
+    let data = ethabi::encode(&[Token::String("It is not your turn".into())]);
+    let error = format!("RPC error: Error {{ code: ServerError(3), message: \"execution reverted\", data: Some(String(\"0x{}{}\")) }}", REVERT_SELECTOR, hex::encode(&data));
+    assert_eq!(revert_reason(&error), Some("It is not your turn".into()));
+    assert_eq!(revert_reason("VM Exception while processing transaction: revert It is not your turn"), Some("It is not your turn".into()));
+    assert_eq!(revert_reason("VM Exception while processing transaction: revert"), None);
+    assert_eq!(revert_reason("insufficient funds for gas * price + value"), None);
+
+    assert!(is_retryable("replacement transaction underpriced"));
+    assert!(!is_retryable("already known"));
+    assert!(is_known_transaction("already known"));
+    assert!(is_known_transaction("Known transaction: 4d6a"));
+    assert!(!is_retryable("Nonce too low"));
+    assert!(is_revert("execution reverted: It is not your turn"));
+    assert!(is_revert("gas required exceeds allowance or always failing transaction"));
+    assert!(!is_revert("connection refused"));
+    assert!(is_nonce_used("Nonce too low"));
+    assert!(!is_retryable("insufficient funds for gas * price + value"));
+}
+
+/// A node for the tests of the submission, which answers the transactions
+/// as it is told to and mines one of them.
+#[cfg(test)]
+#[derive(Clone, Debug, Default)]
+struct MockNode {
+    state: Rc<::std::cell::RefCell<MockState>>
+}
+
+#[cfg(test)]
+#[derive(Debug, Default)]
+struct MockState {
+    /// The errors of the transactions sent, in order, `None` if a
+    /// transaction is accepted.
+    send_errors: Vec<Option<String>>,
+    /// The gas prices of the accepted transactions, whose hashes are their
+    /// indices plus one.
+    sent: Vec<(U256, U256)>,
+    attempts: usize,
+    /// The accepted transaction that is mined once the number of attempts
+    /// is reached.
+    mined: Option<(usize, usize)>,
+    /// The status of the receipt, none before Byzantium.
+    status: Option<u64>,
+    used_all_gas: bool,
+    /// The error of `eth_call`, if the transaction reverts in it.
+    call_error: Option<String>,
+    /// The error of `eth_estimateGas`.
+    estimate_error: Option<String>
+}
+
+#[cfg(test)]
+impl Transport for MockNode {
+    type Out = ::web3::futures::future::FutureResult<Value, ::web3::Error>;
+
+    fn prepare(&self, method: &str, params: Vec<Value>) -> (::web3::RequestId, ::web3::rpc::Call) {
+        (0, ::web3::helpers::build_request(0, method, params))
+    }
+
+    fn send(&self, _: ::web3::RequestId, _: ::web3::rpc::Call) -> Self::Out {
+        unreachable!("The node is only called with execute")
+    }
+
+    fn execute(&self, method: &str, params: Vec<Value>) -> Self::Out {
+        let mut state = self.state.borrow_mut();
+        let transaction_index = |params: &[Value]| params[0].as_str()
+            .and_then(|hash| hash[2..].parse::<H256>().ok())
+            .map(|hash| U256::from(&hash.0[..]).low_u64() as usize - 1);
+        let result = match method {
+            "eth_estimateGas" => match state.estimate_error {
+                Some(ref error) => Err(error.clone()),
+                None => Ok(quantity(U256::from(21000)))
+            },
+            "eth_gasPrice" => Ok(quantity(U256::from(100))),
+            "eth_getTransactionCount" => Ok(quantity(U256::from(7))),
+            "eth_blockNumber" => Ok(quantity(U256::from(5))),
+            "eth_sendTransaction" => {
+                let error = state.send_errors.get(state.attempts).cloned().unwrap_or(None);
+                state.attempts += 1;
+                match error {
+                    Some(error) => Err(error),
+                    None => {
+                        let gas = parse_quantity(&params[0]["gas"]).unwrap();
+                        let gas_price = parse_quantity(&params[0]["gasPrice"]).unwrap();
+                        state.sent.push((gas, gas_price));
+                        Ok(hex_value(&H256::from(state.sent.len() as u64).0[..]))
+                    }
+                }
+            },
+            "eth_getTransactionByHash" => Ok(transaction_index(&params).map(|_| Value::Object(Default::default())).unwrap_or(Value::Null)),
+            "eth_getTransactionReceipt" => Ok(match (state.mined, transaction_index(&params)) {
+                (Some((mined, attempts)), Some(index)) if mined == index && state.attempts >= attempts => {
+                    let gas = state.sent[index].0;
+                    let mut receipt = Value::Object(Default::default());
+                    receipt["blockNumber"] = quantity(U256::from(5));
+                    receipt["gasUsed"] = quantity(if state.used_all_gas { gas } else { gas / U256::from(2) });
+                    if let Some(status) = state.status {
+                        receipt["status"] = quantity(U256::from(status));
+                    }
+                    receipt
+                },
+                _ => Value::Null
+            }),
+            "eth_call" => match state.call_error {
+                Some(ref error) => Err(error.clone()),
+                None => Ok(Value::String("0x".into()))
+            },
+            _ => panic!("Unexpected call of {:?}", method)
+        };
+        ::web3::futures::future::result(result.map_err(|e| ::web3::ErrorKind::Transport(e).into()))
+    }
+}
+
+#[cfg(test)]
+fn submit_to(state: MockState) -> (Result<CallReceipt, TransactionError>, Vec<(U256, U256)>) {
+    let node = MockNode {
+        state: Rc::new(::std::cell::RefCell::new(state))
+    };
+    let policy = TransactionPolicy {
+        retries: 2,
+        resend_after: Duration::from_millis(50),
+        poll_interval: Duration::from_millis(1),
+        ..TransactionPolicy::default()
+    };
+    let submitter = Submitter::new(Web3::new(node.clone()), Address::zero(), None, policy);
+    let result = submitter.submit(Some(Address::zero()), vec![1, 2, 3]);
+    let sent = node.state.borrow().sent.clone();
+    (result, sent)
+}
+
+#[test]
+fn submit_and_retry() {
+    // The first transaction is not mined, so it is sent again with a higher
+    // gas price.
+    let (result, sent) = submit_to(MockState {
+        mined: Some((1, 2)),
+        status: Some(1),
+        ..MockState::default()
+    });
+    let receipt = result.unwrap();
+    assert_eq!(receipt.transaction_hash, H256::from(2));
+    assert_eq!(sent.iter().map(|&(gas, price)| (gas.low_u64(), price.low_u64())).collect::<Vec<_>>(), vec![(31500, 100), (31500, 125)]);
+
+    // A transaction that is never mined fails after the retries.
+    let (result, sent) = submit_to(MockState::default());
+    assert_eq!(result.unwrap_err(), TransactionError::NotMined(3));
+    assert_eq!(sent.len(), 3);
+
+    // The nonce was taken by the first transaction, which was mined while
+    // the second one was sent.
+    let (result, _) = submit_to(MockState {
+        send_errors: vec![None, Some("nonce too low".into())],
+        mined: Some((0, 2)),
+        status: Some(1),
+        ..MockState::default()
+    });
+    assert_eq!(result.unwrap().transaction_hash, H256::from(1));
+
+    // The nonce was taken by a transaction sent by someone else.
+    let (result, sent) = submit_to(MockState {
+        send_errors: vec![Some("nonce too low".into())],
+        ..MockState::default()
+    });
+    match result {
+        Err(TransactionError::Rejected(ref error)) => assert!(is_nonce_used(error)),
+        _ => panic!("The transaction should be rejected")
+    }
+    assert!(sent.is_empty());
+
+    // The node knows the transaction sent again, so the one sent before is
+    // awaited.
+    let (result, sent) = submit_to(MockState {
+        send_errors: vec![None, Some("already known".into())],
+        mined: Some((0, 2)),
+        status: Some(1),
+        ..MockState::default()
+    });
+    assert_eq!(result.unwrap().transaction_hash, H256::from(1));
+    assert_eq!(sent.len(), 1);
+
+    // A known transaction that was not sent by the player cannot be awaited.
+    let (result, _) = submit_to(MockState {
+        send_errors: vec![Some("already known".into())],
+        ..MockState::default()
+    });
+    match result {
+        Err(TransactionError::Rejected(ref error)) => assert!(is_known_transaction(error)),
+        _ => panic!("The transaction should be rejected")
+    }
+}
+
+#[test]
+fn submit_reverted() {
+    let revert = "VM Exception while processing transaction: revert It is not your turn";
+    let (result, _) = submit_to(MockState {
+        mined: Some((0, 1)),
+        status: Some(0),
+        call_error: Some(revert.into()),
+        ..MockState::default()
+    });
+    assert_eq!(result.unwrap_err(), TransactionError::Reverted(Some("It is not your turn".into())));
+
+    // Before Byzantium, a transaction that used all of its gas is only a
+    // revert if it reverts when it is replayed.
+    let (result, _) = submit_to(MockState {
+        mined: Some((0, 1)),
+        used_all_gas: true,
+        call_error: Some(revert.into()),
+        ..MockState::default()
+    });
+    assert_eq!(result.unwrap_err(), TransactionError::Reverted(Some("It is not your turn".into())));
+    let (result, _) = submit_to(MockState {
+        mined: Some((0, 1)),
+        used_all_gas: true,
+        ..MockState::default()
+    });
+    assert!(result.is_ok());
+
+    // Only a revert in the estimate is reported as one, not a node that
+    // cannot be reached.
+    let (result, sent) = submit_to(MockState {
+        estimate_error: Some(revert.into()),
+        ..MockState::default()
+    });
+    assert_eq!(result.unwrap_err(), TransactionError::Reverted(Some("It is not your turn".into())));
+    assert!(sent.is_empty());
+    let (result, _) = submit_to(MockState {
+        estimate_error: Some("connection refused".into()),
+        ..MockState::default()
+    });
+    match result {
+        Err(TransactionError::Node(ref error)) => assert!(error.contains("connection refused")),
+        _ => panic!("The node should have failed")
+    }
+}
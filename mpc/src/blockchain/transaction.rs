@@ -0,0 +1,209 @@
+//! Transactions signed locally with the key of the player and sent with
+//! `eth_sendRawTransaction`, so the node never holds the key. Transactions
+//! are signed for the chain id of the node as in EIP-155, so they cannot be
+//! replayed on another chain.
+
+use protocol::Protected;
+use secp256k1::Secp256k1;
+use secp256k1::key::{PublicKey, SecretKey};
+use sha3::{Digest, Keccak256};
+use web3::futures::Future;
+use web3::types::{Address, BlockNumber, Bytes, H256, U256};
+use web3::{Transport, Web3};
+
+use std::cell::Cell;
+use std::cmp::max;
+
+use super::{chain_id, public_key_address, sign_hash, signed_message_hash};
+
+/// Appends the RLP encoding of a string of bytes.
+fn rlp_bytes(out: &mut Vec<u8>, bytes: &[u8]) {
+    if bytes.len() == 1 && bytes[0] < 0x80 {
+        out.push(bytes[0]);
+    } else {
+        rlp_length(out, bytes.len(), 0x80);
+        out.extend_from_slice(bytes);
+    }
+}
+
+/// Appends the prefix of a string or list with a payload of the length.
+fn rlp_length(out: &mut Vec<u8>, length: usize, offset: u8) {
+    if length < 56 {
+        out.push(offset + length as u8);
+    } else {
+        let be = be_bytes(U256::from(length));
+        out.push(offset + 55 + be.len() as u8);
+        out.extend_from_slice(&be);
+    }
+}
+
+/// The RLP encoding of a list of encoded items.
+fn rlp_list(items: &[Vec<u8>]) -> Vec<u8> {
+    let payload = items.concat();
+    let mut out = vec![];
+    rlp_length(&mut out, payload.len(), 0xc0);
+    out.extend_from_slice(&payload);
+    out
+}
+
+/// The big endian bytes of the integer without leading zeros, as integers
+/// are encoded in RLP.
+fn be_bytes(value: U256) -> Vec<u8> {
+    let mut bytes = [0u8; 32];
+    value.to_big_endian(&mut bytes);
+    let start = bytes.iter().position(|b| *b != 0).unwrap_or(32);
+    bytes[start..].to_vec()
+}
+
+fn rlp_uint(value: U256) -> Vec<u8> {
+    let mut out = vec![];
+    rlp_bytes(&mut out, &be_bytes(value));
+    out
+}
+
+fn rlp_data(bytes: &[u8]) -> Vec<u8> {
+    let mut out = vec![];
+    rlp_bytes(&mut out, bytes);
+    out
+}
+
+#[derive(Clone, Debug)]
+pub struct Transaction {
+    pub nonce: U256,
+    pub gas_price: U256,
+    pub gas: U256,
+    /// `None` deploys a contract.
+    pub to: Option<Address>,
+    pub value: U256,
+    pub data: Vec<u8>
+}
+
+impl Transaction {
+    fn fields(&self) -> Vec<Vec<u8>> {
+        vec![
+            rlp_uint(self.nonce),
+            rlp_uint(self.gas_price),
+            rlp_uint(self.gas),
+            rlp_data(self.to.as_ref().map_or(&[][..], |to| &to.0[..])),
+            rlp_uint(self.value),
+            rlp_data(&self.data)
+        ]
+    }
+
+    /// The hash that is signed for the chain as in EIP-155.
+    pub fn signing_hash(&self, chain_id: u64) -> [u8; 32] {
+        let mut fields = self.fields();
+        fields.push(rlp_uint(U256::from(chain_id)));
+        fields.push(rlp_uint(U256::zero()));
+        fields.push(rlp_uint(U256::zero()));
+        let mut hash = [0u8; 32];
+        hash.copy_from_slice(Keccak256::digest(&rlp_list(&fields)).as_slice());
+        hash
+    }
+
+    /// The signed transaction, as sent with `eth_sendRawTransaction`.
+    pub fn sign(&self, key: &[u8; 32], chain_id: u64) -> Vec<u8> {
+        let (recovery_id, signature) = sign_hash(key, &self.signing_hash(chain_id));
+        let mut fields = self.fields();
+        fields.push(rlp_uint(U256::from(recovery_id as u64 + 35 + chain_id * 2)));
+        fields.push(rlp_uint(U256::from(&signature[..32])));
+        fields.push(rlp_uint(U256::from(&signature[32..])));
+        rlp_list(&fields)
+    }
+}
+
+/// Signs the transactions and messages of a player with its own key.
+pub struct LocalSigner<T: Transport> {
+    web3: Web3<T>,
+    key: Protected<[u8; 32]>,
+    address: Address,
+    chain_id: u64,
+    /// The nonce after the last transaction sent.
+    nonce: Cell<U256>
+}
+
+impl<T: Transport> LocalSigner<T> {
+    pub fn new(web3: Web3<T>, key: Protected<[u8; 32]>) -> LocalSigner<T> {
+        let secp = Secp256k1::new();
+        let secret = SecretKey::from_slice(&secp, &key[..]).expect("\u{274c} The key is not a valid secp256k1 key!");
+        let address = public_key_address(&PublicKey::from_secret_key(&secp, &secret).expect("Public key should be derivable!"));
+        let chain_id = chain_id(&web3);
+        LocalSigner {
+            web3: web3,
+            key: key,
+            address: address,
+            chain_id: chain_id,
+            nonce: Cell::new(U256::zero())
+        }
+    }
+
+    pub fn address(&self) -> Address {
+        self.address
+    }
+
+    /// The nonce of the next transaction. The node may not know about the
+    /// transactions sent last yet, so the larger of its pending count and
+    /// the nonce after the last transaction is used.
+    pub fn next_nonce(&self) -> Result<U256, String> {
+        let pending = try!(self.web3.eth().transaction_count(self.address, Some(BlockNumber::Pending)).wait().map_err(|e| format!("{}", e)));
+        Ok(max(pending, self.nonce.get()))
+    }
+
+    /// Signs a transaction with the nonce. Returns the signed transaction
+    /// and its hash, which is known before it is sent.
+    pub fn sign_transaction(&self, to: Option<Address>, data: Vec<u8>, gas: U256, gas_price: U256, nonce: U256) -> (Vec<u8>, H256) {
+        let transaction = Transaction {
+            nonce: nonce,
+            gas_price: gas_price,
+            gas: gas,
+            to: to,
+            value: U256::zero(),
+            data: data
+        };
+        let raw = transaction.sign(&self.key, self.chain_id);
+        let hash = H256::from(Keccak256::digest(&raw).as_slice());
+        (raw, hash)
+    }
+
+    /// Sends a signed transaction with the nonce.
+    pub fn send_raw_transaction(&self, raw: Vec<u8>, nonce: U256) -> Result<H256, String> {
+        let hash = try!(self.web3.eth().send_raw_transaction(Bytes(raw)).wait().map_err(|e| format!("{}", e)));
+        self.nonce.set(max(self.nonce.get(), nonce + U256::one()));
+        Ok(hash)
+    }
+
+    /// Signs the message as `eth_sign` does. Returns the 65 byte signature
+    /// `r || s || v`.
+    pub fn sign_message(&self, message: &[u8]) -> Vec<u8> {
+        let (recovery_id, signature) = sign_hash(&self.key, &signed_message_hash(message));
+        let mut signed = signature.to_vec();
+        signed.push(recovery_id + 27);
+        signed
+    }
+}
+
+#[test]
+fn sign_transaction() {
+    // The example of EIP-155.
+    let transaction = Transaction {
+        nonce: U256::from(9),
+        gas_price: U256::from(20000000000u64),
+        gas: U256::from(21000),
+        to: Some(Address::from(&[0x35u8; 20][..])),
+        value: U256::from(1000000000000000000u64),
+        data: vec![]
+    };
+    assert_eq!(
+        ::hex::encode(&transaction.signing_hash(1)),
+        "daf5a779ae972f972197303d7b574746c7ef83eadac0f2791ad23db92e4c8e53"
+    );
+    assert_eq!(
+        ::hex::encode(&transaction.sign(&[0x46u8; 32], 1)),
+        "f86c098504a817c800825208943535353535353535353535353535353535353535880de0b6b3a76400008025a028ef61340bd939bc2195fe537567866003e1a15d3c71ff63e1590620aa636276a067cbe9d8997f761aecb703304b3800ccf555c9f3dc64214b297fb1966a3b6d83"
+    );
+
+    // Long strings and lists have a length prefix.
+    assert_eq!(&rlp_data(&[0xaa; 56])[..2], &[0xb8, 56]);
+    assert_eq!(rlp_data(&[0x7f]), vec![0x7f]);
+    assert_eq!(rlp_uint(U256::zero()), vec![0x80]);
+}
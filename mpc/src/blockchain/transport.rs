@@ -0,0 +1,192 @@
+//! The transports to the Ethereum node. The node is reached over HTTP, a
+//! WebSocket or IPC, depending on the endpoint given in the environment
+//! variable `DIST_MPC_WEB3`, e.g. `ws://localhost:8546` or
+//! `/home/user/.ethereum/geth.ipc`. Without it, HTTP on port 8545 of the
+//! host is used.
+//!
+//! Over HTTP, new logs are polled from a filter. Over a WebSocket or IPC the
+//! player subscribes to them with `eth_subscribe`, so the node pushes every
+//! log as soon as its block is imported.
+
+use web3::api::{BaseFilter, SubscriptionStream};
+use web3::futures::{Future, Stream};
+use web3::transports::{Http, Ipc, WebSocket};
+use web3::types::{Address, BlockNumber, Filter, FilterBuilder, Log, H256};
+use web3::{DuplexTransport, Transport, Web3};
+
+use std::env::var;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::Duration;
+
+use consts::WEB3_ENV_KEY;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Endpoint {
+    Http(String),
+    WebSocket(String),
+    Ipc(String)
+}
+
+impl Endpoint {
+    /// Parses an endpoint given as `http(s)://...`, `ws(s)://...` or as the
+    /// path of an IPC socket.
+    pub fn parse(s: &str) -> Endpoint {
+        if s.starts_with("ws://") || s.starts_with("wss://") {
+            Endpoint::WebSocket(s.to_string())
+        } else if s.starts_with("http://") || s.starts_with("https://") {
+            Endpoint::Http(s.to_string())
+        } else {
+            Endpoint::Ipc(s.to_string())
+        }
+    }
+}
+
+/// The endpoint from the environment, or HTTP on port 8545 of the host.
+pub fn web3_endpoint(host: &str) -> Endpoint {
+    match var(WEB3_ENV_KEY) {
+        Ok(endpoint) => {
+            println!("Using web3 endpoint from environment variable: {:?}", endpoint);
+            Endpoint::parse(&endpoint)
+        },
+        Err(_) => Endpoint::Http(format!("http://{}:8545", host))
+    }
+}
+
+/// Connects to the endpoint and evaluates the body with `$web3` bound to a
+/// `Web3` of the transport of the endpoint. The body is compiled once for
+/// every transport, so the code it calls is generic over the transport.
+macro_rules! with_web3 {
+    ($endpoint:expr, $web3:ident => $body:expr) => {
+        match $endpoint {
+            $crate::blockchain::Endpoint::Http(url) => {
+                let (_eloop, transport) = ::web3::transports::Http::new(&url).expect("Error connecting to web3 instance!");
+                let $web3 = ::web3::Web3::new(transport);
+                $body
+            },
+            $crate::blockchain::Endpoint::WebSocket(url) => {
+                let (_eloop, transport) = ::web3::transports::WebSocket::new(&url).expect("Error connecting to web3 instance!");
+                let $web3 = ::web3::Web3::new(transport);
+                $body
+            },
+            $crate::blockchain::Endpoint::Ipc(path) => {
+                let (_eloop, transport) = ::web3::transports::Ipc::new(&path).expect("Error connecting to web3 instance!");
+                let $web3 = ::web3::Web3::new(transport);
+                $body
+            }
+        }
+    }
+}
+
+/// The logs of a filter, in the order they arrive at the node.
+pub trait LogStream {
+    /// The logs that arrived since the last call, waiting at most `timeout`
+    /// for the first one.
+    fn next_logs(&mut self, timeout: &Duration) -> Vec<Log>;
+}
+
+/// A transport over which the logs of a contract can be watched.
+pub trait EventTransport: Transport + Sized + 'static {
+    /// The logs with the topic emitted by the contract from `from_block` on,
+    /// and a stream of the logs that arrive later. Logs can be both in the
+    /// past ones and in the stream.
+    fn watch_logs(web3: &Web3<Self>, address: Address, topic: H256, from_block: u64) -> (Vec<Log>, Box<LogStream>);
+}
+
+fn log_filter(address: Address, topic: H256, from_block: Option<u64>) -> Filter {
+    let builder = FilterBuilder::default()
+        .address(vec![address])
+        .topics(Some(vec![topic]), None, None, None);
+    match from_block {
+        Some(block) => builder.from_block(BlockNumber::Number(block)).build(),
+        None => builder.build()
+    }
+}
+
+struct PolledLogs<T: Transport> {
+    filter: BaseFilter<T, Log>
+}
+
+impl<T: Transport> LogStream for PolledLogs<T> {
+    fn next_logs(&mut self, timeout: &Duration) -> Vec<Log> {
+        let logs = self.filter.poll().wait().expect("Filter should return result!").expect("Polling result should be valid!");
+        if logs.is_empty() {
+            thread::sleep(*timeout);
+        }
+        logs
+    }
+}
+
+impl EventTransport for Http {
+    fn watch_logs(web3: &Web3<Http>, address: Address, topic: H256, from_block: u64) -> (Vec<Log>, Box<LogStream>) {
+        let filter = web3.eth_filter().create_logs_filter(log_filter(address, topic, Some(from_block))).wait().expect("Filter should be registerable!");
+        let past = filter.logs().wait().expect("Error replaying the logs of the filter!");
+        (past, Box::new(PolledLogs {
+            filter: filter
+        }))
+    }
+}
+
+struct SubscribedLogs {
+    logs: Receiver<Log>
+}
+
+impl LogStream for SubscribedLogs {
+    fn next_logs(&mut self, timeout: &Duration) -> Vec<Log> {
+        let mut logs = vec![];
+        match self.logs.recv_timeout(*timeout) {
+            Ok(log) => logs.push(log),
+            Err(RecvTimeoutError::Timeout) => return logs,
+            Err(RecvTimeoutError::Disconnected) => panic!("\u{274c} The node closed the subscription to the logs!")
+        }
+        logs.extend(self.logs.try_iter());
+        logs
+    }
+}
+
+/// Subscribes to the logs before fetching the past ones, so that no log is
+/// emitted in between. The stream of the subscription is read on its own
+/// thread, which passes the logs on as they arrive.
+fn subscribe_logs<T>(web3: &Web3<T>, address: Address, topic: H256, from_block: u64) -> (Vec<Log>, Box<LogStream>) where
+    T: DuplexTransport + 'static,
+    SubscriptionStream<T, Log>: Send + 'static
+{
+    let subscription = web3.eth_subscribe().subscribe_logs(log_filter(address, topic, None)).wait().expect("Error subscribing to the logs!");
+    let past = web3.eth().logs(&log_filter(address, topic, Some(from_block))).wait().expect("Error replaying the logs!");
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        for log in subscription.wait() {
+            match log {
+                Ok(log) => if sender.send(log).is_err() {
+                    break;
+                },
+                Err(e) => {
+                    println!("Error in the subscription to the logs: {:?}", e);
+                    break;
+                }
+            }
+        }
+    });
+    (past, Box::new(SubscribedLogs {
+        logs: receiver
+    }))
+}
+
+impl EventTransport for WebSocket {
+    fn watch_logs(web3: &Web3<WebSocket>, address: Address, topic: H256, from_block: u64) -> (Vec<Log>, Box<LogStream>) {
+        subscribe_logs(web3, address, topic, from_block)
+    }
+}
+
+impl EventTransport for Ipc {
+    fn watch_logs(web3: &Web3<Ipc>, address: Address, topic: H256, from_block: u64) -> (Vec<Log>, Box<LogStream>) {
+        subscribe_logs(web3, address, topic, from_block)
+    }
+}
+
+#[test]
+fn parse_endpoints() {
+    assert_eq!(Endpoint::parse("http://localhost:8545"), Endpoint::Http("http://localhost:8545".into()));
+    assert_eq!(Endpoint::parse("wss://node.example.org"), Endpoint::WebSocket("wss://node.example.org".into()));
+    assert_eq!(Endpoint::parse("/home/user/.ethereum/geth.ipc"), Endpoint::Ipc("/home/user/.ethereum/geth.ipc".into()));
+}
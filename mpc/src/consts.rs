@@ -1,6 +1,9 @@
 pub const PERFORM_MEASUREMENTS: bool = true;
 pub const HOST_ENV_KEY: &str = "DIST_MPC_HOST";
 pub const DEFAULT_HOST: &str = "localhost";
+pub const WEB3_ENV_KEY: &str = "DIST_MPC_WEB3";
+pub const KEYSTORE_PASSWORD_ENV_KEY: &str = "DIST_MPC_KEYSTORE_PASSWORD";
+pub const SECRETS_PASSWORD_ENV_KEY: &str = "DIST_MPC_SECRETS_PASSWORD";
 pub const THREADS: usize = 128;
 pub const NON_INTERACTIVE: bool = true;
 pub const BENCHMARK_ONLY_OUTPUT: bool = true;
@@ -0,0 +1,106 @@
+//! Signed contributions. For every stage a player transforms, it signs a
+//! statement naming the contract, the stage, the hashes of the stage it
+//! received and of the stage it published, and the hash of its public key
+//! with the key of its Ethereum account. The contribution is stored on IPFS
+//! next to the transformed stage, so the transcript stays attributable to
+//! the players when it is exported from the chain.
+
+use blockchain::{recover, sign, DistributedMpc};
+use hex;
+use protocol::{Digest256, Digest512, PublicKey};
+use rustc_serialize::Encodable;
+use web3::types::Address;
+use web3::{Transport, Web3};
+
+#[derive(Clone, PartialEq, RustcEncodable, RustcDecodable)]
+pub struct Statement {
+    pub contract: [u8; 20],
+    pub stage: u64,
+    pub previous: Digest512,
+    pub transformed: Digest512,
+    pub public_key: Digest256
+}
+
+#[derive(Clone, RustcEncodable, RustcDecodable)]
+pub struct Contribution {
+    pub statement: Statement,
+    pub signature: Vec<u8>
+}
+
+impl Statement {
+    /// The statement for transforming `previous` into `transformed` in the
+    /// stage with the index (0 for stage 1).
+    pub fn new<S: Encodable>(contract: Address, stage_index: u64, previous: &S, transformed: &S, pubkey: &PublicKey) -> Statement {
+        Statement {
+            contract: contract.0,
+            stage: stage_index + 1,
+            previous: Digest512::from(previous).expect("Stage should be serializable!"),
+            transformed: Digest512::from(transformed).expect("Stage should be serializable!"),
+            public_key: pubkey.hash()
+        }
+    }
+
+    /// The message that is signed, which is readable so that wallets can
+    /// show what is being signed.
+    pub fn message(&self) -> Vec<u8> {
+        format!(
+            "DistributedMPC contribution\ncontract: 0x{}\nstage: {}\nprevious: {}\ntransformed: {}\npublic key: {}",
+            hex::encode(&self.contract),
+            self.stage,
+            hex::encode(&self.previous.0[..]),
+            hex::encode(&self.transformed.0[..]),
+            self.public_key.to_string()
+        ).into_bytes()
+    }
+}
+
+impl Contribution {
+    /// Signs the statement with the key of the player's account.
+    pub fn sign<T: Transport>(web3: &Web3<T>, contract: &DistributedMpc<T>, statement: Statement) -> Contribution {
+        let signature = sign(web3, contract, &statement.message());
+        Contribution {
+            statement: statement,
+            signature: signature
+        }
+    }
+
+    /// The address that signed the contribution, if the signature is valid.
+    pub fn signer(&self) -> Option<Address> {
+        recover(&self.statement.message(), &self.signature)
+    }
+
+    /// Checks that the player signed this statement.
+    pub fn is_valid(&self, player: Address, expected: &Statement) -> bool {
+        self.statement == *expected && self.signer() == Some(player)
+    }
+}
+
+#[test]
+fn contribution_signature() {
+    use blockchain::{public_key_address, signed_message_hash};
+    use protocol::PrivateKey;
+    use secp256k1::{Message, Secp256k1};
+    use secp256k1::key::{PublicKey as EthPublicKey, SecretKey};
+
+    let rng = &mut ::rand::thread_rng();
+    let pubkey = PrivateKey::new(rng).pubkey(rng);
+    let contract = Address::from(&[1u8; 20][..]);
+    let statement = Statement::new(contract, 0, &"stage", &"transformed stage", &pubkey);
+
+    let secp = Secp256k1::new();
+    let secret = SecretKey::from_slice(&secp, &[0x42; 32]).unwrap();
+    let player = public_key_address(&EthPublicKey::from_secret_key(&secp, &secret).unwrap());
+    let hash = Message::from_slice(&signed_message_hash(&statement.message())).unwrap();
+    let (recovery_id, compact) = secp.sign_recoverable(&hash, &secret).unwrap().serialize_compact(&secp);
+    let mut signature = compact.to_vec();
+    signature.push(recovery_id.to_i32() as u8 + 27);
+
+    let contribution = Contribution {
+        statement: statement.clone(),
+        signature: signature
+    };
+    assert!(contribution.is_valid(player, &statement));
+    assert!(!contribution.is_valid(contract, &statement));
+    assert!(!contribution.is_valid(player, &Statement::new(contract, 1, &"stage", &"transformed stage", &pubkey)));
+    assert!(!contribution.is_valid(player, &Statement::new(contract, 0, &"stage", &"another stage", &pubkey)));
+}
@@ -0,0 +1,343 @@
+//! Entropy for the player's secrets. The seed of the random number generator
+//! is mixed from several sources: the generator of the operating system is
+//! always used, and the player can add sources of its own with `-entropy`,
+//! e.g. dice rolls or a hardware generator. The seed is unpredictable as long
+//! as one of the sources is.
+//!
+//! For every source, a digest of its input is printed and logged to
+//! `entropy.json`. The digest does not reveal the input, but a player who
+//! keeps the input can later prove which sources it used. Since inputs like
+//! dice rolls or a short text could be guessed by brute force, every source
+//! gets a random salt, which is kept locally in `entropy-salts.json` and only
+//! logged as its hash. The seed is derived from the inputs themselves:
+//!
+//! ```text
+//! digest = BLAKE2b-256(salt || input)
+//! salt hash = BLAKE2b-256(salt)
+//! seed = BLAKE2b-256(KDF_VERSION || for every source: name || input)
+//! ```
+//!
+//! where `KDF_VERSION`, every name and every input are prefixed with their
+//! length as a 64 bit little endian integer.
+
+use blake2_rfc::blake2b::{blake2b, Blake2b};
+use byteorder::{ByteOrder, LittleEndian};
+use clap::ArgMatches;
+use hex;
+use protocol::{zeroize_string, Protected};
+use rand::{OsRng, Rng};
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use serde_json;
+
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use std::process::Command;
+use std::time::Instant;
+
+use super::prompt;
+use consts::NON_INTERACTIVE;
+
+pub const ENTROPY_FILE: &str = "entropy.json";
+
+pub const SALTS_FILE: &str = "entropy-salts.json";
+
+const KDF_VERSION: &str = "DistributedMPC/entropy/v1";
+
+/// The number of bytes read from the generator of the operating system.
+const OS_BYTES: usize = 64;
+
+/// The number of bytes read at most from a file or device.
+const MAX_FILE_BYTES: u64 = 1 << 20;
+
+/// The number of dice rolls needed for 256 bits of entropy.
+const DICE_ROLLS: usize = 100;
+
+/// The number of bytes of the salt of a digest.
+const SALT_BYTES: usize = 32;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Source {
+    Os,
+    Keyboard,
+    Dice,
+    File(String),
+    Command(String)
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SourceDigest {
+    pub source: String,
+    pub bytes: usize,
+    pub digest: String,
+    pub salt_hash: String
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SourceSalt {
+    pub source: String,
+    pub salt: String
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct EntropyRecord {
+    pub kdf: String,
+    pub sources: Vec<SourceDigest>
+}
+
+impl Source {
+    /// Parses a source given as `os`, `keyboard`, `dice`, `file:<path>` or
+    /// `command:<command>`.
+    pub fn parse(s: &str) -> Result<Source, String> {
+        match s {
+            "os" => return Ok(Source::Os),
+            "keyboard" => return Ok(Source::Keyboard),
+            "dice" => return Ok(Source::Dice),
+            _ => {}
+        }
+        if s.starts_with("file:") && s.len() > 5 {
+            Ok(Source::File(s[5..].to_string()))
+        } else if s.starts_with("command:") && s.len() > 8 {
+            Ok(Source::Command(s[8..].to_string()))
+        } else {
+            Err(format!("Unknown entropy source {:?}, expected os, keyboard, dice, file:<path> or command:<command>", s))
+        }
+    }
+
+    pub fn name(&self) -> String {
+        match *self {
+            Source::Os => "os".into(),
+            Source::Keyboard => "keyboard".into(),
+            Source::Dice => "dice".into(),
+            Source::File(ref path) => format!("file:{}", path),
+            Source::Command(ref command) => format!("command:{}", command)
+        }
+    }
+
+    pub fn read(&self) -> Vec<u8> {
+        match *self {
+            Source::Os => {
+                let mut rng = OsRng::new().expect("Error opening the generator of the operating system!");
+                let mut bytes = vec![0; OS_BYTES];
+                rng.fill_bytes(&mut bytes);
+                bytes
+            },
+            Source::Keyboard => read_keyboard(),
+            Source::Dice => read_dice(),
+            Source::File(ref path) => {
+                let file = File::open(path).unwrap_or_else(|e| panic!("\u{274c} Error opening entropy source {:?}: {}", path, e));
+                let mut bytes = vec![];
+                file.take(MAX_FILE_BYTES).read_to_end(&mut bytes).unwrap_or_else(|e| panic!("\u{274c} Error reading entropy source {:?}: {}", path, e));
+                if bytes.is_empty() {
+                    panic!("\u{274c} The entropy source {:?} is empty!", path);
+                }
+                bytes
+            },
+            Source::Command(ref command) => {
+                let output = Command::new("sh").arg("-c").arg(command).output()
+                    .unwrap_or_else(|e| panic!("\u{274c} Error running entropy source {:?}: {}", command, e));
+                if !output.status.success() || output.stdout.is_empty() {
+                    panic!("\u{274c} The entropy source {:?} failed or had no output!", command);
+                }
+                output.stdout
+            }
+        }
+    }
+}
+
+/// Lines of text typed by the player, together with the time at which each
+/// line was entered.
+fn read_keyboard() -> Vec<u8> {
+    let start = Instant::now();
+    let mut bytes = vec![];
+    let mut text = prompt("Please type a random string of text and then press [ENTER] to provide additional entropy.");
+    loop {
+        let elapsed = start.elapsed();
+        let mut time = [0; 12];
+        LittleEndian::write_u64(&mut time[..8], elapsed.as_secs());
+        LittleEndian::write_u32(&mut time[8..], elapsed.subsec_nanos());
+        bytes.extend_from_slice(&time);
+        bytes.extend_from_slice(text.as_bytes());
+        if text.is_empty() {
+            return bytes;
+        }
+        zeroize_string(&mut text);
+        text = prompt("Type another line, or press [ENTER] on an empty line to finish.");
+    }
+}
+
+fn parse_dice(rolls: &str) -> Option<Vec<u8>> {
+    let rolls: Option<Vec<u8>> = rolls.chars().filter(|c| !c.is_whitespace()).map(|c| match c {
+        '1'...'6' => Some(c as u8 - b'0'),
+        _ => None
+    }).collect();
+    rolls.and_then(|rolls| if rolls.len() < DICE_ROLLS { None } else { Some(rolls) })
+}
+
+fn read_dice() -> Vec<u8> {
+    loop {
+        let mut text = prompt(&format!("Please roll a die at least {} times and type the results (1-6), then press [ENTER].", DICE_ROLLS));
+        let rolls = parse_dice(&text);
+        zeroize_string(&mut text);
+        if let Some(rolls) = rolls {
+            return rolls;
+        }
+        if NON_INTERACTIVE {
+            panic!("\u{274c} Dice rolls cannot be entered in non-interactive mode!");
+        }
+            println!("\u{274c} That is not a sequence of at least {} dice rolls.", DICE_ROLLS);
+    }
+}
+
+fn write_length(hasher: &mut Blake2b, bytes: &[u8]) {
+    let mut length = [0; 8];
+    LittleEndian::write_u64(&mut length, bytes.len() as u64);
+    hasher.update(&length);
+    hasher.update(bytes);
+}
+
+/// Derives the seed from the inputs of the sources, see the module
+/// documentation.
+pub fn derive_seed(inputs: &[(String, Vec<u8>)]) -> Protected<[u32; 8]> {
+    let mut hasher = Blake2b::new(32);
+    write_length(&mut hasher, KDF_VERSION.as_bytes());
+    for &(ref name, ref input) in inputs {
+        write_length(&mut hasher, name.as_bytes());
+        write_length(&mut hasher, input);
+    }
+    let hash = hasher.finalize();
+
+    let mut seed = Protected::new([0u32; 8]);
+    for i in 0..8 {
+        seed[i] = LittleEndian::read_u32(&hash.as_bytes()[(i*4)..]);
+    }
+    seed
+}
+
+/// The digest of the input of a source under the salt, see the module
+/// documentation.
+pub fn source_digest(name: &str, salt: &[u8], input: &[u8]) -> SourceDigest {
+    let mut hasher = Blake2b::new(32);
+    hasher.update(salt);
+    hasher.update(input);
+    SourceDigest {
+        source: name.to_string(),
+        bytes: input.len(),
+        digest: hex::encode(hasher.finalize().as_bytes()),
+        salt_hash: hex::encode(blake2b(32, &[], salt).as_bytes())
+    }
+}
+
+fn random_salt() -> Vec<u8> {
+    let mut rng = OsRng::new().expect("Error opening the generator of the operating system!");
+    let mut salt = vec![0; SALT_BYTES];
+    rng.fill_bytes(&mut salt);
+    salt
+}
+
+/// The sources given with `-entropy`. The generator of the operating system
+/// is always used, and the keyboard if no other source is given.
+pub fn sources(matches: &ArgMatches) -> Vec<Source> {
+    let mut sources = vec![Source::Os];
+    match matches.values_of("entropy") {
+        Some(values) => {
+            for value in values {
+                let source = Source::parse(value).unwrap_or_else(|e| panic!("\u{274c} {}", e));
+                if !sources.contains(&source) {
+                    sources.push(source);
+                }
+            }
+        },
+        None => sources.push(Source::Keyboard)
+    }
+    sources
+}
+
+fn append_record<R: Serialize + DeserializeOwned>(path: &str, record: R) {
+    let mut records: Vec<R> = vec![];
+    if Path::new(path).is_file() {
+        let file = File::open(path).unwrap_or_else(|e| panic!("\u{274c} Error opening {:?}: {}", path, e));
+        records = serde_json::from_reader(file).unwrap_or_else(|e| panic!("\u{274c} Error parsing {:?}: {}", path, e));
+    }
+    records.push(record);
+    let file = File::create(path).unwrap_or_else(|e| panic!("\u{274c} Error creating {:?}: {}", path, e));
+    serde_json::to_writer_pretty(file, &records).unwrap_or_else(|e| panic!("\u{274c} Error writing {:?}: {}", path, e));
+}
+
+/// Reads all sources, logs their digests and derives the seed.
+pub fn get_entropy(matches: &ArgMatches) -> Protected<[u32; 8]> {
+    let mut inputs = vec![];
+    let mut digests = vec![];
+    let mut salts = vec![];
+    for source in sources(matches) {
+        let name = source.name();
+            println!("Reading entropy from {}...", name);
+        let input = source.read();
+        let salt = random_salt();
+        let digest = source_digest(&name, &salt, &input);
+            println!("\u{2714} {}: {} bytes, digest {}", name, digest.bytes, digest.digest);
+        digests.push(digest);
+        salts.push(SourceSalt {
+            source: name.clone(),
+            salt: hex::encode(&salt)
+        });
+        inputs.push((name, input));
+    }
+
+    let seed = derive_seed(&inputs);
+    for &mut (_, ref mut input) in inputs.iter_mut() {
+        for byte in input.iter_mut() {
+            *byte = 0;
+        }
+    }
+    append_record(ENTROPY_FILE, EntropyRecord {
+        kdf: KDF_VERSION.into(),
+        sources: digests
+    });
+    append_record(SALTS_FILE, salts);
+        println!("\u{2714} Logged the digests of the entropy sources to {:?}, keep their salts in {:?} private.", ENTROPY_FILE, SALTS_FILE);
+    seed
+}
+
+#[test]
+fn seed_derivation() {
+    let inputs = vec![("os".to_string(), vec![1u8; 64]), ("dice".to_string(), vec![6u8; 100])];
+    let seed = derive_seed(&inputs);
+    assert_eq!(*seed, *derive_seed(&inputs));
+
+    // Every input, its source and their order change the seed.
+    let mut changed = inputs.clone();
+    changed[1].1[0] = 5;
+    assert!(*seed != *derive_seed(&changed));
+    let mut renamed = inputs.clone();
+    renamed[1].0 = "keyboard".into();
+    assert!(*seed != *derive_seed(&renamed));
+    let reversed: Vec<_> = inputs.iter().rev().cloned().collect();
+    assert!(*seed != *derive_seed(&reversed));
+    // Inputs cannot be shifted between sources.
+    let shifted = vec![("os".to_string(), vec![1u8; 63]), ("dice".to_string(), [vec![1u8], vec![6u8; 100]].concat())];
+    assert!(*seed != *derive_seed(&shifted));
+
+    // The digest does not depend on the other sources, but on the salt.
+    let salt = [7u8; SALT_BYTES];
+    let digest = source_digest("dice", &salt, &inputs[1].1);
+    assert_eq!(digest.digest, hex::encode(blake2b(32, &[], &[vec![7u8; SALT_BYTES], vec![6u8; 100]].concat()).as_bytes()));
+    assert_eq!(digest.salt_hash, hex::encode(blake2b(32, &[], &salt).as_bytes()));
+    assert!(digest.digest != source_digest("dice", &[8u8; SALT_BYTES], &inputs[1].1).digest);
+    assert!(random_salt() != random_salt());
+}
+
+#[test]
+fn entropy_sources() {
+    assert_eq!(Source::parse("dice"), Ok(Source::Dice));
+    assert_eq!(Source::parse("file:/dev/hwrng"), Ok(Source::File("/dev/hwrng".into())));
+    assert_eq!(Source::parse("command:head -c 32 /dev/urandom"), Ok(Source::Command("head -c 32 /dev/urandom".into())));
+    assert!(Source::parse("file:").is_err());
+    assert!(Source::parse("mouse").is_err());
+    assert_eq!(Source::parse("file:/dev/hwrng").unwrap().name(), "file:/dev/hwrng");
+
+    assert_eq!(parse_dice(&"123456 ".repeat(17)).unwrap().len(), 102);
+    assert!(parse_dice(&"1".repeat(DICE_ROLLS - 1)).is_none());
+    assert!(parse_dice(&"7".repeat(DICE_ROLLS)).is_none());
+}
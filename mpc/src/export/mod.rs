@@ -0,0 +1,2 @@
+pub mod solidity;
+pub mod zokrates;
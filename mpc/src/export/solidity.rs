@@ -0,0 +1,222 @@
+use bn::*;
+use hex;
+use protocol::{VerifyingKey, Proof};
+use protocol::affine::{g1_to_be_bytes, g2_to_be_bytes, fr_to_be_bytes};
+
+const TEMPLATE: &str = include_str!("verifier.sol.template");
+
+// The precompiled contracts encode the point at infinity as (0, 0), and
+// elements of Fq2 with the imaginary part first.
+
+fn uint(bytes: &[u8; 32]) -> String {
+    format!("0x{}", hex::encode(bytes))
+}
+
+fn g1_coordinates(p: &G1) -> (String, String) {
+    match g1_to_be_bytes(p) {
+        Some((x, y)) => (uint(&x), uint(&y)),
+        None => (uint(&[0; 32]), uint(&[0; 32]))
+    }
+}
+
+fn g2_coordinates(p: &G2) -> ((String, String), (String, String)) {
+    match g2_to_be_bytes(p) {
+        Some((x, y)) => (
+            (uint(&x[1]), uint(&x[0])),
+            (uint(&y[1]), uint(&y[0]))
+        ),
+        None => (
+            (uint(&[0; 32]), uint(&[0; 32])),
+            (uint(&[0; 32]), uint(&[0; 32]))
+        )
+    }
+}
+
+fn g1_point(p: &G1) -> String {
+    let (x, y) = g1_coordinates(p);
+    format!("Pairing.G1Point({}, {})", x, y)
+}
+
+fn g2_point(p: &G2) -> String {
+    let ((x1, x0), (y1, y0)) = g2_coordinates(p);
+    format!("Pairing.G2Point([{}, {}], [{}, {}])", x1, x0, y1, y0)
+}
+
+/// Generates a Solidity contract which verifies proofs against the
+/// verification key.
+pub fn verifier_contract(vk: &VerifyingKey) -> String {
+    assert!(vk.num_inputs() > 0, "The verifier contract requires at least one public input!");
+
+    let mut lines = vec![
+        format!("vk.A = {};", g2_point(&vk.a)),
+        format!("vk.B = {};", g1_point(&vk.b)),
+        format!("vk.C = {};", g2_point(&vk.c)),
+        format!("vk.gamma = {};", g2_point(&vk.gamma)),
+        format!("vk.gammaBeta1 = {};", g1_point(&vk.gamma_beta_1)),
+        format!("vk.gammaBeta2 = {};", g2_point(&vk.gamma_beta_2)),
+        format!("vk.Z = {};", g2_point(&vk.z)),
+        format!("vk.IC = new Pairing.G1Point[]({});", vk.ic.len())
+    ];
+    for (i, ic) in vk.ic.iter().enumerate() {
+        lines.push(format!("vk.IC[{}] = {};", i, g1_point(ic)));
+    }
+
+    let vk_lines: Vec<String> = lines.into_iter().map(|l| format!("        {}", l)).collect();
+
+    TEMPLATE
+        .replace("<%vk%>", &vk_lines.join("\n"))
+        .replace("<%input_length%>", &format!("{}", vk.num_inputs()))
+}
+
+/// The arguments of `verifyTx` for the proof and public inputs, as a JSON
+/// array that can be passed to web3 or Remix.
+pub fn verifier_arguments(proof: &Proof, inputs: &[Fr]) -> String {
+    fn g1(p: &G1) -> String {
+        let (x, y) = g1_coordinates(p);
+        format!("[\"{}\",\"{}\"]", x, y)
+    }
+
+    let ((b_x1, b_x0), (b_y1, b_y0)) = g2_coordinates(&proof.b);
+    let inputs: Vec<String> = inputs.iter().map(|i| format!("\"{}\"", uint(&fr_to_be_bytes(i)))).collect();
+
+    format!(
+        "[{},{},[[\"{}\",\"{}\"],[\"{}\",\"{}\"]],{},{},{},{},{},[{}]]",
+        g1(&proof.a),
+        g1(&proof.a_p),
+        b_x1, b_x0, b_y1, b_y0,
+        g1(&proof.b_p),
+        g1(&proof.c),
+        g1(&proof.c_p),
+        g1(&proof.h),
+        g1(&proof.k),
+        inputs.join(",")
+    )
+}
+
+#[test]
+fn verifier_contract_matches_proof() {
+    use serde_json::{self, Value};
+    use snark::CS;
+    use protocol::*;
+    use protocol::affine::{g1_from_be_bytes, g2_from_be_bytes};
+    use std::env;
+    use std::fs;
+
+    fn parse_uint(v: &Value) -> Vec<u8> {
+        let s = v.as_str().unwrap();
+        assert!(s.starts_with("0x"));
+        hex::decode(&s[2..]).unwrap()
+    }
+
+    // The constraint system `x * y = z` with the public input `z`.
+    let path = env::temp_dir().join(format!("r1cs-{}", ::rand::random::<u64>()));
+    let path = path.to_str().unwrap();
+    CS::write(path, 1, 2, &[vec![(2, Fr::one())]], &[vec![(3, Fr::one())]], &[vec![(1, Fr::one())]]);
+    let cs = CS::from_path(path);
+    fs::remove_file(path).unwrap();
+
+    let rng = &mut ::rand::thread_rng();
+    let privkey = PrivateKey::new(rng);
+
+    let mut stage1 = Stage1Contents::new(&cs);
+    stage1.transform(&privkey);
+    let mut stage2 = Stage2Contents::new(&cs, &stage1);
+    stage2.transform(&privkey);
+    let mut stage3 = Stage3Contents::new(&cs, &stage2);
+    stage3.transform(&privkey);
+
+    let kp = keypair(&cs, &stage1, &stage2, &stage3);
+    let vk = verifying_key(&cs, &stage2, &stage3);
+    let contract = verifier_contract(&vk);
+
+    assert!(!contract.contains("<%"));
+    assert!(contract.contains(&format!("uint[{}] input", cs.num_inputs)));
+    assert!(contract.contains(&format!("vk.IC = new Pairing.G1Point[]({});", cs.num_inputs + 1)));
+    assert!(contract.contains(&format!("vk.A = {};", g2_point(&vk.a))));
+    assert!(contract.contains(&format!("vk.Z = {};", g2_point(&vk.z))));
+    for (i, ic) in vk.ic.iter().enumerate() {
+        assert!(contract.contains(&format!("vk.IC[{}] = {};", i, g1_point(ic))));
+    }
+
+    // A proof created by libsnark with the keypair, which the verification
+    // key in the contract accepts for the public input only.
+    let three = Fr::from_str("3").unwrap();
+    let five = Fr::from_str("5").unwrap();
+    let inputs = vec![three * five];
+    let proof = Proof::from(&kp.prove(&cs, &inputs, &[three, five]));
+    assert!(vk.verify(&proof, &inputs));
+    assert!(!vk.verify(&proof, &[three]));
+
+    // Reading the arguments back as the contract does yields the same proof.
+    let args: Value = serde_json::from_str(&verifier_arguments(&proof, &inputs)).unwrap();
+    let args = args.as_array().unwrap();
+    assert_eq!(args.len(), 9);
+
+    let g1 = |v: &Value| {
+        let v = v.as_array().unwrap();
+        let x = parse_uint(&v[0]);
+        let y = parse_uint(&v[1]);
+        if x.iter().all(|b| *b == 0) && y.iter().all(|b| *b == 0) {
+            G1::zero()
+        } else {
+            g1_from_be_bytes(&x, &y).unwrap()
+        }
+    };
+    let b = args[2].as_array().unwrap();
+    let (b_x, b_y) = (b[0].as_array().unwrap(), b[1].as_array().unwrap());
+    let read_proof = Proof {
+        a: g1(&args[0]),
+        a_p: g1(&args[1]),
+        b: g2_from_be_bytes(
+            (&parse_uint(&b_x[1]), &parse_uint(&b_x[0])),
+            (&parse_uint(&b_y[1]), &parse_uint(&b_y[0]))
+        ).unwrap(),
+        b_p: g1(&args[3]),
+        c: g1(&args[4]),
+        c_p: g1(&args[5]),
+        h: g1(&args[6]),
+        k: g1(&args[7])
+    };
+    assert!(read_proof == proof);
+
+    let read_inputs = args[8].as_array().unwrap();
+    assert_eq!(read_inputs.len(), inputs.len());
+    for (read, input) in read_inputs.iter().zip(inputs.iter()) {
+        assert_eq!(parse_uint(read), fr_to_be_bytes(input).to_vec());
+    }
+}
+
+/// Compiles the verifier contract with solc, if it is installed.
+#[test]
+fn verifier_contract_compiles() {
+    use snark::CS;
+    use protocol::*;
+    use std::env;
+    use std::fs::{self, File};
+    use std::io::Write;
+    use std::process::Command;
+
+    let rng = &mut ::rand::thread_rng();
+    let cs = CS::dummy();
+    let privkey = PrivateKey::new(rng);
+
+    let mut stage1 = Stage1Contents::new(&cs);
+    stage1.transform(&privkey);
+    let mut stage2 = Stage2Contents::new(&cs, &stage1);
+    stage2.transform(&privkey);
+    let mut stage3 = Stage3Contents::new(&cs, &stage2);
+    stage3.transform(&privkey);
+
+    let path = env::temp_dir().join(format!("verifier-{}.sol", ::rand::random::<u64>()));
+    File::create(&path).unwrap().write_all(verifier_contract(&verifying_key(&cs, &stage2, &stage3)).as_bytes()).unwrap();
+
+    let output = Command::new("solc").arg("--bin").arg(&path).output();
+    fs::remove_file(&path).unwrap();
+    match output {
+        Ok(output) => {
+            assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+            assert!(String::from_utf8_lossy(&output.stdout).contains("======= "));
+        },
+        Err(_) => println!("solc is not installed, the verifier contract was not compiled")
+    }
+}
@@ -0,0 +1,240 @@
+//! Export of the keypair in the formats of ZoKrates' PGHR13 backend.
+//!
+//! ZoKrates reads the verification key from a text file with one line per
+//! element, e.g. `vk.B = 0x.., 0x..`, to generate its verifier contract.
+//! Coordinates are big-endian hex strings without leading zeros, elements
+//! of Fq2 are written with the imaginary part first and the point at
+//! infinity in libff's affine coordinates. The proving key is libsnark's
+//! serialization of the key followed by the constraint system, which newer
+//! versions of libsnark store in the proving key.
+
+use bn::*;
+use hex;
+use protocol::VerifyingKey;
+use protocol::affine::{g1_from_be_bytes, g2_from_be_bytes, g1_to_be_bytes, g2_to_be_bytes};
+
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::Path;
+
+#[cfg(feature = "snark")]
+use snark::{CS, Keypair};
+
+macro_rules! try_opt {
+    ($e:expr) => (match $e { Some(v) => v, None => return None })
+}
+
+pub const PROVING_KEY: &str = "proving.key";
+pub const VERIFICATION_KEY: &str = "verification.key";
+
+/// A coordinate as hex without leading zeros, as ZoKrates writes it.
+fn uint(bytes: &[u8; 32]) -> String {
+    let hex = hex::encode(bytes);
+    let start = hex.find(|c| c != '0').unwrap_or(hex.len() - 1);
+    format!("0x{}", &hex[start..])
+}
+
+/// The point at infinity is (0, 1) in libff's affine coordinates.
+fn g1_hex(p: &G1) -> String {
+    let mut one = [0; 32];
+    one[31] = 1;
+    let (x, y) = g1_to_be_bytes(p).unwrap_or(([0; 32], one));
+    format!("{}, {}", uint(&x), uint(&y))
+}
+
+fn g2_hex(p: &G2) -> String {
+    let mut one = [0; 32];
+    one[31] = 1;
+    let ((x0, x1), (y0, y1)) = match g2_to_be_bytes(p) {
+        Some((x, y)) => ((x[0], x[1]), (y[0], y[1])),
+        None => (([0; 32], [0; 32]), (one, [0; 32]))
+    };
+    format!("[{}, {}], [{}, {}]", uint(&x1), uint(&x0), uint(&y1), uint(&y0))
+}
+
+fn parse_uint(v: &str) -> Option<Vec<u8>> {
+    if !v.starts_with("0x") || v.len() < 3 || v.len() > 66 {
+        return None;
+    }
+
+    hex::decode(format!("{:0>64}", &v[2..])).ok()
+}
+
+fn parse_uints(s: &str) -> Option<Vec<Vec<u8>>> {
+    s.split(',')
+        .map(|v| v.trim().trim_matches(|c: char| c == '[' || c == ']').trim())
+        .map(parse_uint)
+        .collect()
+}
+
+fn is_uint(v: &[u8], n: u8) -> bool {
+    v[..31].iter().all(|b| *b == 0) && v[31] == n
+}
+
+fn parse_g1(s: &str) -> Option<G1> {
+    let v = try_opt!(parse_uints(s));
+    if v.len() != 2 {
+        return None;
+    }
+    if is_uint(&v[0], 0) && is_uint(&v[1], 1) {
+        return Some(G1::zero());
+    }
+
+    g1_from_be_bytes(&v[0], &v[1])
+}
+
+fn parse_g2(s: &str) -> Option<G2> {
+    let v = try_opt!(parse_uints(s));
+    if v.len() != 4 {
+        return None;
+    }
+    if is_uint(&v[0], 0) && is_uint(&v[1], 0) && is_uint(&v[2], 0) && is_uint(&v[3], 1) {
+        return Some(G2::zero());
+    }
+
+    g2_from_be_bytes((&v[1], &v[0]), (&v[3], &v[2]))
+}
+
+/// The verification key in the format of ZoKrates' `verification.key`.
+pub fn verification_key(vk: &VerifyingKey) -> String {
+    let mut lines = vec![
+        format!("vk.A = {}", g2_hex(&vk.a)),
+        format!("vk.B = {}", g1_hex(&vk.b)),
+        format!("vk.C = {}", g2_hex(&vk.c)),
+        format!("vk.gamma = {}", g2_hex(&vk.gamma)),
+        format!("vk.gammaBeta1 = {}", g1_hex(&vk.gamma_beta_1)),
+        format!("vk.gammaBeta2 = {}", g2_hex(&vk.gamma_beta_2)),
+        format!("vk.Z = {}", g2_hex(&vk.z)),
+        format!("vk.IC.len() = {}", vk.ic.len())
+    ];
+    for (i, ic) in vk.ic.iter().enumerate() {
+        lines.push(format!("vk.IC[{}] = {}", i, g1_hex(ic)));
+    }
+
+    lines.into_iter().map(|l| format!("\t\t{}\n", l)).collect()
+}
+
+/// Parses a verification key in the format of ZoKrates' `verification.key`.
+/// Returns `None` if an element is missing or not a valid point.
+pub fn read_verification_key(s: &str) -> Option<VerifyingKey> {
+    let (mut a, mut b, mut c, mut gamma, mut gamma_beta_1, mut gamma_beta_2, mut z) =
+        (None, None, None, None, None, None, None);
+    let mut ic_len = None;
+    let mut ic = vec![];
+
+    for line in s.lines().map(|l| l.trim()).filter(|l| !l.is_empty()) {
+        let mut parts = line.splitn(2, " = ");
+        let (key, value) = match (parts.next(), parts.next()) {
+            (Some(key), Some(value)) => (key, value),
+            _ => return None
+        };
+
+        match key {
+            "vk.A" => a = Some(try_opt!(parse_g2(value))),
+            "vk.B" => b = Some(try_opt!(parse_g1(value))),
+            "vk.C" => c = Some(try_opt!(parse_g2(value))),
+            "vk.gamma" => gamma = Some(try_opt!(parse_g2(value))),
+            "vk.gammaBeta1" => gamma_beta_1 = Some(try_opt!(parse_g1(value))),
+            "vk.gammaBeta2" => gamma_beta_2 = Some(try_opt!(parse_g2(value))),
+            "vk.Z" => z = Some(try_opt!(parse_g2(value))),
+            "vk.IC.len()" => ic_len = value.parse::<usize>().ok(),
+            _ if key == format!("vk.IC[{}]", ic.len()) => ic.push(try_opt!(parse_g1(value))),
+            _ => return None
+        }
+    }
+
+    if ic_len != Some(ic.len()) || ic.is_empty() {
+        return None;
+    }
+
+    Some(VerifyingKey {
+        a: try_opt!(a),
+        b: try_opt!(b),
+        c: try_opt!(c),
+        gamma: try_opt!(gamma),
+        gamma_beta_1: try_opt!(gamma_beta_1),
+        gamma_beta_2: try_opt!(gamma_beta_2),
+        z: try_opt!(z),
+        ic: ic
+    })
+}
+
+/// Writes `proving.key` and `verification.key` to the directory, which is
+/// created if it does not exist.
+#[cfg(feature = "snark")]
+pub fn write_keypair(dir: &str, cs: &CS, kp: &Keypair, vk: &VerifyingKey) -> io::Result<()> {
+    let dir = Path::new(dir);
+    try!(fs::create_dir_all(dir));
+
+    let mut file = try!(File::create(dir.join(VERIFICATION_KEY)));
+    try!(file.write_all(verification_key(vk).as_bytes()));
+
+    kp.write_zokrates_proving_key(cs, dir.join(PROVING_KEY).to_str().expect("Paths should be valid unicode"));
+
+    Ok(())
+}
+
+#[test]
+fn verification_key_fixture() {
+    let fixture = include_str!("fixtures/verification.key");
+
+    let vk = VerifyingKey {
+        a: G2::one(),
+        b: G1::one(),
+        c: G2::one(),
+        gamma: G2::one(),
+        gamma_beta_1: G1::one(),
+        gamma_beta_2: G2::one(),
+        z: G2::one(),
+        ic: vec![G1::one(), G1::zero()]
+    };
+
+    assert_eq!(verification_key(&vk), fixture);
+    assert!(read_verification_key(fixture).unwrap() == vk);
+
+    // Invalid points and missing elements are rejected.
+    assert!(read_verification_key(&fixture.replace("vk.B = 0x1, 0x2", "vk.B = 0x1, 0x3")).is_none());
+    assert!(read_verification_key(&fixture.replace("vk.B = 0x1, 0x2", "vk.B = 0x1, 0x")).is_none());
+    assert!(read_verification_key(&fixture.replace("vk.IC[1] = 0x0, 0x1", "vk.IC[1] = 0x0, 0x0")).is_none());
+    assert!(read_verification_key(&fixture.replace("vk.IC.len() = 2", "vk.IC.len() = 3")).is_none());
+    assert!(read_verification_key(&fixture.replace("vk.Z", "vk.Y")).is_none());
+
+    // Leading zeros are accepted.
+    let padded = fixture.replace("vk.B = 0x1,", "vk.B = 0x0000000000000000000000000000000000000000000000000000000000000001,");
+    assert!(read_verification_key(&padded).unwrap() == vk);
+}
+
+#[test]
+fn keypair_roundtrip() {
+    use protocol::*;
+    use std::env;
+
+    let rng = &mut ::rand::thread_rng();
+    let cs = CS::dummy();
+    let privkey = PrivateKey::new(rng);
+
+    let mut stage1 = Stage1Contents::new(&cs);
+    stage1.transform(&privkey);
+    let mut stage2 = Stage2Contents::new(&cs, &stage1);
+    stage2.transform(&privkey);
+    let mut stage3 = Stage3Contents::new(&cs, &stage2);
+    stage3.transform(&privkey);
+
+    let kp = keypair(&cs, &stage1, &stage2, &stage3);
+    let vk = verifying_key(&cs, &stage2, &stage3);
+
+    let dir = env::temp_dir().join(format!("zokrates-{}", ::rand::random::<u64>()));
+    let dir = dir.to_str().unwrap();
+    write_keypair(dir, &cs, &kp, &vk).unwrap();
+
+    let mut s = String::new();
+    {
+        use std::io::Read;
+        File::open(Path::new(dir).join(VERIFICATION_KEY)).unwrap().read_to_string(&mut s).unwrap();
+    }
+    assert!(read_verification_key(&s).unwrap() == vk);
+    assert!(kp.test_zokrates_proving_key(&cs, Path::new(dir).join(PROVING_KEY).to_str().unwrap()).unwrap());
+    assert!(kp.test_zokrates_proving_key(&cs, Path::new(dir).join("missing").to_str().unwrap()).is_err());
+
+    fs::remove_dir_all(dir).unwrap();
+}
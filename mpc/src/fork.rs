@@ -0,0 +1,447 @@
+//! Forks of failed ceremonies. If a player publishes an invalid
+//! transformation or vanishes, the work of the players before it is not
+//! thrown away: `player fork-ceremony` verifies the transcript of the failed
+//! ceremony up to its last valid transformation and deploys a new contract
+//! that continues from it.
+//!
+//! The new contract records its parent, the stage and the player of the
+//! transformation it continues, and the IPFS hash of its provenance, the
+//! verified contributions of the parent. Its coordinator publishes that
+//! transformation as the initial stage of the stage it continues, and the
+//! stages before are taken from the parent. The verifier of a fork verifies
+//! the parent up to the fork first, so the final keypair is attributable to
+//! the players of both ceremonies.
+
+use blockchain::*;
+use contribution::{Contribution, Statement};
+use dist_files::*;
+use protocol::*;
+use receipt::fingerprint;
+use rustc_serialize::{Decodable, Encodable};
+use snark::CS;
+use web3::contract::Contract;
+use web3::types::{Address, H256};
+use web3::{Transport, Web3};
+
+use std::time::Duration;
+
+use super::create_stage1;
+
+/// A verified contribution to a ceremony.
+#[derive(Clone, Debug, PartialEq, RustcEncodable, RustcDecodable)]
+pub struct ForkedContribution {
+    pub contract: [u8; 20],
+    pub stage: u64,
+    pub player: u64,
+    pub account: [u8; 20],
+    pub stage_ipfs: String,
+    pub contribution_ipfs: String,
+    pub fingerprint: String
+}
+
+/// The provenance of a fork: the verified contributions of the ceremony it
+/// continues, and the provenance of that ceremony if it is a fork itself.
+#[derive(Clone, RustcEncodable, RustcDecodable)]
+pub struct Provenance {
+    pub parent: [u8; 20],
+    pub parent_provenance: String,
+    pub contributions: Vec<ForkedContribution>
+}
+
+/// Where a fork continues its parent.
+pub struct Fork {
+    pub parent: Address,
+    pub stage: u64,
+    pub player: u64,
+    pub provenance: String
+}
+
+/// The verified part of the transcript of a ceremony. The stages before the
+/// last one reached are final, with the random beacon applied, and the last
+/// one is its last valid transformation.
+pub struct Prefix {
+    pub stage1: Option<Stage1Contents>,
+    pub stage2: Option<Stage2Contents>,
+    pub stage3: Option<Stage3Contents>,
+    pub contributions: Vec<ForkedContribution>,
+    pub complete: bool
+}
+
+/// A player with its public key, if it has a valid one.
+struct Participant {
+    address: Address,
+    excluded_in: u64,
+    pubkey: Option<PublicKey>
+}
+
+pub fn get_fork<T: Transport>(contract: &DistributedMpc<T>) -> Option<Fork> {
+    let parent = contract.parent_contract();
+    if parent == Address::zero() {
+        return None;
+    }
+    Some(Fork {
+        parent: parent,
+        stage: contract.fork_stage().low_u64(),
+        player: contract.fork_player().low_u64(),
+        provenance: String::from_utf8(contract.fork_provenance()).expect("Should be valid IPFS hash!")
+    })
+}
+
+pub fn parent_contract<T: Transport>(web3: &Web3<T>, contract: &DistributedMpc<T>, fork: &Fork) -> DistributedMpc<T> {
+    let web3_contract = Contract::from_json(
+        web3.eth(),
+        fork.parent,
+        include_bytes!("../abi.json")
+    ).expect("Error loading contract from json!");
+    DistributedMpc::new(web3_contract, contract.submitter().clone())
+}
+
+/// The transformation of the parent a fork continues, if the stage at the
+/// index is the one it continues.
+pub fn seed_stage<S, T>(web3: &Web3<T>, contract: &DistributedMpc<T>, fork: &Option<Fork>, stage_index: u64, ipfs: &mut IPFSWrapper) -> Option<S> where
+    S: Transform + Verify + Clone + Encodable + Decodable,
+    T: Transport
+{
+    match *fork {
+        Some(ref fork) if fork.stage == stage_index => {
+            let parent = parent_contract(web3, contract, fork);
+            let stage_hash = parent.get_transformation(fork.stage, fork.player);
+            Some(ipfs.download_stage(String::from_utf8(stage_hash).expect("Should be valid IPFS hash!").as_str()))
+        },
+        _ => None
+    }
+}
+
+impl Provenance {
+    pub fn new<T: Transport>(parent: &DistributedMpc<T>, prefix: &Prefix) -> Provenance {
+        Provenance {
+            parent: parent.address().0,
+            parent_provenance: get_fork(parent).map(|fork| fork.provenance).unwrap_or_default(),
+            contributions: prefix.contributions.clone()
+        }
+    }
+
+    /// Whether the provenance records the same parent and contributions.
+    pub fn matches(&self, other: &Provenance) -> bool {
+        self.parent == other.parent &&
+        self.parent_provenance == other.parent_provenance &&
+        self.contributions == other.contributions
+    }
+}
+
+/// Checks the public key of the player at the index against its commitment
+/// and its nizks.
+fn check_public_key(index: u64, pubkey: &PublicKey, commitment: &Digest256, nizks: &PublicKeyNizks, transcript: &NizkTranscript) -> Result<(), String> {
+    if pubkey.hash() != *commitment {
+        return Err(format!("Invalid commitment from player {}", index));
+    }
+    if !nizks.is_valid(pubkey, transcript) {
+        return Err(format!("Invalid nizks from player {}", index));
+    }
+    Ok(())
+}
+
+/// The public key of the player at the index, or `None` if it has not
+/// published it. Panics if the public key or the nizks are invalid, since
+/// the player was not excluded for it.
+fn verify_public_key<T: Transport>(contract: &DistributedMpc<T>, chain_id: u64, index: u64, address: Address, commitment: &Digest256, hash_of_commitments: &Digest512, ipfs: &mut IPFSWrapper) -> Option<PublicKey> {
+    let publickey_hash = contract.get_public_key(index);
+    let nizks_hash = contract.get_nizks(index);
+    if publickey_hash.is_empty() || nizks_hash.is_empty() {
+        println!("Player {} has not published its public key and nizks", index);
+        return None;
+    }
+    let pubkey: PublicKey = ipfs.download_object(String::from_utf8(publickey_hash).expect("Error decoding public key to object").as_str());
+    let nizks: PublicKeyNizks = ipfs.download_object(String::from_utf8(nizks_hash).expect("Error decoding nizks to object").as_str());
+    let transcript = NizkTranscript::new(chain_id, contract.address().0, address.0, index, *hash_of_commitments);
+    if let Err(e) = check_public_key(index, &pubkey, commitment, &nizks, &transcript) {
+        panic!("\u{274c} {} ({:?}), the ceremony cannot be verified!", e, address);
+    }
+    println!("\u{2714} Commitment {} and nizks of player {} are valid", commitment, index);
+    Some(pubkey)
+}
+
+/// Whether a player excluded in the state took part in the commitments,
+/// i.e. it was not excluded before the stages.
+fn has_public_key(excluded_in: u64) -> bool {
+    excluded_in == 0 || excluded_in >= 4
+}
+
+/// Whether a player excluded in the state took part in the stage at the
+/// index, i.e. it was not excluded before or during the stage.
+fn takes_part_in(excluded_in: u64, stage_index: u64) -> bool {
+    excluded_in == 0 || excluded_in > 4 + stage_index
+}
+
+/// The players of the ceremony. Players excluded before the stages only
+/// count with their commitment.
+fn verify_participants<T: Transport>(contract: &DistributedMpc<T>, chain_id: u64, ipfs: &mut IPFSWrapper) -> Vec<Participant> {
+    let number_of_players = contract.get_number_of_players().low_u64();
+    let addresses: Vec<Address> = (0..number_of_players).map(|i| contract.players(i)).collect();
+    let commitments: Vec<Digest256> = addresses.iter().map(|address| Digest256(contract.get_commitment(*address))).collect();
+    let hash_of_commitments = Digest512::from(&commitments).unwrap();
+
+    let mut participants = vec![];
+    for i in 0..number_of_players {
+        let address = addresses[i as usize];
+        let excluded_in = contract.get_exclusion_state(i).low_u64();
+        let pubkey = if has_public_key(excluded_in) {
+            verify_public_key(contract, chain_id, i, address, &commitments[i as usize], &hash_of_commitments, ipfs)
+        } else {
+            println!("Player {} ({:?}) was excluded in state {} and did not contribute", i, address, excluded_in);
+            None
+        };
+        participants.push(Participant {
+            address: address,
+            excluded_in: excluded_in,
+            pubkey: pubkey
+        });
+    }
+    participants
+}
+
+/// Verifies the transformations of the stage at the index in the order of
+/// the players, starting from the recomputed initial stage. Returns the last
+/// valid stage, and whether all players who took part in the stage published
+/// a valid transformation before the verification reached `until`.
+fn verify_stage<S, T>(contract: &DistributedMpc<T>, stage_index: u64, initial: S, participants: &[Participant], until: Option<(u64, u64)>, contributions: &mut Vec<ForkedContribution>, ipfs: &mut IPFSWrapper) -> (S, bool) where
+    S: Transform + Verify + Clone + Encodable + Decodable,
+    T: Transport
+{
+    let initial_hash = contract.get_initial_stage(stage_index);
+    if initial_hash.is_empty() {
+        println!("Stage {} has not been initialized", stage_index + 1);
+        return (initial, false);
+    }
+    let published: S = ipfs.download_stage(String::from_utf8(initial_hash).expect("Should be valid IPFS hash!").as_str());
+    if Digest512::from(&published).expect("Stage should be serializable!") != Digest512::from(&initial).expect("Stage should be serializable!") {
+        println!("\u{274c} The initial stage {} does not match the recomputed stage", stage_index + 1);
+        return (initial, false);
+    }
+    println!("\u{2714} The initial stage {} is correct", stage_index + 1);
+
+    let mut prior = initial;
+    for (i, participant) in participants.iter().enumerate() {
+        if !takes_part_in(participant.excluded_in, stage_index) {
+            continue;
+        }
+        let stage_hash = contract.get_transformation(stage_index, i as u64);
+        if stage_hash.is_empty() {
+            println!("Player {} has not published stage {}", i, stage_index + 1);
+            return (prior, false);
+        }
+        let pubkey = match participant.pubkey {
+            Some(ref pubkey) => pubkey,
+            None => {
+                println!("\u{274c} Player {} published stage {} without a valid public key", i, stage_index + 1);
+                return (prior, false);
+            }
+        };
+        let stage_ipfs = String::from_utf8(stage_hash).expect("Should be valid IPFS hash!");
+        let next: S = ipfs.download_stage(stage_ipfs.as_str());
+        if !next.verify_transform(&prior, pubkey) {
+            println!("\u{274c} Invalid stage {} transformation from player {}", stage_index + 1, i);
+            return (prior, false);
+        }
+        println!("\u{2714} Stage {} has been transformed correctly by player {}", stage_index + 1, i);
+
+        let contribution_hash = contract.get_contribution(stage_index, i as u64);
+        let contribution_ipfs = String::from_utf8(contribution_hash).expect("Should be valid IPFS hash!");
+        let contribution: Contribution = ipfs.download_object(contribution_ipfs.as_str());
+        let expected = Statement::new(contract.address(), stage_index, &prior, &next, pubkey);
+        if !contribution.is_valid(participant.address, &expected) {
+            println!("\u{274c} Invalid signature on the stage {} contribution of player {}", stage_index + 1, i);
+            return (prior, false);
+        }
+        println!("\u{2714} Stage {} contribution of player {} is signed by {:?}. Fingerprint: {}", stage_index + 1, i, participant.address, fingerprint(&expected));
+        contributions.push(ForkedContribution {
+            contract: contract.address().0,
+            stage: stage_index,
+            player: i as u64,
+            account: participant.address.0,
+            stage_ipfs: stage_ipfs,
+            contribution_ipfs: contribution_ipfs,
+            fingerprint: fingerprint(&expected)
+        });
+        prior = next;
+        if until == Some((stage_index, i as u64)) {
+            return (prior, false);
+        }
+    }
+    (prior, true)
+}
+
+/// Waits until the random beacon of the stage is recorded. The contract fixes
+/// its block `beaconDelay` blocks after the last transformation of the stage
+/// and lets anyone record it. The caller records it if no one has `rank`
+/// blocks later, so that the coordinator (rank 0) does so first and the other
+/// players only if it does not. Stages that did not end in the contract, e.g.
+/// those a fork takes from its parent, have no beacon in it.
+pub fn await_beacon<T: Transport>(web3: &Web3<T>, contract: &DistributedMpc<T>, stage_index: u64, rank: u64, poll_interval: &Duration) {
+    let end = contract.stage_end_blocks(stage_index).low_u64();
+    if end == 0 || contract.beacon_blocks(stage_index).low_u64() != 0 {
+        return;
+    }
+    await_block_hash(web3, end + contract.beacon_delay().low_u64() + rank, poll_interval);
+    if contract.beacon_blocks(stage_index).low_u64() == 0 {
+        println!("Recording the random beacon of stage {}...", stage_index + 1);
+        contract.record_stage_beacon(stage_index);
+    }
+}
+
+/// The random beacon of the stage, the hash of the block recorded after its
+/// last transformation, hashed as often as the contract says, if there is
+/// one.
+pub fn stage_beacon<T: Transport>(web3: &Web3<T>, contract: &DistributedMpc<T>, stage_index: u64) -> Option<Beacon> {
+    let number = contract.beacon_blocks(stage_index).low_u64();
+    if number == 0 {
+        return None;
+    }
+    let hash = H256(contract.beacon_hashes(stage_index));
+    if await_block_hash(web3, number, &Duration::new(1, 0)) != hash {
+        panic!("\u{274c} The beacon of stage {} is not the hash of block {}!", stage_index + 1, number);
+    }
+    let iterations = contract.beacon_iterations().low_u64();
+    if iterations >= 64 {
+        panic!("\u{274c} The beacon of stage {} is hashed 2^{} times, at most 2^63 are supported!", stage_index + 1, iterations);
+    }
+    println!("Using the hash of block {} as the random beacon of stage {}, hashed 2^{} times: {:?}", number, stage_index + 1, iterations, hash);
+    Some(Beacon::new(hash.to_vec(), iterations as u8))
+}
+
+fn apply_beacon<S: Transform + Verify + Clone>(beacon: &Option<Beacon>, stage: S, stage_index: u64) -> S {
+    match *beacon {
+        Some(ref beacon) => {
+            let stage = beacon.apply(&stage);
+            println!("\u{2714} Applied random beacon to stage {}", stage_index + 1);
+            stage
+        },
+        None => stage
+    }
+}
+
+/// Verifies the transcript of the ceremony until the first transformation
+/// that is missing or invalid, or up to the transformation `until` given as
+/// stage and player index. A fork is verified together with its parent.
+pub fn verify_prefix<T: Transport>(web3: &Web3<T>, contract: &DistributedMpc<T>, cs: &CS, powers_of_tau: Option<&str>, until: Option<(u64, u64)>, ipfs: &mut IPFSWrapper) -> Prefix {
+    let mut prefix = Prefix {
+        stage1: None,
+        stage2: None,
+        stage3: None,
+        contributions: vec![],
+        complete: false
+    };
+    let start = match get_fork(contract) {
+        Some(fork) => {
+            println!("{:?} is a fork of {:?}, verifying it up to the stage {} transformation of player {}...", contract.address(), fork.parent, fork.stage + 1, fork.player);
+            let parent = parent_contract(web3, contract, &fork);
+            let parent_prefix = verify_prefix(web3, &parent, cs, powers_of_tau, Some((fork.stage, fork.player)), ipfs);
+            if parent_prefix.contributions.last().map(|c| (c.stage, c.player)) != Some((fork.stage, fork.player)) {
+                panic!("\u{274c} The transformation the fork continues is not valid!");
+            }
+            let provenance: Provenance = ipfs.download_object(fork.provenance.as_str());
+            let expected = Provenance::new(&parent, &parent_prefix);
+            if !provenance.matches(&expected) {
+                panic!("\u{274c} The provenance of the fork does not match the transcript of its parent!");
+            }
+            println!("\u{2714} {:?} is valid up to the fork", fork.parent);
+            prefix.stage1 = parent_prefix.stage1;
+            prefix.stage2 = parent_prefix.stage2;
+            prefix.stage3 = parent_prefix.stage3;
+            fork.stage
+        },
+        None => 0
+    };
+    let participants = verify_participants(contract, chain_id(web3), ipfs);
+
+    if start == 0 {
+        let initial = prefix.stage1.take().unwrap_or_else(|| create_stage1(contract, cs, powers_of_tau));
+        let (stage1, complete) = verify_stage(contract, 0, initial, &participants, until, &mut prefix.contributions, ipfs);
+        if !complete {
+            prefix.stage1 = Some(stage1);
+            return prefix;
+        }
+        prefix.stage1 = Some(apply_beacon(&stage_beacon(web3, contract, 0), stage1, 0));
+    }
+    if start <= 1 {
+        let initial = prefix.stage2.take().unwrap_or_else(|| Stage2Contents::new(cs, prefix.stage1.as_ref().expect("Stage 1 should be final!")));
+        let (stage2, complete) = verify_stage(contract, 1, initial, &participants, until, &mut prefix.contributions, ipfs);
+        if !complete {
+            prefix.stage2 = Some(stage2);
+            return prefix;
+        }
+        prefix.stage2 = Some(apply_beacon(&stage_beacon(web3, contract, 1), stage2, 1));
+    }
+    let initial = prefix.stage3.take().unwrap_or_else(|| Stage3Contents::new(cs, prefix.stage2.as_ref().expect("Stage 2 should be final!")));
+    let (stage3, complete) = verify_stage(contract, 2, initial, &participants, until, &mut prefix.contributions, ipfs);
+    if !complete {
+        prefix.stage3 = Some(stage3);
+        return prefix;
+    }
+    prefix.stage3 = Some(apply_beacon(&stage_beacon(web3, contract, 2), stage3, 2));
+    prefix.complete = true;
+    prefix
+}
+
+#[test]
+fn public_key_checks() {
+    let rng = &mut ::rand::thread_rng();
+    let privkey = PrivateKey::new(rng);
+    let pubkey = privkey.pubkey(rng);
+    let commitment = pubkey.hash();
+    let hash_of_commitments = Digest512::from(&vec![commitment]).unwrap();
+    let transcript = NizkTranscript::new(1, [1u8; 20], [2u8; 20], 1, hash_of_commitments);
+    let nizks = pubkey.nizks(rng, &privkey, &transcript);
+    assert!(check_public_key(1, &pubkey, &commitment, &nizks, &transcript).is_ok());
+
+    let other = PrivateKey::new(rng).pubkey(rng);
+    assert!(check_public_key(1, &pubkey, &other.hash(), &nizks, &transcript).is_err());
+    // The nizks are bound to the player and to the ceremony.
+    let other_player = NizkTranscript::new(1, [1u8; 20], [3u8; 20], 1, hash_of_commitments);
+    assert!(check_public_key(1, &pubkey, &commitment, &nizks, &other_player).is_err());
+    let other_chain = NizkTranscript::new(2, [1u8; 20], [2u8; 20], 1, hash_of_commitments);
+    assert!(check_public_key(1, &pubkey, &commitment, &nizks, &other_chain).is_err());
+}
+
+#[test]
+fn exclusion() {
+    // Active players take part in everything.
+    assert!(has_public_key(0) && takes_part_in(0, 0) && takes_part_in(0, 2));
+    // Excluded while committing, revealing or publishing nizks.
+    for state in 1..4 {
+        assert!(!has_public_key(state));
+    }
+    // Excluded in stage 2: it took part in stage 1 only.
+    assert!(has_public_key(5));
+    assert!(takes_part_in(5, 0));
+    assert!(!takes_part_in(5, 1) && !takes_part_in(5, 2));
+}
+
+#[test]
+fn provenance_matches() {
+    let contribution = ForkedContribution {
+        contract: [1u8; 20],
+        stage: 0,
+        player: 1,
+        account: [2u8; 20],
+        stage_ipfs: "stage".into(),
+        contribution_ipfs: "contribution".into(),
+        fingerprint: "fingerprint".into()
+    };
+    let provenance = Provenance {
+        parent: [1u8; 20],
+        parent_provenance: String::new(),
+        contributions: vec![contribution.clone()]
+    };
+    assert!(provenance.matches(&provenance.clone()));
+
+    let mut altered = provenance.clone();
+    altered.contributions[0].fingerprint = "other".into();
+    assert!(!provenance.matches(&altered));
+    let mut truncated = provenance.clone();
+    truncated.contributions.clear();
+    assert!(!provenance.matches(&truncated));
+    let mut reparented = provenance.clone();
+    reparented.parent = [3u8; 20];
+    assert!(!provenance.matches(&reparented));
+}
@@ -1,44 +1,109 @@
-use dist_files::ipfs::IPFSWrapper;
+use dist_files::ipfs::{IPFSAddResponse, IPFSWrapper};
 use super::blockchain::*;
 use web3::contract::*;
 use web3::futures::Future;
-use web3::transports::*;
-use web3::types::{Address, U256};
+use web3::types::Address;
 use web3::{Transport, Web3};
 
 use consts::*;
 
 use hex;
-use json;
+use serde_json;
 use std::fs::File;
-use std::io::Read;
-use std::time::Instant;
-use time::Duration as MDuration;
-use serde_json::value::Value; 
+use std::path::Path;
+use std::rc::Rc;
 
 pub struct Manager<T: Transport>{
     pub ipfs: IPFSWrapper,
     pub web3: Web3<T>,
-    contract: Option<ContractWrapper<T>>
+    contract: Option<DistributedMpc<T>>,
+    signer: Option<Rc<LocalSigner<T>>>,
+    policy: TransactionPolicy
 }
 
-#[derive(Deserialize, Debug)]
-struct ContractJson {
-    abi: Vec<Value>,
-    bytecode: String
+/// The summary of a deployed ceremony, which the coordinator hands to the
+/// players.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Deployment {
+    pub contract: String,
+    pub transaction: String,
+    pub block: u64,
+    pub gas_used: u64,
+    pub coordinator: String,
+    pub constraint_system: String,
+    pub constraint_system_size: u64,
+    pub timeout_blocks: u64,
+    pub timeout_seconds: u64,
+    pub expected_players: Vec<String>,
+    pub max_players: u64,
+    pub powers_of_tau: Option<String>,
+    pub beacon_delay: u64,
+    pub beacon_iterations: u8
 }
 
-impl Manager <Http>{
-    pub fn new(_web3: Web3<Http>, ipfs_url: &str, ipfs_port: u16) -> Self{
+fn address_hex(address: &Address) -> String {
+    format!("0x{}", hex::encode(&address.0[..]))
+}
+
+impl Deployment {
+    pub fn new(receipt: &CallReceipt, contract: Address, coordinator: Address, cs_ipfs: &IPFSAddResponse, params: &CeremonyParameters) -> Deployment {
+        Deployment {
+            contract: address_hex(&contract),
+            transaction: format!("{:?}", receipt.transaction_hash),
+            block: receipt.block_number,
+            gas_used: receipt.gas_used.low_u64(),
+            coordinator: address_hex(&coordinator),
+            constraint_system: cs_ipfs.hash.clone(),
+            constraint_system_size: u64::from_str_radix(&cs_ipfs.size, 10).expect("IPFS should return the size as a number!"),
+            timeout_blocks: params.timeout.blocks,
+            timeout_seconds: params.timeout.seconds,
+            expected_players: params.expected_players.iter().map(address_hex).collect(),
+            max_players: params.max_players,
+            powers_of_tau: if params.powers_of_tau == [0; 32] {
+                None
+            } else {
+                Some(format!("0x{}", hex::encode(&params.powers_of_tau[..])))
+            },
+            beacon_delay: params.beacon_delay,
+            beacon_iterations: params.beacon_iterations
+        }
+    }
+
+    pub fn write(&self, path: &str) {
+        let file = File::create(path).unwrap_or_else(|e| panic!("\u{274c} Error creating the deployment summary {:?}: {}", path, e));
+        serde_json::to_writer_pretty(file, self).expect("Error writing the deployment summary!");
+    }
+}
+
+impl<T: Transport> Manager<T> {
+    pub fn new(_web3: Web3<T>, ipfs_url: &str, ipfs_port: u16) -> Self{
         let _ipfs = IPFSWrapper::new(ipfs_url, ipfs_port);
         Manager{
             ipfs: _ipfs,
             web3: _web3,
-            contract: None
+            contract: None,
+            signer: None,
+            policy: TransactionPolicy::default()
         }
     }
 
+    /// Signs the transactions locally with the signer, if any, instead of
+    /// with an account of the node.
+    pub fn with_signer(mut self, signer: Option<Rc<LocalSigner<T>>>) -> Self {
+        self.signer = signer;
+        self
+    }
+
+    /// Submits the transactions according to the policy.
+    pub fn with_policy(mut self, policy: TransactionPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
     pub fn init_account(&self, index: Option<&str>) -> Address {
+        if let Some(ref signer) = self.signer {
+            return signer.address();
+        }
         let accounts: Vec<Address> = self.web3.eth().accounts().wait().expect("Error getting accounts!");
         let mut account_index: usize = 0;
         if index.is_some() {
@@ -48,54 +113,58 @@ impl Manager <Http>{
         accounts[account_index]
     }
 
-    fn deploy_contract(&mut self, path: &str, account: Address) -> Contract<Http> {
-        let contract_build: &mut String = &mut String::new();
-        File::open(path).expect("Error opening contract json file.").read_to_string(contract_build).expect("Should be readable.");
-        let contract_build_json = json::parse(contract_build.as_str()).expect("Error parsing json!");
-        let abi = &contract_build_json["abi"];
-        let bytecode = &contract_build_json["bytecode"].dump();
-        let len = bytecode.len()-1;
-        let bytecode_hex: Vec<u8> = hex::decode(&bytecode[3..len]).expect("Unexpected error!");       //skip leading and trailing special characters like "0x..."
-        let cs_ipfs = self.ipfs.upload_file("r1cs");
+    /// Submits the transactions of the account with the signer and policy
+    /// of the manager.
+    pub fn submitter(&self, account: Address) -> Submitter<T> {
+        Submitter::new(self.web3.clone(), account, self.signer.clone(), self.policy.clone())
+    }
+
+    /// Deploys the contract from the bytecode embedded at build time with the
+    /// constraint system in the file, which is uploaded to IPFS.
+    fn deploy_contract(&mut self, cs_path: &str, submitter: &Submitter<T>, params: &CeremonyParameters) -> (Contract<T>, Deployment) {
+        if !Path::new(cs_path).is_file() {
+            panic!("\u{274c} The constraint system {:?} does not exist!", cs_path);
+        }
+        let code: Vec<u8> = hex::decode(BYTECODE.trim()).unwrap_or_else(|e| panic!("\u{274c} The embedded bytecode of the contract is not hex: {}", e));
+        if code.is_empty() {
+            panic!("\u{274c} The player was built without the bytecode of the contract! Run `UPDATE_BYTECODE=1 truffle test` in blockchain and build again.");
+        }
+        let cs_ipfs = self.ipfs.upload_file(cs_path);
         println!("Size of constraint system : {} B", cs_ipfs.size);
         if PERFORM_MEASUREMENTS {
             unsafe {
                 TOTAL_BYTES += u64::from_str_radix(&cs_ipfs.size, 10).unwrap();
             }
         }
-        let wait_start = Instant::now();
-        let contract = Contract::deploy(self.web3.eth(), &abi.dump().into_bytes()).expect("Abi should be well-formed!")
-        .options(Options::with(|opt|{opt.gas = Some(U256::from(4000000))}))
-        .execute(bytecode_hex, cs_ipfs.hash.into_bytes(), account).expect("execute failed!").wait().expect("Error after wait!");
-        if PERFORM_MEASUREMENTS {
-            let duration = MDuration::from_std(wait_start.elapsed());
-            if duration.is_ok() {
-                unsafe {
-                    FILTER_OVERHEAD_MS += duration.unwrap().num_milliseconds();
-                }
-            } else {
-                println!("Error in time measurement: Overflow in duration");
-            }
-        }
-        contract
+        let data = deployment_data(code, cs_ipfs.hash.clone().into_bytes(), params.timeout.blocks, params.timeout.seconds, params.expected_players.clone(), params.max_players, params.powers_of_tau, params.beacon_delay, params.beacon_iterations);
+        // The time waited for the deployment is measured by the submitter.
+        let receipt = submitter.submit(None, data).unwrap_or_else(|e| panic!("\u{274c} Error deploying the contract: {}", e));
+        let address = receipt.contract_address.expect("Deployment should create a contract!");
+        let contract = Contract::from_json(self.web3.eth(), address, include_bytes!("../../abi.json")).expect("Abi should be well-formed!");
+        (contract, Deployment::new(&receipt, address, submitter.account(), &cs_ipfs, params))
     }
 
-    pub fn init_contract(mut self, index: Option<&str>, address: Option<&str>) -> ContractWrapper<Http>{
+    /// Deploys a ceremony with the constraint system in the file and the
+    /// parameters. The account deploys it and is its coordinator.
+    pub fn deploy(mut self, index: Option<&str>, cs_path: &str, params: &CeremonyParameters) -> (DistributedMpc<T>, Deployment) {
         let default_account = self.init_account(index);
-        let _contract;
-        if address.is_some() {
-            let contract_address: Address = address.unwrap().parse().expect("Error reading the contract address from the command line!");
-            let web3_contract = Contract::from_json(
-                self.web3.eth(),
-                contract_address,
-                include_bytes!("../../abi.json")
-            ).expect("Error loading contract from json!");
-            _contract = ContractWrapper::new(web3_contract, default_account);
-        } else {
-            let web3_contract = self.deploy_contract("../blockchain/build/contracts/DistributedMPC.json", default_account);
-            _contract = ContractWrapper::new(web3_contract, default_account);
-        }
-        self.contract = Some(_contract);
+        let submitter = self.submitter(default_account);
+        let (web3_contract, deployment) = self.deploy_contract(cs_path, &submitter, params);
+        (DistributedMpc::new(web3_contract, submitter), deployment)
+    }
+
+    /// Loads the contract at the address. New ceremonies are deployed with
+    /// `deploy`.
+    pub fn init_contract(mut self, index: Option<&str>, address: &str) -> DistributedMpc<T>{
+        let default_account = self.init_account(index);
+        let submitter = self.submitter(default_account);
+        let contract_address: Address = address.parse().expect("Error reading the contract address from the command line!");
+        let web3_contract = Contract::from_json(
+            self.web3.eth(),
+            contract_address,
+            include_bytes!("../../abi.json")
+        ).expect("Error loading contract from json!");
+        self.contract = Some(DistributedMpc::new(web3_contract, submitter));
         self.contract.unwrap()
     }
 }
\ No newline at end of file
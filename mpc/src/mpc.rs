@@ -0,0 +1,215 @@
+extern crate bincode;
+extern crate blake2_rfc;
+extern crate bn;
+extern crate byteorder;
+extern crate crossbeam;
+extern crate hex;
+extern crate rand;
+extern crate rustc_serialize;
+extern crate serde_json;
+extern crate snark;
+
+#[macro_use]
+extern crate clap;
+use clap::{App, ArgMatches};
+
+#[macro_use]
+extern crate serde_derive;
+
+#[macro_use]
+mod protocol;
+
+mod consts;
+use self::consts::*;
+
+mod export;
+
+mod witness;
+use self::witness::*;
+
+mod r1cs;
+use self::r1cs::*;
+
+use bn::*;
+use snark::{CS, ProvingKey, VerificationKey};
+use std::fmt;
+use std::fs::File;
+use std::path::Path;
+use std::process;
+
+fn existing(path: &str) -> &str {
+    if !Path::new(path).is_file() {
+        panic!("\u{274c} File {:?} does not exist", path);
+    }
+    path
+}
+
+/// Prints the error and exits.
+fn fail(e: String) -> ! {
+    println!("\u{274c} {}", e);
+    process::exit(1);
+}
+
+fn read_error<E: fmt::Display>(what: &str, path: &str, e: E) -> String {
+    format!("Error reading {} {:?}: {}", what, path, e)
+}
+
+/// Creates a proof that the witness satisfies the constraint system with
+/// the proving key at `pk_path`.
+fn create_proof(pk_path: &str, cs: &CS, witness: &Witness) -> Result<snark::Proof, String> {
+    let pk = try!(ProvingKey::read(pk_path).map_err(|e| read_error("proving key", pk_path, e)));
+    Ok(pk.prove(cs, &witness.primary, &witness.auxiliary))
+}
+
+/// Verifies the proof at `proof_path` for the public inputs with the
+/// verification key at `vk_path`.
+fn verify_proof(vk_path: &str, proof_path: &str, inputs: &[Fr]) -> Result<bool, String> {
+    let vk = try!(VerificationKey::read(vk_path).map_err(|e| read_error("verification key", vk_path, e)));
+    let proof = try!(snark::Proof::read(proof_path).map_err(|e| read_error("proof", proof_path, e)));
+    Ok(vk.verify(inputs, &proof))
+}
+
+/// Parses the public inputs, which are given in decimal.
+fn parse_inputs<'a, I: Iterator<Item = &'a str>>(values: I) -> Result<Vec<Fr>, String> {
+    values.map(|value| {
+        Fr::from_str(value).ok_or_else(|| format!("Invalid public input {:?}, expected a field element in decimal", value))
+    }).collect()
+}
+
+fn prove(matches: &ArgMatches) {
+    let pk_path = matches.value_of("pk").unwrap();
+    let r1cs_path = existing(matches.value_of("r1cs").unwrap());
+    let witness_path = matches.value_of("witness").unwrap();
+    let proof_path = matches.value_of("proof").unwrap();
+
+    println!("Reading constraint system from {:?}...", r1cs_path);
+    let cs = CS::from_path(r1cs_path);
+    let witness = Witness::read(witness_path).unwrap_or_else(|e| fail(read_error("witness", witness_path, e)));
+    witness.check(&cs);
+    println!("\u{2714} The witness satisfies the constraint system");
+
+    println!("Reading proving key from {:?}...", pk_path);
+    let proof = create_proof(pk_path, &cs, &witness).unwrap_or_else(|e| fail(e));
+    proof.write(proof_path);
+    println!("\u{2714} Wrote proof to {:?}", proof_path);
+
+    if matches.is_present("solidity") {
+        let proof = protocol::Proof::from(&proof);
+        println!("Arguments of verifyTx: {}", export::solidity::verifier_arguments(&proof, &witness.primary));
+    }
+}
+
+fn verify(matches: &ArgMatches) {
+    let vk_path = matches.value_of("vk").unwrap();
+    let proof_path = matches.value_of("proof").unwrap();
+    let inputs: Vec<Fr> = match matches.values_of("inputs") {
+        Some(values) => parse_inputs(values).unwrap_or_else(|e| fail(e)),
+        None => vec![]
+    };
+
+    if verify_proof(vk_path, proof_path, &inputs).unwrap_or_else(|e| fail(e)) {
+        println!("\u{2714} The proof is valid");
+    } else {
+        println!("\u{274c} The proof is invalid");
+        process::exit(1);
+    }
+}
+
+fn import_r1cs(matches: &ArgMatches) {
+    let out = matches.value_of("out").unwrap();
+    let open = |path: &str| File::open(existing(path)).unwrap_or_else(|e| panic!("\u{274c} Error opening {:?}: {}", path, e));
+
+    let path = matches.value_of("circom").unwrap();
+    println!("Importing constraint system from {:?}...", path);
+    let r1cs = read_circom(open(path)).unwrap_or_else(|e| panic!("\u{274c} Error importing constraint system: {}", e));
+
+    println!("\u{2714} {} constraints, {} public inputs, {} private variables", r1cs.constraints.len(), r1cs.num_inputs, r1cs.num_aux);
+    r1cs.write(out);
+
+    let cs = CS::from_path(out);
+    println!("\u{2714} Wrote constraint system to {:?} (QAP degree {})", out, cs.d);
+}
+
+fn main() {
+    let yaml = load_yaml!("../mpc.yml");
+    let matches = App::from_yaml(yaml).get_matches();
+
+    match matches.subcommand() {
+        ("prove", Some(matches)) => prove(matches),
+        ("verify", Some(matches)) => verify(matches),
+        ("import-r1cs", Some(matches)) => import_r1cs(matches),
+        _ => {
+            println!("{}", matches.usage());
+            process::exit(1);
+        }
+    }
+}
+
+#[test]
+fn prove_and_verify() {
+    use snark::Keypair;
+    use std::env;
+    use std::fs;
+
+    let dir = env::temp_dir().join(format!("mpc-{}", rand::random::<u64>()));
+    fs::create_dir(&dir).unwrap();
+    let path = |name: &str| dir.join(name).to_str().unwrap().to_string();
+
+    multiplication().write(&path("r1cs"));
+    let cs = CS::from_path(&path("r1cs"));
+    let rng = &mut rand::thread_rng();
+    let kp = Keypair::generate(
+        &cs,
+        &Fr::random(rng),
+        &Fr::random(rng),
+        &Fr::random(rng),
+        &Fr::random(rng),
+        &Fr::random(rng),
+        &Fr::random(rng),
+        &Fr::random(rng),
+        &Fr::random(rng)
+    );
+    kp.write_to(&path("pk"), &path("vk"));
+
+    let three = Fr::from_str("3").unwrap();
+    let five = Fr::from_str("5").unwrap();
+    let witness = Witness {
+        primary: vec![three * five],
+        auxiliary: vec![three, five]
+    };
+    witness.check(&cs);
+    create_proof(&path("pk"), &cs, &witness).unwrap().write(&path("proof"));
+
+    assert!(verify_proof(&path("vk"), &path("proof"), &[three * five]).unwrap());
+    assert!(!verify_proof(&path("vk"), &path("proof"), &[three]).unwrap());
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn read_errors() {
+    use std::env;
+    use std::fs;
+    use std::io::Write;
+
+    let dir = env::temp_dir().join(format!("mpc-{}", rand::random::<u64>()));
+    fs::create_dir(&dir).unwrap();
+    let path = |name: &str| dir.join(name).to_str().unwrap().to_string();
+    File::create(path("invalid")).unwrap().write_all(b"not a key").unwrap();
+
+    let cs = CS::dummy();
+    let witness = Witness {
+        primary: vec![],
+        auxiliary: vec![]
+    };
+    assert!(create_proof(&path("missing"), &cs, &witness).is_err());
+    assert!(create_proof(&path("invalid"), &cs, &witness).is_err());
+    assert!(verify_proof(&path("missing"), &path("invalid"), &[]).is_err());
+    assert!(verify_proof(&path("invalid"), &path("missing"), &[]).is_err());
+    assert!(Witness::read(&path("missing")).is_err());
+    assert!(Witness::read(&path("invalid")).is_err());
+    assert!(parse_inputs(vec!["15", "0x0f"].into_iter()).is_err());
+    assert!(parse_inputs(vec!["15"].into_iter()).unwrap() == vec![Fr::from_str("15").unwrap()]);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,532 @@
+//! Contributing from an air-gapped machine. The player's secrets never
+//! touch a networked machine:
+//!
+//! 1. `prepare-challenge` (online) joins the protocol if necessary and writes
+//!    what the player has to do in the current state of the protocol to a
+//!    challenge file, e.g. the stage to transform.
+//! 2. `contribute-offline` (offline) generates the secrets from local entropy
+//!    on first use, keeps them encrypted with a passphrase in a local file for
+//!    the later rounds, and writes the public key and the answer to the
+//!    challenge (NIZKs or the transformed stage) to a response file. The file
+//!    of the secrets is overwritten and deleted after stage 3.
+//! 3. `submit-response` (online) checks the response against the challenge,
+//!    uploads it to IPFS and publishes it to the contract.
+//!
+//! This is repeated for every state of the protocol the player takes part in.
+//! Both machines print a fingerprint of the response, so the player can check
+//! that the response was not altered in transit.
+
+use bincode::SizeLimit::Infinite;
+use bincode::rustc_serialize::{decode_from, encode_into};
+use clap::ArgMatches;
+use crypto::aes::{self, KeySize};
+use crypto::scrypt::{scrypt, ScryptParams};
+use crypto::symmetriccipher::SynchronousStreamCipher;
+use rand::{self, OsRng, Rng, SeedableRng};
+use rustc_serialize::{Encodable, Decodable};
+use snark::CS;
+use web3::Web3;
+use web3::types::{Address, H256};
+use sha3::{Digest, Keccak256};
+use web3::Transport;
+
+use std::env::var;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::time::Duration;
+
+use blockchain::*;
+use consts::SECRETS_PASSWORD_ENV_KEY;
+use contribution::Statement;
+use dist_files::*;
+use entropy::get_entropy;
+use fork::{await_beacon, get_fork, seed_stage, stage_beacon};
+use manager::Manager;
+use protocol::*;
+use receipt::fingerprint;
+use super::{create_stage1, download_final_stage, fetch_all_commitments, get_current_state, get_current_turn,
+            get_exclusion_state, get_players, local_signer, nizk_transcript, player_joined_cb, publish_initial_stage, publish_transformation, transaction_policy, upload_object, verify_initial_stage,
+            verify_received_stage};
+
+/// What the player has to do in the current state of the protocol.
+#[derive(RustcEncodable, RustcDecodable)]
+pub enum Task {
+    Commit,
+    Reveal,
+    Nizks(NizkTranscript),
+    Stage1(Stage1Contents),
+    Stage2(Stage2Contents),
+    Stage3(Stage3Contents)
+}
+
+#[derive(RustcEncodable, RustcDecodable)]
+pub struct Challenge {
+    contract: [u8; 20],
+    player: [u8; 20],
+    task: Task
+}
+
+/// The answer to a task. The transformed stages come with the statement the
+/// player signs when the response is submitted.
+#[derive(RustcEncodable, RustcDecodable)]
+pub enum Answer {
+    PublicKey,
+    Nizks(PublicKeyNizks),
+    Stage1(Stage1Contents, Statement),
+    Stage2(Stage2Contents, Statement),
+    Stage3(Stage3Contents, Statement)
+}
+
+#[derive(RustcEncodable, RustcDecodable)]
+pub struct Response {
+    contract: [u8; 20],
+    player: [u8; 20],
+    public_key: PublicKey,
+    answer: Answer,
+    fingerprint: String
+}
+
+/// The secrets of the player, which are kept on the offline machine, or by
+/// the online player to resume after a restart, for the whole protocol. They
+/// are bound to the contract, so they are never reused in another ceremony.
+pub struct Secrets {
+    pub contract: [u8; 20],
+    pub privkey: PrivateKey,
+    pub pubkey: PublicKey
+}
+
+/// The secrets as they are written to disk: the private key is encrypted
+/// with AES-128-CTR under a key derived from the passphrase with scrypt, and
+/// authenticated with a Keccak MAC, like the key of a keystore.
+#[derive(RustcEncodable, RustcDecodable)]
+struct SealedSecrets {
+    contract: [u8; 20],
+    pubkey: PublicKey,
+    scrypt_log_n: u8,
+    salt: [u8; 32],
+    iv: [u8; 16],
+    ciphertext: Vec<u8>,
+    mac: [u8; 32]
+}
+
+/// The cost of scrypt for new secrets, 2^18 as geth uses it.
+const SCRYPT_LOG_N: u8 = 18;
+
+fn derive_secrets_key(passphrase: &str, salt: &[u8], log_n: u8) -> Protected<[u8; 32]> {
+    let mut key = Protected::new([0u8; 32]);
+    scrypt(passphrase.as_bytes(), salt, &ScryptParams::new(log_n, 8, 1), &mut key[..]);
+    key
+}
+
+fn secrets_mac(key: &[u8; 32], ciphertext: &[u8]) -> [u8; 32] {
+    let mut hasher = Keccak256::new();
+    hasher.input(&key[16..]);
+    hasher.input(ciphertext);
+    let mut mac = [0u8; 32];
+    mac.copy_from_slice(hasher.result().as_slice());
+    mac
+}
+
+fn seal(secrets: &Secrets, passphrase: &str, log_n: u8) -> SealedSecrets {
+    let mut rng = OsRng::new().expect("Error opening the generator of the operating system!");
+    let mut salt = [0u8; 32];
+    let mut iv = [0u8; 16];
+    rng.fill_bytes(&mut salt);
+    rng.fill_bytes(&mut iv);
+
+    let key = derive_secrets_key(passphrase, &salt, log_n);
+    let mut ciphertext = vec![0u8; PRIVATE_KEY_BYTES];
+    aes::ctr(KeySize::KeySize128, &key[..16], &iv).process(&secrets.privkey.to_bytes()[..], &mut ciphertext);
+    SealedSecrets {
+        contract: secrets.contract,
+        pubkey: secrets.pubkey.clone(),
+        scrypt_log_n: log_n,
+        salt: salt,
+        iv: iv,
+        mac: secrets_mac(&key, &ciphertext),
+        ciphertext: ciphertext
+    }
+}
+
+fn unseal(sealed: SealedSecrets, passphrase: &str) -> Result<Secrets, String> {
+    if sealed.ciphertext.len() != PRIVATE_KEY_BYTES {
+        return Err("The file does not hold the secrets of a player".into());
+    }
+    let key = derive_secrets_key(passphrase, &sealed.salt, sealed.scrypt_log_n);
+    if secrets_mac(&key, &sealed.ciphertext) != sealed.mac {
+        return Err("Wrong passphrase, or the secrets are corrupt".into());
+    }
+
+    let mut bytes = Protected::new([0u8; PRIVATE_KEY_BYTES]);
+    aes::ctr(KeySize::KeySize128, &key[..16], &sealed.iv).process(&sealed.ciphertext, &mut bytes[..]);
+    let privkey = try!(PrivateKey::from_bytes(&bytes).ok_or_else(|| "The secrets are corrupt".to_string()));
+    Ok(Secrets {
+        contract: sealed.contract,
+        privkey: privkey,
+        pubkey: sealed.pubkey
+    })
+}
+
+/// Overwrites the file with zeros before it is deleted, so the secrets
+/// cannot be recovered from the disk.
+pub fn destroy_file(path: &str) {
+    let len = fs::metadata(path).map(|m| m.len()).unwrap_or_else(|e| panic!("\u{274c} Error reading {:?}: {}", path, e));
+    let mut file = OpenOptions::new().write(true).open(path).unwrap_or_else(|e| panic!("\u{274c} Error opening {:?}: {}", path, e));
+    file.write_all(&vec![0u8; len as usize])
+        .and_then(|_| file.sync_all())
+        .unwrap_or_else(|e| panic!("\u{274c} Error overwriting {:?}: {}", path, e));
+    fs::remove_file(path).unwrap_or_else(|e| panic!("\u{274c} Error deleting {:?}: {}", path, e));
+}
+
+fn write_object<E: Encodable>(obj: &E, path: &str) {
+    let mut file = File::create(path).unwrap_or_else(|e| panic!("\u{274c} Error creating {:?}: {}", path, e));
+    encode_into(obj, &mut file, Infinite).unwrap_or_else(|e| panic!("\u{274c} Error writing {:?}: {}", path, e));
+}
+
+fn read_object<D: Decodable>(path: &str) -> D {
+    let mut file = File::open(path).unwrap_or_else(|e| panic!("\u{274c} Error opening {:?}: {}", path, e));
+    decode_from(&mut file, Infinite).unwrap_or_else(|e| panic!("\u{274c} Error reading {:?}: {}", path, e))
+}
+
+fn connect<T: Transport>(matches: &ArgMatches, host: &str, web3: &Web3<T>) -> (DistributedMpc<T>, IPFSWrapper) {
+    let manager: Manager<T> = Manager::new(web3.clone(), format!("http://{}", host).as_str(), 5001).with_signer(local_signer(matches, web3)).with_policy(transaction_policy(matches));
+    let ipfs: IPFSWrapper = IPFSWrapper::new(format!("http://{}", host).as_str(), 5001);
+    let contract = manager.init_contract(matches.value_of("account"), matches.value_of("contract").unwrap());
+        println!("Your account used: {:?}", contract.account());
+        println!("Contract address: {:?}", contract.address());
+    (contract, ipfs)
+}
+
+fn player_index(players: &[Address], account: Address) -> usize {
+    players.iter().position(|p| *p == account).unwrap_or_else(|| panic!("\u{274c} {:?} is not a player in the protocol!", account))
+}
+
+fn download_cs<T: Transport>(contract: &DistributedMpc<T>, ipfs: &mut IPFSWrapper) -> CS {
+    let cs_hash = contract.get_constraint_system();
+    ipfs.download_cs(String::from_utf8(cs_hash).expect("Not a valid utf8 string").as_str())
+}
+
+/// The stage the player has to transform: the initial stage for the
+/// coordinator, otherwise the verified result of the last player who
+/// published one.
+fn stage_to_transform<S, T>(contract: &DistributedMpc<T>, stage_index: u64, players: &[Address], account: Address, initial: S, verify_chain: bool, ipfs: &mut IPFSWrapper) -> S where
+    S: Transform + Verify + Clone + Encodable + Decodable,
+    T: Transport
+{
+    let index = player_index(players, account);
+    if index == 0 {
+        return initial;
+    }
+
+    if get_current_turn(contract) != index {
+        panic!("\u{274c} It is not your turn to transform stage {} yet. Try again later.", stage_index + 1);
+    }
+    let stage_hash = contract.get_latest_transformation();
+    verify_initial_stage(contract, stage_index, &initial, ipfs);
+    let stage: S = ipfs.download_stage(String::from_utf8(stage_hash).expect("Should be valid IPFS hash").as_str());
+    verify_received_stage(contract, stage_index, players, account, &stage, verify_chain, ipfs);
+    stage
+}
+
+/// Writes the challenge for the current state of the protocol.
+pub fn prepare_challenge(matches: &ArgMatches, host: &str) {
+    with_web3!(web3_endpoint(host), web3 => write_challenge(matches, host, web3))
+}
+
+fn write_challenge<T: EventTransport>(matches: &ArgMatches, host: &str, web3: Web3<T>) {
+    let path = matches.value_of("challenge").unwrap();
+    let powers_of_tau = matches.value_of("powersoftau");
+    let verify_chain = matches.is_present("verify-chain");
+    let poll_interval = Duration::new(1, 0);
+
+    let (contract, mut ipfs) = connect(matches, host, &web3);
+    let account = contract.account();
+    let state = get_current_state(&contract);
+
+    let mut players = get_players(&contract);
+    if !players.contains(&account) {
+        if state != 0 {
+            panic!("\u{274c} The protocol has already started without you!");
+        }
+            println!("Welcome new player! Joining now...");
+        let mut player_joined_filter = EventFilterBuilder::new(web3.clone(), contract.address(), replay_start(&contract, CURSOR_FILE)).create_filter("Waiting for player joining...".into(), player_joined_cb, Some(account));
+        contract.join();
+        player_joined_filter.await(&poll_interval);
+        players = get_players(&contract);
+    }
+    let index = player_index(&players, account);
+    require_not_excluded(&contract, index);
+    let fork = get_fork(&contract);
+
+    let task = match state {
+        0 | 1 => Task::Commit,
+        2 => Task::Reveal,
+        3 => {
+            let hash_of_all_commitments = Digest512::from(&fetch_all_commitments(&contract, players.clone())).unwrap();
+            Task::Nizks(nizk_transcript(&contract, chain_id(&web3), account, index, &hash_of_all_commitments))
+        },
+        4 => {
+            let cs = download_cs(&contract, &mut ipfs);
+            let initial = seed_stage(&web3, &contract, &fork, 0, &mut ipfs).unwrap_or_else(|| create_stage1(&contract, &cs, powers_of_tau));
+            Task::Stage1(stage_to_transform(&contract, 0, &players, account, initial, verify_chain, &mut ipfs))
+        },
+        5 => {
+            let initial = match seed_stage(&web3, &contract, &fork, 1, &mut ipfs) {
+                Some(seed) => seed,
+                None => {
+                    let cs = download_cs(&contract, &mut ipfs);
+                    await_beacon(&web3, &contract, 0, index as u64, &poll_interval);
+                    let beacon = stage_beacon(&web3, &contract, 0);
+                    let final_stage1: Stage1Contents = download_final_stage(&contract, 0, &players, &beacon, &mut ipfs);
+                    Stage2Contents::new(&cs, &final_stage1)
+                }
+            };
+            Task::Stage2(stage_to_transform(&contract, 1, &players, account, initial, verify_chain, &mut ipfs))
+        },
+        6 => {
+            let initial = match seed_stage(&web3, &contract, &fork, 2, &mut ipfs) {
+                Some(seed) => seed,
+                None => {
+                    let cs = download_cs(&contract, &mut ipfs);
+                    await_beacon(&web3, &contract, 1, index as u64, &poll_interval);
+                    let beacon = stage_beacon(&web3, &contract, 1);
+                    let final_stage2: Stage2Contents = download_final_stage(&contract, 1, &players, &beacon, &mut ipfs);
+                    Stage3Contents::new(&cs, &final_stage2)
+                }
+            };
+            Task::Stage3(stage_to_transform(&contract, 2, &players, account, initial, verify_chain, &mut ipfs))
+        },
+        7 => {
+            await_beacon(&web3, &contract, 2, index as u64, &poll_interval);
+                println!("\u{2714} The random beacon of stage 3 is recorded. The protocol is finished.");
+            return;
+        },
+        _ => panic!("\u{274c} The protocol is finished, there is nothing left to contribute.")
+    };
+
+    write_object(&Challenge {
+        contract: contract.address().0,
+        player: account.0,
+        task: task
+    }, path);
+        println!("\u{2714} Wrote challenge for state {} to {:?}. Copy it to the offline machine and run contribute-offline.", state, path);
+}
+
+/// The passphrase of the secrets, from `DIST_MPC_SECRETS_PASSWORD` or asked
+/// for on the terminal.
+fn secrets_passphrase(message: &str) -> String {
+    var(SECRETS_PASSWORD_ENV_KEY).unwrap_or_else(|_| read_password(message))
+}
+
+/// Loads the secrets for the contract, or generates them from local entropy
+/// if there are none yet.
+pub fn load_secrets(matches: &ArgMatches, path: &str, contract: [u8; 20]) -> Secrets {
+    if Path::new(path).is_file() {
+        let sealed: SealedSecrets = read_object(path);
+        if sealed.contract != contract {
+            panic!("\u{274c} The secrets in {:?} belong to another ceremony! Use a different path.", path);
+        }
+        let mut passphrase = secrets_passphrase(&format!("Passphrase of the secrets in {:?}: ", path));
+        let secrets = unseal(sealed, &passphrase);
+        zeroize_string(&mut passphrase);
+        return secrets.unwrap_or_else(|e| panic!("\u{274c} Error decrypting the secrets in {:?}: {}", path, e));
+    }
+
+        println!("Generating secrets from local entropy...");
+    let mut rng = Protected::new(rand::chacha::ChaChaRng::from_seed(&get_entropy(matches)[..]));
+    let privkey = PrivateKey::new(&mut *rng);
+    let pubkey = privkey.pubkey(&mut *rng);
+    let secrets = Secrets {
+        contract: contract,
+        privkey: privkey,
+        pubkey: pubkey
+    };
+    let mut passphrase = secrets_passphrase("Passphrase to encrypt the secrets with: ");
+    if var(SECRETS_PASSWORD_ENV_KEY).is_err() {
+        let mut confirmation = read_password("Repeat the passphrase: ");
+        let matching = confirmation == passphrase;
+        zeroize_string(&mut confirmation);
+        if !matching {
+            zeroize_string(&mut passphrase);
+            panic!("\u{274c} The passphrases do not match!");
+        }
+    }
+    write_object(&seal(&secrets, &passphrase, SCRYPT_LOG_N), path);
+    zeroize_string(&mut passphrase);
+        println!("\u{2714} Wrote encrypted secrets to {:?}. Keep the file, it is destroyed after stage 3.", path);
+    secrets
+}
+
+fn transform_offline<S>(stage: &S, stage_index: u64, contract: [u8; 20], secrets: &Secrets) -> (S, Statement) where
+    S: Transform + Verify + Clone + Encodable
+{
+    let mut transformed = stage.clone();
+        println!("Transforming stage {}...", stage_index + 1);
+    transformed.transform(&secrets.privkey);
+    assert!(transformed.verify_transform(stage, &secrets.pubkey), "Invalid stage transformation!");
+    let statement = Statement::new(Address::from(contract), stage_index, stage, &transformed, &secrets.pubkey);
+    (transformed, statement)
+}
+
+/// Answers the challenge with the local secrets, without any network access.
+pub fn contribute_offline(matches: &ArgMatches) {
+    let challenge: Challenge = read_object(matches.value_of("challenge").unwrap());
+    let response_path = matches.value_of("response").unwrap();
+    let secrets_path = matches.value_of("secrets").unwrap();
+    let secrets = load_secrets(matches, secrets_path, challenge.contract);
+    let commitment = secrets.pubkey.hash().to_string();
+
+    let (answer, fingerprint) = match challenge.task {
+        Task::Commit | Task::Reveal => (Answer::PublicKey, commitment),
+        Task::Nizks(ref transcript) => {
+            let mut rng = Protected::new(rand::chacha::ChaChaRng::from_seed(&get_entropy(matches)[..]));
+            (Answer::Nizks(secrets.pubkey.nizks(&mut *rng, &secrets.privkey, transcript)), commitment)
+        },
+        Task::Stage1(ref stage) => {
+            let (transformed, statement) = transform_offline(stage, 0, challenge.contract, &secrets);
+            let fingerprint = fingerprint(&statement);
+            (Answer::Stage1(transformed, statement), fingerprint)
+        },
+        Task::Stage2(ref stage) => {
+            let (transformed, statement) = transform_offline(stage, 1, challenge.contract, &secrets);
+            let fingerprint = fingerprint(&statement);
+            (Answer::Stage2(transformed, statement), fingerprint)
+        },
+        Task::Stage3(ref stage) => {
+            let (transformed, statement) = transform_offline(stage, 2, challenge.contract, &secrets);
+            let fingerprint = fingerprint(&statement);
+            (Answer::Stage3(transformed, statement), fingerprint)
+        }
+    };
+
+    write_object(&Response {
+        contract: challenge.contract,
+        player: challenge.player,
+        public_key: secrets.pubkey.clone(),
+        answer: answer,
+        fingerprint: fingerprint.clone()
+    }, response_path);
+        println!("\u{2714} Wrote response to {:?}. Fingerprint: {}", response_path, fingerprint);
+        println!("Copy the response to the online machine and run submit-response. It must print the same fingerprint.");
+
+    // The secrets are not needed after stage 3, and the response can be
+    // submitted again without them.
+    if let Task::Stage3(_) = challenge.task {
+        destroy_file(secrets_path);
+            println!("\u{2714} Destroyed the secrets in {:?}.", secrets_path);
+    }
+}
+
+fn require_state<T: Transport>(contract: &DistributedMpc<T>, expected: u64) {
+    let state = get_current_state(contract);
+    if state != expected {
+        panic!("\u{274c} The protocol is in state {}, but the response is for state {}!", state, expected);
+    }
+}
+
+fn require_not_excluded<T: Transport>(contract: &DistributedMpc<T>, index: usize) {
+    if get_exclusion_state(contract, index) != 0 {
+        panic!("\u{274c} You have been excluded from the protocol because you missed a deadline.");
+    }
+}
+
+fn require_commitment<T: Transport>(contract: &DistributedMpc<T>, pubkey: &PublicKey) {
+    let commitment = contract.get_commitment(contract.account());
+    if Digest256(commitment) != pubkey.hash() {
+        panic!("\u{274c} The public key of the response does not match your commitment!");
+    }
+}
+
+fn submit_stage<S, T>(prev_stage: &S, stage: &S, statement: &Statement, stage_index: u64, player_index: usize, pubkey: &PublicKey, web3: &Web3<T>, contract: &DistributedMpc<T>, ipfs: &mut IPFSWrapper) -> H256 where
+    S: Transform + Verify + Clone + Encodable + Decodable,
+    T: Transport
+{
+    require_state(contract, 4 + stage_index);
+    require_commitment(contract, pubkey);
+    if !stage.verify_transform(prev_stage, pubkey) {
+        panic!("\u{274c} The stage of the response is not a valid transformation of the challenge!");
+    }
+    if *statement != Statement::new(contract.address(), stage_index, prev_stage, stage, pubkey) {
+        panic!("\u{274c} The statement of the response does not match the transformation!");
+    }
+
+    let file_name = format!("stage{}", stage_index + 1);
+    if player_index == 0 {
+        publish_initial_stage(prev_stage, stage, stage_index, pubkey, web3, contract, &file_name, ipfs)
+    } else {
+        publish_transformation(prev_stage, stage, stage_index, pubkey, web3, contract, &format!("{}_transformed", file_name), ipfs)
+    }
+}
+
+/// Checks the response against its challenge and publishes it.
+pub fn submit_response(matches: &ArgMatches, host: &str) {
+    with_web3!(web3_endpoint(host), web3 => publish_response(matches, host, web3))
+}
+
+fn publish_response<T: Transport>(matches: &ArgMatches, host: &str, web3: Web3<T>) {
+    let challenge: Challenge = read_object(matches.value_of("challenge").unwrap());
+    let mut response: Response = read_object(matches.value_of("response").unwrap());
+
+    let (contract, mut ipfs) = connect(matches, host, &web3);
+    let account = contract.account();
+    if challenge.contract != contract.address().0 || response.contract != challenge.contract {
+        panic!("\u{274c} The response belongs to another ceremony!");
+    }
+    if challenge.player != account.0 || response.player != challenge.player {
+        panic!("\u{274c} The response belongs to another player!");
+    }
+    let index = player_index(&get_players(&contract), account);
+    require_not_excluded(&contract, index);
+        println!("Submitting response with fingerprint {}", response.fingerprint);
+
+    let transaction_hash = match (&challenge.task, &mut response.answer) {
+        (&Task::Commit, &mut Answer::PublicKey) => {
+            require_state(&contract, if index == 0 { 0 } else { 1 });
+            contract.commit(response.public_key.hash().0).transaction_hash
+        },
+        (&Task::Reveal, &mut Answer::PublicKey) => {
+            require_state(&contract, 2);
+            require_commitment(&contract, &response.public_key);
+            upload_object(&mut response.public_key, &contract, DistributedMpc::reveal_commitment, "publicKey", &mut ipfs)
+        },
+        (&Task::Nizks(ref transcript), &mut Answer::Nizks(ref mut nizks)) => {
+            require_state(&contract, 3);
+            require_commitment(&contract, &response.public_key);
+            if !nizks.is_valid(&response.public_key, transcript) {
+                panic!("\u{274c} The NIZKs of the response are invalid!");
+            }
+            upload_object(nizks, &contract, DistributedMpc::publish_nizks, "nizks", &mut ipfs)
+        },
+        (&Task::Stage1(ref prev_stage), &mut Answer::Stage1(ref stage, ref statement)) =>
+            submit_stage(prev_stage, stage, statement, 0, index, &response.public_key, &web3, &contract, &mut ipfs),
+        (&Task::Stage2(ref prev_stage), &mut Answer::Stage2(ref stage, ref statement)) =>
+            submit_stage(prev_stage, stage, statement, 1, index, &response.public_key, &web3, &contract, &mut ipfs),
+        (&Task::Stage3(ref prev_stage), &mut Answer::Stage3(ref stage, ref statement)) =>
+            submit_stage(prev_stage, stage, statement, 2, index, &response.public_key, &web3, &contract, &mut ipfs),
+        _ => panic!("\u{274c} The response does not answer the challenge!")
+    };
+        println!("\u{2714} Submitted response (transaction {:?}). Fingerprint: {}", transaction_hash, response.fingerprint);
+}
+
+#[test]
+fn sealed_secrets() {
+    let rng = &mut rand::thread_rng();
+    let privkey = PrivateKey::new(rng);
+    let pubkey = privkey.pubkey(rng);
+    let secrets = Secrets {
+        contract: [7u8; 20],
+        privkey: privkey,
+        pubkey: pubkey
+    };
+
+    let unsealed = unseal(seal(&secrets, "passphrase", 4), "passphrase").unwrap();
+    assert_eq!(unsealed.contract, secrets.contract);
+    assert!(unsealed.pubkey == secrets.pubkey);
+    assert_eq!(&unsealed.privkey.to_bytes()[..], &secrets.privkey.to_bytes()[..]);
+
+    assert!(unseal(seal(&secrets, "passphrase", 4), "wrong").is_err());
+    let mut tampered = seal(&secrets, "passphrase", 4);
+    tampered.ciphertext[0] ^= 1;
+    assert!(unseal(tampered, "passphrase").is_err());
+}
@@ -3,13 +3,16 @@ extern crate blake2_rfc;
 extern crate bn;
 extern crate byteorder;
 extern crate crossbeam;
+extern crate crypto;
 extern crate ethabi;
 extern crate ethereum_types;
 extern crate hex;
 extern crate ipfs_api;
-extern crate json;
 extern crate rand;
+extern crate rpassword;
 extern crate rustc_serialize;
+extern crate secp256k1;
+extern crate serde;
 extern crate serde_json;
 extern crate sha3;
 extern crate spinner;
@@ -18,7 +21,7 @@ extern crate web3;
 
 #[macro_use]
 extern crate clap;
-use clap::{App};
+use clap::{App, ArgMatches};
 
 #[macro_use]
 extern crate serde_derive; 
@@ -31,6 +34,7 @@ mod protocol;
 use self::protocol::*;
 use protocol::{Transform, Verify};
 
+#[macro_use]
 mod blockchain;
 use self::blockchain::*;
 
@@ -43,105 +47,192 @@ use self::dist_files::*;
 mod consts;
 use self::consts::*;
 
+mod contribution;
+use self::contribution::*;
+
+mod receipt;
+use self::receipt::*;
+
+mod entropy;
+use self::entropy::get_entropy;
+
+mod offline;
+
+mod fork;
+use self::fork::*;
+
 use spinner::SpinnerBuilder;
 use rustc_serialize::{Encodable, Decodable};
 
 use ethereum_types::{Address, H256, U256};
 use web3::api::Eth;
-use web3::contract::tokens::{Tokenize};
 use web3::futures::Future;
-use web3::types::{Log, TransactionReceipt};
+use web3::types::{TransactionReceipt};
 use web3::{Transport, Web3};
-use web3::transports::Http;
 
-use rand::{SeedableRng, Rng};
+use rand::SeedableRng;
 
 use std::time::{Duration, Instant};
-use std::io::{self};
 use std::fs::File;
+use std::io::{self};
+use std::path::Path;
 use std::env::var;
+use std::rc::Rc;
 
 use time::Duration as MDuration;
 
-fn get_entropy() -> [u32; 8] {
-    use blake2_rfc::blake2s::blake2s;
+/// Creates stage 1, from the powers of tau file if the ceremony imports one.
+/// The file has to have the hash recorded in the contract.
+fn create_stage1<T: Transport>(contract: &DistributedMpc<T>, cs: &CS, powers_of_tau: Option<&str>) -> Stage1Contents {
+    let hash = contract.powers_of_tau();
+    match (powers_of_tau, hash == [0; 32]) {
+        (Some(path), false) => {
+                println!("Importing powers of tau from {:?}...", path);
+            Stage1Contents::from_powers_of_tau(cs, path, &hash).unwrap_or_else(|e| panic!("\u{274c} Error importing powers of tau from {:?}: {}", path, e))
+        },
+        (None, true) => Stage1Contents::new(cs),
+        (Some(_), true) => panic!("\u{274c} The ceremony does not import powers of tau, run it without -t!"),
+        (None, false) => panic!("\u{274c} The ceremony imports stage 1 from the powers of tau file with hash 0x{}, pass it with -t!", hex::encode(&hash[..]))
+    }
+}
 
-    let mut v: Vec<u8> = vec![];
+fn get_current_state<T: Transport>(contract: &DistributedMpc<T>) -> u64 {
+    contract.current_state().low_u64()
+}
 
-    {
-        let input_from_user = prompt(
-            "Please type a random string of text and then press [ENTER] to provide additional entropy."
-        );
+/// Downloads the stage whose IPFS hash the query returns.
+fn download_stage<Q, S, T>(contract: &DistributedMpc<T>, query: Q, ipfs: &mut IPFSWrapper) -> S where 
+    Q: FnOnce(&DistributedMpc<T>) -> Vec<u8>,
+    S: Transform + Verify + Clone + Encodable + Decodable,
+    T: Transport
+{
+    let spinner = SpinnerBuilder::new("Querying stage hash from Ethereum...".into()).spinner(spinner::DANCING_KIRBY.to_vec()).step(Duration::from_millis(500)).start();
+    let stage_hash = query(contract);
+    spinner.message(format!("Downloading stage from IPFS (hash: {:?})", String::from_utf8(stage_hash.clone()).unwrap()));
+    let stage = ipfs.download_stage(String::from_utf8(stage_hash).unwrap().as_str());
+    spinner.close();
+    stage
+}
 
-        let hash = blake2s(32, &[], input_from_user.as_bytes());
+fn download_public_key<T: Transport>(contract: &DistributedMpc<T>, player_index: u64, ipfs: &mut IPFSWrapper) -> PublicKey {
+    let pubkey_bin = contract.get_public_key(player_index);
+    ipfs.download_object(String::from_utf8(pubkey_bin).expect("Should be valid IPFS hash!").as_str())
+}
 
-        v.extend_from_slice(hash.as_bytes());
-    }
+/// Verifies the stage received from the previous player against its public
+/// key and the stage it transformed, or with `verify_chain` all transformations
+/// of the stage so far. Panics, naming the player to blame, if one is invalid.
+/// Players excluded from the stage are skipped.
+fn verify_received_stage<S, T>(contract: &DistributedMpc<T>, stage_index: u64, players: &[Address], player: Address, received: &S, verify_chain: bool, ipfs: &mut IPFSWrapper) where
+    S: Transform + Verify + Clone + Encodable + Decodable,
+    T: Transport
+{
+    let stage_players = get_players_in_state(contract, 4 + stage_index, players);
+    let position = stage_players.iter().position(|i| players[*i] == player).expect("Should take part in the stage!");
+    assert!(position > 0, "The coordinator does not receive a stage!");
+    let first = if verify_chain { 0 } else { position - 1 };
 
-        println!("Please wait while Linux fills up its entropy pool...");
-    
-    {
-        let wait_start = Instant::now();
-        let mut linux_rng = rand::read::ReadRng::new(File::open("/dev/random").unwrap());
-        for _ in 0..32 {
-            v.push(linux_rng.gen());
-        }
-        if PERFORM_MEASUREMENTS {
-            let duration = MDuration::from_std(wait_start.elapsed());
-            if duration.is_ok() {
-                unsafe {
-                    INPUT_OVERHEAD_MS += duration.unwrap().num_milliseconds();
-                }
-            } else {
-                    println!("Error in time measurement: Overflow in duration");
-            }
+    let mut prior: S = if first == 0 {
+        download_stage(contract, |contract| contract.get_initial_stage(stage_index), ipfs)
+    } else {
+        download_stage(contract, |contract| contract.get_transformation(stage_index, stage_players[first - 1] as u64), ipfs)
+    };
+    for j in first..position {
+        let i = stage_players[j];
+        let pubkey = download_public_key(contract, i as u64, ipfs);
+        let next: S = if j == position - 1 {
+            received.clone()
+        } else {
+            download_stage(contract, |contract| contract.get_transformation(stage_index, i as u64), ipfs)
+        };
+        if !next.verify_transform(&prior, &pubkey) {
+            panic!("\u{274c} Invalid stage {} transformation from player {} ({:?})! Refusing to build on it.", stage_index + 1, i, players[i]);
         }
+            println!("\u{2714} Stage {} has been transformed correctly by player {}", stage_index + 1, i);
+        prior = next;
     }
+}
 
-    assert_eq!(v.len(), 64);
-
-    let hash = blake2s(32, &[], &v);
-    let hash = hash.as_bytes();
-
-    let mut seed: [u32; 8] = [0; 8];
-
-    for i in 0..8 {
-        use byteorder::{ByteOrder, LittleEndian};
+/// Compares the initial stage published by the coordinator with the one
+/// recomputed by this player, and aborts if they differ.
+fn verify_initial_stage<S, T>(contract: &DistributedMpc<T>, stage_index: u64, recomputed: &S, ipfs: &mut IPFSWrapper) where
+    S: Transform + Verify + Clone + Encodable + Decodable,
+    T: Transport
+{
+    let published: S = download_stage(contract, |contract| contract.get_initial_stage(stage_index), ipfs);
+    if Digest512::from(&published).expect("Stage should be serializable!") != Digest512::from(recomputed).expect("Stage should be serializable!") {
+        panic!("\u{274c} The initial stage {} published by the coordinator does not match the recomputed stage! Aborting.", stage_index + 1);
+    }
+        println!("\u{2714} The initial stage {} published by the coordinator is correct", stage_index + 1);
+}
 
-        seed[i] = LittleEndian::read_u32(&hash[(i*4)..]);
+/// The result of the last player for the stage, with the random beacon
+/// applied if there is one.
+fn download_final_stage<S, T>(contract: &DistributedMpc<T>, stage_index: u64, players: &[Address], beacon: &Option<Beacon>, ipfs: &mut IPFSWrapper) -> S where
+    S: Transform + Verify + Clone + Encodable + Decodable,
+    T: Transport
+{
+    let last = *get_players_in_state(contract, 4 + stage_index, players).last().expect("The coordinator takes part in every stage!");
+    let stage: S = download_stage(contract, |contract| contract.get_transformation(stage_index, last as u64), ipfs);
+    match *beacon {
+        Some(ref beacon) => beacon.apply(&stage),
+        None => stage
     }
+}
 
-    seed
+/// Signs the statement about the transformation of `prev_stage` into `stage`
+/// and uploads the contribution to IPFS. Returns the statement and the IPFS
+/// hash of the contribution.
+fn sign_contribution<S, T>(web3: &Web3<T>, contract: &DistributedMpc<T>, stage_index: u64, prev_stage: &S, stage: &S, pubkey: &PublicKey, file_name: &str, ipfs: &mut IPFSWrapper) -> (Statement, String) where
+    S: Encodable,
+    T: Transport
+{
+    let statement = Statement::new(contract.address(), stage_index, prev_stage, stage, pubkey);
+    let contribution = Contribution::sign(web3, contract, statement.clone());
+    (statement, ipfs.upload_object(&contribution, format!("{}_contribution", file_name).as_str()).hash)
 }
 
-fn get_current_state<T: Transport>(contract: &ContractWrapper<T>) -> u64 {
-    let current_state: U256 = contract.query("currentState", ());
-    current_state.low_u64()
+/// Appends the receipt of the contribution to the local receipts and prints
+/// its fingerprint.
+fn write_receipt(statement: &Statement, stage_ipfs: &str, contribution_ipfs: &str, transaction_hash: H256) {
+    let receipt = append_receipt(RECEIPTS_FILE, statement, stage_ipfs, contribution_ipfs, transaction_hash);
+        println!("\u{2714} Stage {} contribution published. Fingerprint: {} (receipt in {:?})", statement.stage, receipt.fingerprint, RECEIPTS_FILE);
 }
 
-fn to_bytes_fixed(vec: &Vec<u8>) -> [u8; 32] {
-    let mut arr = [0; 32];
-    assert_eq!(32, vec.len());;
-    for i in 0..vec.len() {
-        arr[i] = vec[i];
-    }
-    arr
+/// Publishes the transformation of `prev_stage` into `stage` along with the
+/// signed contribution.
+fn publish_transformation<S, T>(prev_stage: &S, stage: &S, stage_index: u64, pubkey: &PublicKey, web3: &Web3<T>, contract: &DistributedMpc<T>, file_name: &str, ipfs: &mut IPFSWrapper) -> H256 where
+    S: Encodable,
+    T: Transport
+{
+    let spinner = SpinnerBuilder::new("Signing contribution and uploading it with the transformation to IPFS...".into()).spinner(spinner::DANCING_KIRBY.to_vec()).step(Duration::from_millis(500)).start();
+    let (statement, contribution_ipfs) = sign_contribution(web3, contract, stage_index, prev_stage, stage, pubkey, file_name, ipfs);
+    let stage_ipfs = ipfs.upload_object(stage, file_name);
+    spinner.message("Publishing transformation and contribution hashes to Ethereum...".into());
+    let transaction_hash = contract.publish_stage_results(stage_ipfs.hash.clone().into_bytes(), contribution_ipfs.clone().into_bytes()).transaction_hash;
+    spinner.close();
+    write_receipt(&statement, &stage_ipfs.hash, &contribution_ipfs, transaction_hash);
+    transaction_hash
 }
 
-fn download_stage<P, S, T>(contract: &ContractWrapper<T>, method: &str, params: P, ipfs: &mut IPFSWrapper) -> S where 
-    P: Tokenize,
-    S: Transform + Verify + Clone + Encodable + Decodable,
+/// Publishes the initial stage `prev_stage` and the coordinator's
+/// transformation of it into `stage` along with the signed contribution.
+fn publish_initial_stage<S, T>(prev_stage: &S, stage: &S, stage_index: u64, pubkey: &PublicKey, web3: &Web3<T>, contract: &DistributedMpc<T>, file_name: &str, ipfs: &mut IPFSWrapper) -> H256 where
+    S: Encodable,
     T: Transport
 {
-    let spinner = SpinnerBuilder::new("Querying stage hash from Ethereum...".into()).spinner(spinner::DANCING_KIRBY.to_vec()).step(Duration::from_millis(500)).start();
-    let stage_hash: Vec<u8> = contract.query(method, params);
-    spinner.message(format!("Downloading stage from IPFS (hash: {:?})", String::from_utf8(stage_hash.clone()).unwrap()));
-    let stage = ipfs.download_stage(String::from_utf8(stage_hash).unwrap().as_str());
+    let spinner = SpinnerBuilder::new("Signing contribution and uploading it with the stage and transformation to IPFS...".into()).spinner(spinner::DANCING_KIRBY.to_vec()).step(Duration::from_millis(500)).start();
+    let (statement, contribution_ipfs) = sign_contribution(web3, contract, stage_index, prev_stage, stage, pubkey, file_name, ipfs);
+    let prev_stage_ipfs = ipfs.upload_object(prev_stage, file_name);
+    let stage_ipfs = ipfs.upload_object(stage, format!("{}_transformed", file_name).as_str());
+    spinner.message("Publishing stage, transformation and contribution hashes to Ethereum...".into());
+    let transaction_hash = contract.set_initial_stage(prev_stage_ipfs.hash.into_bytes(), stage_ipfs.hash.clone().into_bytes(), contribution_ipfs.clone().into_bytes()).transaction_hash;
     spinner.close();
-    stage
+    write_receipt(&statement, &stage_ipfs.hash, &contribution_ipfs, transaction_hash);
+    transaction_hash
 }
 
-fn transform_and_upload<S, T>(stage: &mut S, privkey: &PrivateKey, pubkey: &PublicKey, contract: &ContractWrapper<T>, file_name: &str, ipfs: &mut IPFSWrapper) -> H256 where
+fn transform_and_upload<S, T>(stage: &mut S, stage_index: u64, privkey: &PrivateKey, pubkey: &PublicKey, web3: &Web3<T>, contract: &DistributedMpc<T>, file_name: &str, ipfs: &mut IPFSWrapper) -> H256 where
     S: Transform + Verify + Clone + Encodable + Decodable,
     T: Transport
 {
@@ -150,10 +241,10 @@ fn transform_and_upload<S, T>(stage: &mut S, privkey: &PrivateKey, pubkey: &Publ
     stage.transform(privkey);
     assert!(stage.verify_transform(prev_stage, pubkey), "Invalid stage transformation!");
     spinner.close();
-    upload_object(stage, contract, "publishStageResults", file_name, ipfs)
+    publish_transformation(prev_stage, stage, stage_index, pubkey, web3, contract, file_name, ipfs)
 }
 
-fn init_stage_and_upload<S, T>(stage: &mut S, privkey: &PrivateKey, pubkey: &PublicKey, contract: &ContractWrapper<T>, file_name: &str, ipfs: &mut IPFSWrapper) -> H256 where
+fn init_stage_and_upload<S, T>(stage: &mut S, stage_index: u64, privkey: &PrivateKey, pubkey: &PublicKey, web3: &Web3<T>, contract: &DistributedMpc<T>, file_name: &str, ipfs: &mut IPFSWrapper) -> H256 where
     S: Transform + Verify + Clone + Encodable + Decodable,
     T: Transport
 {
@@ -161,13 +252,8 @@ fn init_stage_and_upload<S, T>(stage: &mut S, privkey: &PrivateKey, pubkey: &Pub
     let spinner = SpinnerBuilder::new("Transforming stage...".into()).spinner(spinner::DANCING_KIRBY.to_vec()).step(Duration::from_millis(500)).start();
     stage.transform(privkey);
     assert!(stage.verify_transform(prev_stage, pubkey), "Invalid stage transformation!");
-    spinner.message("Uploading stage and transformation to IPFS...".into());
-    let prev_stage_ipfs = ipfs.upload_object(prev_stage, file_name);
-    let stage_ipfs = ipfs.upload_object(stage, format!("{}_transformed", file_name).as_str());
-    spinner.message("Publishing stage and transformation hashes to Ethereum...".into());
-    let transaction_hash = contract.call("setInitialStage", (prev_stage_ipfs.hash.into_bytes(), stage_ipfs.hash.into_bytes()));
     spinner.close();
-    transaction_hash
+    publish_initial_stage(prev_stage, stage, stage_index, pubkey, web3, contract, file_name, ipfs)
 }
 
 fn measure_gas_usage<T: Transport>(hash: H256, eth: &Eth<T>) {
@@ -186,13 +272,16 @@ fn measure_gas_usage<T: Transport>(hash: H256, eth: &Eth<T>) {
     }
 }
 
-fn upload_object<S, T>(object: &mut S, contract: &ContractWrapper<T>, method_name: &str, file_name: &str, ipfs: &mut IPFSWrapper) -> H256 where
+/// Uploads the object to IPFS and publishes its hash with the method of the
+/// contract.
+fn upload_object<S, P, T>(object: &mut S, contract: &DistributedMpc<T>, publish: P, file_name: &str, ipfs: &mut IPFSWrapper) -> H256 where
     S: Encodable,
+    P: FnOnce(&DistributedMpc<T>, Vec<u8>) -> CallReceipt,
     T: Transport
 {
     let spinner = SpinnerBuilder::new(format!("Uploading {:?} to ipfs ...", file_name)).spinner(spinner::DANCING_KIRBY.to_vec()).step(Duration::from_millis(500)).start();
     let stage_ipfs = ipfs.upload_object(object, file_name);
-    let transaction_hash = contract.call(method_name, stage_ipfs.hash.into_bytes());
+    let transaction_hash = publish(contract, stage_ipfs.hash.into_bytes()).transaction_hash;
     spinner.close();    
     transaction_hash
 }
@@ -225,50 +314,326 @@ fn prompt(s: &str) -> String {
     }
 }
 
-fn is_coordinator<T: Transport>(contract: &ContractWrapper<T>, account: Address) -> bool {
-    account == contract.query("players", 0)
+/// The signer for the key given with -keystore, if any. The password of a
+/// keystore is read from the environment, or asked for.
+fn local_signer<T: Transport>(matches: &ArgMatches, web3: &Web3<T>) -> Option<Rc<LocalSigner<T>>> {
+    matches.value_of("keystore").map(|path| {
+        let key = load_key(path, || var(KEYSTORE_PASSWORD_ENV_KEY).unwrap_or_else(|_| read_password(&format!("Password of the keystore {:?}: ", path))));
+        let signer = LocalSigner::new(web3.clone(), key);
+            println!("\u{2714} Signing transactions locally as {:?}.", signer.address());
+        Rc::new(signer)
+    })
+}
+
+/// The policy for submitting transactions given on the command line.
+fn transaction_policy(matches: &ArgMatches) -> TransactionPolicy {
+    TransactionPolicy {
+        gas_limit: GasLimit::parse(matches.value_of("gas-limit").unwrap()).unwrap_or_else(|e| panic!("\u{274c} {}", e)),
+        gas_price: GasPrice::parse(matches.value_of("gas-price").unwrap()).unwrap_or_else(|e| panic!("\u{274c} {}", e)),
+        confirmations: value_t!(matches, "confirmations", u64).unwrap_or_else(|e| e.exit()),
+        retries: value_t!(matches, "retries", u32).unwrap_or_else(|e| e.exit()),
+        ..TransactionPolicy::default()
+    }
+}
+
+fn is_coordinator<T: Transport>(contract: &DistributedMpc<T>, account: Address) -> bool {
+    account == contract.players(0u64)
+}
+
+/// Panics if the account is not among the expected players of the ceremony
+/// or it is full, since joining would revert.
+fn check_may_join<T: Transport>(contract: &DistributedMpc<T>, account: Address, number_of_players: usize) {
+    let number_of_expected = contract.get_number_of_expected_players().low_u64();
+    if number_of_expected > 0 && !(0..number_of_expected).any(|i| contract.expected_players(i) == account) {
+        panic!("\u{274c} Your account {:?} is not one of the {} players expected by the ceremony!", account, number_of_expected);
+    }
+    let max_players = contract.max_players().low_u64();
+    if max_players > 0 && number_of_players as u64 >= max_players {
+        panic!("\u{274c} The ceremony already has its maximum of {} players!", max_players);
+    }
 }
 
-fn get_players<T: Transport>(contract: &ContractWrapper<T>) -> Vec<Address> {
+fn get_players<T: Transport>(contract: &DistributedMpc<T>) -> Vec<Address> {
     let mut players: Vec<Address> = vec![];
-    let number_of_players: u64 = contract.query("getNumberOfPlayers", ());
+    let number_of_players = contract.get_number_of_players().low_u64();
     for i in 0..number_of_players { 
-        let player: Address = contract.query("players", i);
-        players.push(player);
+        players.push(contract.players(i));
     }
     players
 }
 
-fn get_previous_player(players: Vec<Address>, player: Address) -> Option<Address> {
-    for i in 0..players.len() {
-        if i == 0 && players[i] == player {
-            return None;
+/// The state in which the player at the index was excluded for missing a
+/// deadline, or 0 if it takes part in the protocol.
+fn get_exclusion_state<T: Transport>(contract: &DistributedMpc<T>, player_index: usize) -> u64 {
+    contract.get_exclusion_state(player_index as u64).low_u64()
+}
+
+/// The indices of the players who took part in the state, i.e. who were not
+/// excluded in it or before.
+fn get_players_in_state<T: Transport>(contract: &DistributedMpc<T>, state: u64, players: &[Address]) -> Vec<usize> {
+    (0..players.len()).filter(|i| {
+        let excluded_in = get_exclusion_state(contract, *i);
+        excluded_in == 0 || excluded_in > state
+    }).collect()
+}
+
+fn get_current_turn<T: Transport>(contract: &DistributedMpc<T>) -> usize {
+    contract.current_turn().low_u64() as usize
+}
+
+fn get_turn_start<T: Transport>(contract: &DistributedMpc<T>) -> (U256, U256) {
+    (contract.turn_start_block(), contract.turn_start_time())
+}
+
+/// Whether the deadline of the current turn has passed, unless it has been
+/// reported for this turn already.
+fn is_deadline_missed<T: Transport>(contract: &DistributedMpc<T>, reported: &Option<(U256, U256)>) -> bool {
+    contract.is_turn_expired() && *reported != Some(get_turn_start(contract))
+}
+
+/// Tells the player whom the protocol is waiting for after the deadline of
+/// the current turn has passed. The coordinator excludes these players, so
+/// the protocol continues without them. Returns the start of the turn.
+fn handle_missed_deadline<T: Transport>(contract: &DistributedMpc<T>, players: &[Address], coordinator: bool) -> (U256, U256) {
+    let turn_start = get_turn_start(contract);
+    let missing: Vec<usize> = (0..players.len()).filter(|i| contract.is_missing(*i as u64)).collect();
+    for i in &missing {
+            println!("\u{274c} Player {} ({:?}) missed the deadline of state {}.", i, players[*i], get_current_state(contract));
+    }
+    if missing.contains(&0) {
+            println!("The protocol cannot continue without the coordinator. Waiting for it...");
+    } else if missing.is_empty() {
+            println!("Waiting for the next turn...");
+    } else if coordinator {
+            println!("Excluding the missing players from the protocol...");
+        contract.exclude_missing_players();
+    } else {
+            println!("Waiting for the coordinator to exclude the missing players...");
+    }
+    turn_start
+}
+
+/// Waits until the protocol has left the state, handling missed deadlines.
+/// The events only wake the player up, the state of the contract decides, so
+/// events replayed from before the state are harmless.
+fn await_next_stage<T, F>(filter: &mut EventFilter<T, NextStage, F, bool>, contract: &DistributedMpc<T>, state: u64, players: &[Address], coordinator: bool, poll_interval: &Duration) where
+    T: Transport,
+    F: Fn(NextStage, Option<Address>) -> Option<bool>
+{
+    let mut reported = None;
+    while get_current_state(contract) == state {
+        let next = filter.await_until(poll_interval, || get_current_state(contract) != state || is_deadline_missed(contract, &reported));
+        if next.is_none() && get_current_state(contract) == state {
+            reported = Some(handle_missed_deadline(contract, players, coordinator));
+        }
+    }
+}
+
+/// Waits until it is the player's turn in the stage, and returns the IPFS
+/// hash of the stage to transform: the result of the last player before it
+/// who was not excluded.
+fn await_turn<T, F>(filter: &mut EventFilter<T, StageResultPublished, F, Vec<u8>>, contract: &DistributedMpc<T>, players: &[Address], player_index: usize, poll_interval: &Duration) -> Vec<u8> where
+    T: Transport,
+    F: Fn(StageResultPublished, Option<Address>) -> Option<Vec<u8>>
+{
+    let mut reported = None;
+    while get_current_turn(contract) != player_index {
+        if get_exclusion_state(contract, player_index) != 0 {
+            panic!("\u{274c} You have been excluded from the protocol because you missed a deadline.");
         }
-        if players[i] == player {
-            return Some(players[i-1]);
+        let published = filter.await_until(poll_interval, || get_current_turn(contract) == player_index || is_deadline_missed(contract, &reported));
+        if published.is_none() && get_current_turn(contract) != player_index {
+            reported = Some(handle_missed_deadline(contract, players, false));
         }
     }
-    None
+    contract.get_latest_transformation()
+}
+
+fn has_committed<T: Transport>(contract: &DistributedMpc<T>, account: Address) -> bool {
+    contract.get_commitment(account) != [0u8; 32]
+}
+
+/// Whether the player still has to use its secrets: until it has published
+/// its transformation of stage 3.
+fn needs_secrets<T: Transport>(contract: &DistributedMpc<T>, player_index: usize) -> bool {
+    let state = get_current_state(contract);
+    state < 6 || (state == 6 && contract.get_transformation(2u64, player_index as u64).is_empty())
 }
 
-fn fetch_all_commitments<T: Transport>(contract: &ContractWrapper<T>, players: Vec<Address>) -> Vec<Vec<u8>> {
-    let mut all_commitments : Vec<Vec<u8>> = vec![];
+/// Loads the secrets of the player from the file, or generates them if it
+/// has not committed yet. After a restart, they have to match the
+/// commitment of the player. Without a file, the secrets are generated and
+/// kept in memory only, so that the protocol cannot be resumed.
+fn resume_secrets<T: Transport>(matches: &ArgMatches, path: Option<&str>, contract: &DistributedMpc<T>) -> offline::Secrets {
+    let committed = has_committed(contract, contract.account());
+    let path = match path {
+        Some(path) => path,
+        None => {
+            if committed {
+                panic!("\u{274c} You have committed already, but your secrets were not kept on disk! The protocol cannot be resumed without them.");
+            }
+            let mut rng = Protected::new(rand::chacha::ChaChaRng::from_seed(&get_entropy(matches)[..]));
+            let privkey = PrivateKey::new(&mut *rng);
+            let pubkey = privkey.pubkey(&mut *rng);
+            return offline::Secrets {
+                contract: contract.address().0,
+                privkey: privkey,
+                pubkey: pubkey
+            };
+        }
+    };
+    if committed && !Path::new(path).is_file() {
+        panic!("\u{274c} You have committed already, but your secrets are not in {:?}! The protocol cannot be resumed without them.", path);
+    }
+    let secrets = offline::load_secrets(matches, path, contract.address().0);
+    if committed {
+        if Digest256(contract.get_commitment(contract.account())) != secrets.pubkey.hash() {
+            panic!("\u{274c} The secrets in {:?} do not match your commitment!", path);
+        }
+            println!("\u{2714} Loaded your secrets from {:?}, resuming...", path);
+    }
+    secrets
+}
+
+fn secret_pubkey(pubkey: &Option<PublicKey>) -> &PublicKey {
+    pubkey.as_ref().expect("Secrets should not be destroyed yet!")
+}
+
+fn fetch_all_commitments<T: Transport>(contract: &DistributedMpc<T>, players: Vec<Address>) -> Vec<Digest256> {
+    let mut all_commitments : Vec<Digest256> = vec![];
     for player in players {
-        let commitment: Vec<u8> = contract.query("getCommitment", player);
-        all_commitments.push(commitment);
+        all_commitments.push(Digest256(contract.get_commitment(player)));
     }
     all_commitments
 }
 
-fn verify_all_nizks_valid<T: Transport>(contract: &ContractWrapper<T>, players: Vec<Address>, hash_of_all_commitments: &Digest512, ipfs: &mut IPFSWrapper) {
-    for i in 0..players.len() {
+/// The transcript the nizks of the player at the index are bound to.
+fn nizk_transcript<T: Transport>(contract: &DistributedMpc<T>, chain_id: u64, player: Address, player_index: usize, hash_of_all_commitments: &Digest512) -> NizkTranscript {
+    NizkTranscript::new(chain_id, contract.address().0, player.0, player_index as u64, *hash_of_all_commitments)
+}
+
+fn verify_all_nizks_valid<T: Transport>(contract: &DistributedMpc<T>, chain_id: u64, players: Vec<Address>, hash_of_all_commitments: &Digest512, ipfs: &mut IPFSWrapper) {
+    for i in get_players_in_state(contract, 3, &players) {
         let player_index: u64 = i as u64; 
-        let nizks_bin: Vec<u8> = contract.query("getNizks", player_index);
-        let pubkey_bin: Vec<u8> = contract.query("getPublicKey", player_index);
+        let transcript = nizk_transcript(contract, chain_id, players[i], i, hash_of_all_commitments);
+        let nizks_bin = contract.get_nizks(player_index);
+        let pubkey_bin = contract.get_public_key(player_index);
         let nizks: PublicKeyNizks = ipfs.download_object(String::from_utf8(nizks_bin).expect("Should be valid IPFS hash!").as_str());
         let pubkey: PublicKey = ipfs.download_object(String::from_utf8(pubkey_bin).expect("Should be valid IPFS hash!").as_str());
-        assert!(nizks.is_valid(&pubkey, hash_of_all_commitments), format!("Nizks was invalid for player {}! Aborting.", i));
+        assert!(nizks.is_valid(&pubkey, &transcript), format!("Nizks was invalid for player {}! Aborting.", i));
+    }
+}
+
+/// Verifies the transcript of a failed ceremony up to its last valid
+/// transformation and deploys a fork that continues from it. The account
+/// that deploys the fork is its coordinator.
+fn fork_ceremony(matches: &ArgMatches, host: &str) {
+        println!("Initializing Web3 and IPFS...");
+    with_web3!(web3_endpoint(host), web3 => deploy_fork(matches, host, web3))
+}
+
+fn deploy_fork<T: EventTransport>(matches: &ArgMatches, host: &str, web3: Web3<T>) {
+    let powers_of_tau = matches.value_of("powersoftau");
+    let poll_interval = Duration::new(1, 0);
+    let mut params = CeremonyParameters {
+        timeout: TurnTimeout {
+            blocks: value_t!(matches, "timeout-blocks", u64).unwrap_or_else(|e| e.exit()),
+            seconds: value_t!(matches, "timeout-seconds", u64).unwrap_or_else(|e| e.exit())
+        },
+        ..CeremonyParameters::default()
+    };
+
+    let mut ipfs: IPFSWrapper = IPFSWrapper::new(format!("http://{}", host).as_str(), 5001);
+    let signer = local_signer(matches, &web3);
+    let parent = Manager::new(web3.clone(), format!("http://{}", host).as_str(), 5001).with_signer(signer.clone()).with_policy(transaction_policy(matches)).init_contract(matches.value_of("account"), matches.value_of("contract").unwrap());
+        println!("Successfully initialized.");
+
+    // the fork imports the same powers of tau, which its contract checks, and
+    // derives its beacons like the parent
+    params.powers_of_tau = parent.powers_of_tau();
+    params.beacon_delay = parent.beacon_delay().low_u64();
+    params.beacon_iterations = parent.beacon_iterations().low_u64() as u8;
+    let cs_hash = parent.get_constraint_system();
+    let cs = ipfs.download_cs(String::from_utf8(cs_hash.clone()).expect("Not a valid utf8 string").as_str());
+        println!("Verifying the transcript of {:?}...", parent.address());
+    let prefix = verify_prefix(&web3, &parent, &cs, powers_of_tau, None, &mut ipfs);
+    if prefix.complete {
+        panic!("\u{274c} The ceremony finished successfully, there is nothing to fork.");
+    }
+    let (stage, player) = match prefix.contributions.last() {
+        Some(last) => (last.stage, last.player),
+        None => panic!("\u{274c} The ceremony has no valid transformation to continue from!")
+    };
+        println!("\u{2714} The ceremony is valid up to the stage {} transformation of player {}.", stage + 1, player);
+
+    let provenance = ipfs.upload_object(&Provenance::new(&parent, &prefix), "provenance").hash;
+        println!("Deploying the fork...");
+    let (contract, _) = Manager::new(web3.clone(), format!("http://{}", host).as_str(), 5001).with_signer(signer).with_policy(transaction_policy(matches)).deploy(matches.value_of("account"), matches.value_of("constraint-system").unwrap(), &params);
+    let fork_cs_hash = contract.get_constraint_system();
+    if fork_cs_hash != cs_hash {
+        panic!("\u{274c} The constraint system of the fork differs from the one of the ceremony!");
     }
+    let mut forked_filter = EventFilterBuilder::new(web3.clone(), contract.address(), replay_start(&contract, CURSOR_FILE)).create_filter("Waiting for the fork to be recorded...".into(), ceremony_forked_cb, None);
+    contract.fork(parent.address(), stage, player, provenance.clone().into_bytes());
+    forked_filter.await(&poll_interval);
+        println!("\u{2714} Deployed the fork {:?}, which continues the stage {} transformation of player {} of {:?}.", contract.address(), stage + 1, player, parent.address());
+        println!("Its provenance, the {} verified contributions of {:?}, is stored on IPFS as {}.", prefix.contributions.len(), parent.address(), provenance);
+        println!("Run the player with -c {} to start the protocol as its coordinator.", hex::encode(&contract.address().0[..]));
+}
+
+/// Deploys a new ceremony with the constraint system and parameters given on
+/// the command line and writes a summary of it for the players.
+fn deploy(matches: &ArgMatches, host: &str) {
+        println!("Initializing Web3 and IPFS...");
+    with_web3!(web3_endpoint(host), web3 => deploy_ceremony(matches, host, web3))
+}
+
+fn deploy_ceremony<T: EventTransport>(matches: &ArgMatches, host: &str, web3: Web3<T>) {
+    let expected_players: Vec<Address> = matches.values_of("player").map(|players| players.map(|player| {
+        player.trim_left_matches("0x").parse().unwrap_or_else(|_| panic!("\u{274c} {:?} is not an address!", player))
+    }).collect()).unwrap_or_default();
+    let params = CeremonyParameters {
+        timeout: TurnTimeout {
+            blocks: value_t!(matches, "timeout-blocks", u64).unwrap_or_else(|e| e.exit()),
+            seconds: value_t!(matches, "timeout-seconds", u64).unwrap_or_else(|e| e.exit())
+        },
+        expected_players: expected_players,
+        max_players: value_t!(matches, "max-players", u64).unwrap_or_else(|e| e.exit()),
+        powers_of_tau: matches.value_of("powersoftau").map(|path| {
+            File::open(path).and_then(|mut file| hash_powers_of_tau(&mut file))
+                .unwrap_or_else(|e| panic!("\u{274c} Error reading the powers of tau {:?}: {}", path, e))
+        }).unwrap_or_default(),
+        beacon_delay: value_t!(matches, "beacon-delay", u64).unwrap_or_else(|e| e.exit()),
+        beacon_iterations: value_t!(matches, "beacon-iterations", u8).unwrap_or_else(|e| e.exit())
+    };
+    if params.beacon_delay == 0 {
+        panic!("\u{274c} The beacon block has to come after the last transformation of the stage, the delay has to be at least 1!");
+    }
+    if params.beacon_iterations >= 64 {
+        panic!("\u{274c} The beacon can be hashed at most 2^63 times!");
+    }
+    let cs_path = matches.value_of("constraint-system").unwrap();
+    let summary_path = matches.value_of("summary").unwrap();
+
+    let manager = Manager::new(web3.clone(), format!("http://{}", host).as_str(), 5001).with_signer(local_signer(matches, &web3)).with_policy(transaction_policy(matches));
+        println!("Successfully initialized.");
+        println!("Deploying the ceremony for the constraint system {:?}...", cs_path);
+    let (contract, deployment) = manager.deploy(matches.value_of("account"), cs_path, &params);
+    deployment.write(summary_path);
+        println!("\u{2714} Deployed the ceremony {:?} in block {} (transaction {}, {} gas).", contract.address(), deployment.block, deployment.transaction, deployment.gas_used);
+        println!("Coordinator: {}", deployment.coordinator);
+        println!("Constraint system: {} ({} B)", deployment.constraint_system, deployment.constraint_system_size);
+        println!("Turn timeout: {} blocks, {} seconds (0 = no limit)", deployment.timeout_blocks, deployment.timeout_seconds);
+    if deployment.expected_players.is_empty() {
+            println!("Expected players: anyone can join");
+    } else {
+            println!("Expected players: {}", deployment.expected_players.join(", "));
+    }
+        println!("Maximum number of players: {} (0 = no limit)", deployment.max_players);
+        println!("Random beacon: the hash of the block {} blocks after each stage, hashed 2^{} times", deployment.beacon_delay, deployment.beacon_iterations);
+        println!("Powers of tau: {}", deployment.powers_of_tau.as_ref().map(|hash| hash.as_str()).unwrap_or("none, stage 1 is created from scratch"));
+        println!("The summary of the deployment was written to {:?}.", summary_path);
+        println!("Run the player with -c {} to start the protocol as its coordinator.", hex::encode(&contract.address().0[..]));
 }
 
 fn main() {
@@ -280,18 +645,29 @@ fn main() {
                 println!("Using host from environment variable: {:?}", host);
     }
 
-    let mut call_transactions: Vec<H256> = vec![];
-
     let yaml = load_yaml!("../player.yml");
     let matches = App::from_yaml(yaml).get_matches();
-    let account_index = matches.value_of("account");
-    let contract_address = matches.value_of("contract");
+    match matches.subcommand() {
+        ("prepare-challenge", Some(matches)) => return offline::prepare_challenge(matches, &host),
+        ("contribute-offline", Some(matches)) => return offline::contribute_offline(matches),
+        ("submit-response", Some(matches)) => return offline::submit_response(matches, &host),
+        ("fork-ceremony", Some(matches)) => return fork_ceremony(matches, &host),
+        ("deploy", Some(matches)) => return deploy(matches, &host),
+        _ => {}
+    }
 
         println!("Initializing Web3 and IPFS...");
-    let (_eloop, transport) = Http::new(format!("http://{}:8545", host).as_str()).expect("Error connecting to web3 instance!");
-    let manager: Manager<Http> = Manager::new(Web3::new(transport), format!("http://{}", host).as_str(), 5001);
+    with_web3!(web3_endpoint(&host), web3 => run_protocol(&matches, &host, web3, program_start))
+}
+
+fn run_protocol<T: EventTransport>(matches: &ArgMatches, host: &str, web3: Web3<T>, program_start: Instant) {
+    let mut call_transactions: Vec<H256> = vec![];
+    let account_index = matches.value_of("account");
+    let contract_address = matches.value_of("contract").unwrap();
+    let powers_of_tau = matches.value_of("powersoftau");
+    let verify_chain = matches.is_present("verify-chain");
 
-    let web3: Web3<Http> = manager.web3.clone();
+    let manager: Manager<T> = Manager::new(web3.clone(), format!("http://{}", host).as_str(), 5001).with_signer(local_signer(matches, &web3)).with_policy(transaction_policy(matches));
     let mut ipfs: IPFSWrapper = IPFSWrapper::new(format!("http://{}", host).as_str(), 5001);
         println!("Successfully initialized.");
     
@@ -299,11 +675,16 @@ fn main() {
     let default_account = contract.account(); 
         println!("Your account used: {:?}", default_account);
         println!("Contract address: {:?}", contract.address());
+    let chain_id = chain_id(&web3);
+    let fork = get_fork(&contract);
+    if let Some(ref fork) = fork {
+            println!("The protocol is a fork of {:?} and continues the stage {} transformation of its player {}.", fork.parent, fork.stage + 1, fork.player);
+    }
 
-    let filter_builder = EventFilterBuilder::new(web3.clone()); 
+    let filter_builder = EventFilterBuilder::new(web3.clone(), contract.address(), replay_start(&contract, CURSOR_FILE)); 
     let poll_interval = Duration::new(1, 0);
-    let mut player_joined_filter = filter_builder.create_filter("PlayerJoined(address)", "Waiting for player joining...".into(), player_joined_cb, Some(default_account));
-    let mut next_stage_filter = filter_builder.create_filter("NextStage(uint256)", "Waiting for next stage to start...".into(), next_stage_cb, None);
+    let mut player_joined_filter = filter_builder.create_filter("Waiting for player joining...".into(), player_joined_cb, Some(default_account));
+    let mut next_stage_filter = filter_builder.create_filter("Waiting for next stage to start...".into(), next_stage_cb, None);
     
     // IF CURRENT ACCOUNT IS NOT A PLAYER, JOIN!
     let mut players: Vec<Address> = get_players(&contract);
@@ -311,152 +692,246 @@ fn main() {
             println!("You are a player in the protocol already, continuing...");
     } else {
             println!("Welcome new player! Joining now...");
-        let transaction_hash = contract.call("join", ());
+        check_may_join(&contract, default_account, players.len());
+        let transaction_hash = contract.join().transaction_hash;
         if PERFORM_MEASUREMENTS {
             call_transactions.push(transaction_hash);
         }
         player_joined_filter.await(&poll_interval);    
         players = get_players(&contract);
     }
-    let previous_player: Option<Address> = get_previous_player(players.clone(), default_account);
-    let prev_player_str: String;
-    if previous_player.is_some() {
-        prev_player_str = format!("{:?}", previous_player.unwrap());
+    let player_index = players.iter().position(|p| *p == default_account).expect("Player should have joined!");
+    let coordinator = player_index == 0;
+    let mut stage_result_published_filter = filter_builder.create_filter("Waiting for your turn...".into(), stage_result_cb, None);
+    // With -secrets, the secrets are kept encrypted on disk until stage 3,
+    // so that the player can resume after a restart. They are dropped, and
+    // thereby zeroized, as soon as they are no longer needed.
+    let secrets_path = matches.value_of("secrets");
+    let (mut privkey, mut pubkey) = if needs_secrets(&contract, player_index) {
+        let secrets = resume_secrets(matches, secrets_path, &contract);
+        if !secrets.privkey.is_locked() {
+                println!("Warning: Your secrets could not be locked into memory and may be swapped to disk.");
+        }
+        (Some(secrets.privkey), Some(secrets.pubkey))
     } else {
-        prev_player_str = "nobody".into();   
+            println!("Your part of the protocol is done, continuing without your secrets...");
+        (None, None)
+    };
+    if let Some(ref pubkey) = pubkey {
+            println!("Your commitment: {}", pubkey.hash());
     }
-    let mut stage_result_published_filter = filter_builder.create_filter("StageResultPublished(address,bytes)", format!("Waiting for {:?} to publish results...", prev_player_str), stage_result_cb, previous_player); 
-    let mut chacha_rng = rand::chacha::ChaChaRng::from_seed(&get_entropy());
-
-    let privkey = PrivateKey::new(&mut chacha_rng);
-    let mut pubkey = privkey.pubkey(&mut chacha_rng);
-    let commitment = pubkey.hash();
 
-    let cs_hash: Vec<u8> = contract.query("getConstraintSystem", ());
+    let cs_hash = contract.get_constraint_system();
     let cs = ipfs.download_cs(String::from_utf8(cs_hash).expect("Not a valid utf8 string").as_str());
     let mut stop = false;
     let mut stage1: Stage1Contents;
     let mut stage2: Stage2Contents;
     let mut stage3: Stage3Contents;
-        println!("!!! READ CAREFULLY !!! Beyond this point, the program MUST NOT BE STOPPED OR INTERRUPTED until the end of the protocol.");
-        println!("If it is interrupted anyways, there is no way to restart the protocol using the same Smart Contract!");
+    match secrets_path {
+        Some(path) => {
+                println!("If the program is interrupted, run it again with the same account and {:?} to resume before your turn expires.", path);
+        },
+        None => {
+                println!("!!! READ CAREFULLY !!! Beyond this point, the program MUST NOT BE STOPPED OR INTERRUPTED until the end of the protocol.");
+                println!("If it is interrupted anyways, there is no way to restart the protocol using the same Smart Contract!");
+                println!("Run the player with -secrets to keep your secrets encrypted on disk and be able to resume.");
+        },
+    }
     prompt("Press [ENTER] when you are ready to start the protocol.");
     while !stop {
-        match get_current_state(&contract) {
+        // After a restart, the events are replayed from the block in which
+        // the player last queried the state.
+        write_cursor(CURSOR_FILE, contract.address(), block_number(&web3));
+        if get_exclusion_state(&contract, player_index) != 0 {
+                println!("\u{274c} You have been excluded from the protocol because you missed a deadline.");
+            break;
+        }
+        let state = get_current_state(&contract);
+        match state {
             0 => {
-                if is_coordinator(&contract, default_account){
+                if is_coordinator(&contract, default_account) && !has_committed(&contract, default_account) {
                     prompt("You are the coordinator. Press [ENTER] to start the protocol.");
-                    let transaction_hash = contract.call("commit", to_bytes_fixed(&commitment.clone()));
+                    let transaction_hash = contract.commit(secret_pubkey(&pubkey).hash().0).transaction_hash;
                     if PERFORM_MEASUREMENTS {
                         call_transactions.push(transaction_hash);
                     }
-                } else {
+                } else if !is_coordinator(&contract, default_account) {
                         println!("You are not the coordinator. The protocol will start as the coordinator decides.");
                 }
-                next_stage_filter.await(&poll_interval);
+                await_next_stage(&mut next_stage_filter, &contract, state, &players, coordinator, &poll_interval);
                 players = get_players(&contract);
             },
             1 => {
-                if !is_coordinator(&contract, default_account){
-                    let transaction_hash = contract.call("commit", to_bytes_fixed(&commitment.clone()));
+                if !has_committed(&contract, default_account) {
+                    let transaction_hash = contract.commit(secret_pubkey(&pubkey).hash().0).transaction_hash;
                     if PERFORM_MEASUREMENTS {
                         call_transactions.push(transaction_hash);
                     }
                 }
-                next_stage_filter.await(&poll_interval);
+                await_next_stage(&mut next_stage_filter, &contract, state, &players, coordinator, &poll_interval);
                     println!("All players committed. Proceeding to next round.");
             },
             2 => {
-                let transaction_hash = upload_object(&mut pubkey, &contract, "revealCommitment", "publicKey", &mut ipfs);
-                if PERFORM_MEASUREMENTS {
-                    call_transactions.push(transaction_hash);
+                if contract.get_public_key(player_index as u64).is_empty() {
+                    let transaction_hash = upload_object(pubkey.as_mut().expect("Secrets should not be destroyed yet!"), &contract, DistributedMpc::reveal_commitment, "publicKey", &mut ipfs);
+                    if PERFORM_MEASUREMENTS {
+                        call_transactions.push(transaction_hash);
+                    }
                 }
                     println!("Public Key revealed! Waiting for other players to reveal...");
-                next_stage_filter.await(&poll_interval);
+                await_next_stage(&mut next_stage_filter, &contract, state, &players, coordinator, &poll_interval);
                     println!("All players revealed their commitments. Proceeding to next round.");
             },
             3 => {
                 let mut all_commitments = fetch_all_commitments(&contract, players.clone());
                 let hash_of_all_commitments = Digest512::from(&all_commitments).unwrap();
-                    println!("Creating nizks...");
-                let mut nizks = pubkey.nizks(&mut chacha_rng, &privkey, &hash_of_all_commitments);
-                    println!("Nizks created.");
-                let transaction_hash = upload_object(&mut nizks, &contract, "publishNizks", "nizks", &mut ipfs);
-                if PERFORM_MEASUREMENTS {
-                    call_transactions.push(transaction_hash);
+                if contract.get_nizks(player_index as u64).is_empty() {
+                        println!("Creating nizks...");
+                    let transcript = nizk_transcript(&contract, chain_id, default_account, player_index, &hash_of_all_commitments);
+                    let mut nizks = {
+                        let mut rng = Protected::new(rand::chacha::ChaChaRng::from_seed(&get_entropy(&matches)[..]));
+                        secret_pubkey(&pubkey).nizks(&mut *rng, privkey.as_ref().expect("Secrets should not be destroyed yet!"), &transcript)
+                    };
+                        println!("Nizks created.");
+                    let transaction_hash = upload_object(&mut nizks, &contract, DistributedMpc::publish_nizks, "nizks", &mut ipfs);
+                    if PERFORM_MEASUREMENTS {
+                        call_transactions.push(transaction_hash);
+                    }
                 }
-                next_stage_filter.await(&poll_interval);
+                await_next_stage(&mut next_stage_filter, &contract, state, &players, coordinator, &poll_interval);
                     println!("All nizks published. Checking validity...");
-                verify_all_nizks_valid(&contract, players.clone(), &hash_of_all_commitments, &mut ipfs);
+                verify_all_nizks_valid(&contract, chain_id, players.clone(), &hash_of_all_commitments, &mut ipfs);
             },
             4 => {
-                if is_coordinator(&contract, default_account) {
-                        println!("Creating stage...");
-                    stage1 = Stage1Contents::new(&cs);
-                    let transaction_hash = init_stage_and_upload(&mut stage1, &privkey, &pubkey, &contract, "stage1", &mut ipfs);
-                    if PERFORM_MEASUREMENTS {
-                        call_transactions.push(transaction_hash);
-                    }
-                    drop(stage1);
-                } else {
-                    let stage_hash = stage_result_published_filter.await(&poll_interval).unwrap();
-                    let mut stage1: Stage1Contents;
-                    stage1 = ipfs.download_stage(String::from_utf8(stage_hash).expect("Should be valid IPFS hash").as_str());
-                    let transaction_hash = transform_and_upload(&mut stage1, &privkey, &pubkey, &contract, "stage1_transformed", &mut ipfs);
-                    if PERFORM_MEASUREMENTS {
-                        call_transactions.push(transaction_hash);
+                if contract.get_transformation(0u64, player_index as u64).is_empty() {
+                    if is_coordinator(&contract, default_account) {
+                            println!("Creating stage...");
+                        stage1 = seed_stage(&web3, &contract, &fork, 0, &mut ipfs).unwrap_or_else(|| create_stage1(&contract, &cs, powers_of_tau));
+                        let transaction_hash = init_stage_and_upload(&mut stage1, 0, privkey.as_ref().expect("Secrets should not be destroyed yet!"), secret_pubkey(&pubkey), &web3, &contract, "stage1", &mut ipfs);
+                        if PERFORM_MEASUREMENTS {
+                            call_transactions.push(transaction_hash);
+                        }
+                        drop(stage1);
+                    } else {
+                        let stage_hash = await_turn(&mut stage_result_published_filter, &contract, &players, player_index, &poll_interval);
+                        let mut stage1: Stage1Contents;
+                        stage1 = ipfs.download_stage(String::from_utf8(stage_hash).expect("Should be valid IPFS hash").as_str());
+                        verify_initial_stage(&contract, 0, &seed_stage(&web3, &contract, &fork, 0, &mut ipfs).unwrap_or_else(|| create_stage1(&contract, &cs, powers_of_tau)), &mut ipfs);
+                        verify_received_stage(&contract, 0, &players, default_account, &stage1, verify_chain, &mut ipfs);
+                        let transaction_hash = transform_and_upload(&mut stage1, 0, privkey.as_ref().expect("Secrets should not be destroyed yet!"), secret_pubkey(&pubkey), &web3, &contract, "stage1_transformed", &mut ipfs);
+                        if PERFORM_MEASUREMENTS {
+                            call_transactions.push(transaction_hash);
+                        }
+                        drop(stage1);
                     }
-                    drop(stage1);
                 }
-                next_stage_filter.await(&poll_interval);
+                await_next_stage(&mut next_stage_filter, &contract, state, &players, coordinator, &poll_interval);
             },
             5 => {
-                if is_coordinator(&contract, default_account) {
-                        println!("Creating stage...");
-                    stage1 = download_stage(&contract, "getLatestTransformation", (), &mut ipfs);
-                    stage2 = Stage2Contents::new(&cs, &stage1);
-                    drop(stage1);
-                    let transaction_hash = init_stage_and_upload(&mut stage2, &privkey, &pubkey, &contract, "stage2", &mut ipfs);
-                    if PERFORM_MEASUREMENTS {
-                        call_transactions.push(transaction_hash);
-                    }
-                    drop(stage2);
-                } else {
-                    let stage_hash = stage_result_published_filter.await(&poll_interval).unwrap();
-                    let mut stage2: Stage2Contents;
-                    stage2 = ipfs.download_stage(String::from_utf8(stage_hash).expect("Should be valid IPFS hash").as_str());
-                    let transaction_hash = transform_and_upload(&mut stage2, &privkey, &pubkey, &contract, "stage2_transformed", &mut ipfs);
-                    if PERFORM_MEASUREMENTS {
-                        call_transactions.push(transaction_hash);
+                if contract.get_transformation(1u64, player_index as u64).is_empty() {
+                    if is_coordinator(&contract, default_account) {
+                            println!("Creating stage...");
+                        stage2 = match seed_stage(&web3, &contract, &fork, 1, &mut ipfs) {
+                            Some(seed) => seed,
+                            None => {
+                                stage1 = download_stage(&contract, |contract| contract.get_latest_transformation(), &mut ipfs);
+                                await_beacon(&web3, &contract, 0, 0, &poll_interval);
+                                if let Some(beacon) = stage_beacon(&web3, &contract, 0) {
+                                        println!("Applying random beacon to stage 1...");
+                                    stage1 = beacon.apply(&stage1);
+                                }
+                                let initial = Stage2Contents::new(&cs, &stage1);
+                                drop(stage1);
+                                initial
+                            }
+                        };
+                        let transaction_hash = init_stage_and_upload(&mut stage2, 1, privkey.as_ref().expect("Secrets should not be destroyed yet!"), secret_pubkey(&pubkey), &web3, &contract, "stage2", &mut ipfs);
+                        if PERFORM_MEASUREMENTS {
+                            call_transactions.push(transaction_hash);
+                        }
+                        drop(stage2);
+                    } else {
+                        let stage_hash = await_turn(&mut stage_result_published_filter, &contract, &players, player_index, &poll_interval);
+                        let mut stage2: Stage2Contents;
+                        stage2 = ipfs.download_stage(String::from_utf8(stage_hash).expect("Should be valid IPFS hash").as_str());
+                        {
+                            let recomputed = match seed_stage(&web3, &contract, &fork, 1, &mut ipfs) {
+                                Some(seed) => seed,
+                                None => {
+                                    let beacon = stage_beacon(&web3, &contract, 0);
+                                    let final_stage1: Stage1Contents = download_final_stage(&contract, 0, &players, &beacon, &mut ipfs);
+                                    Stage2Contents::new(&cs, &final_stage1)
+                                }
+                            };
+                            verify_initial_stage(&contract, 1, &recomputed, &mut ipfs);
+                        }
+                        verify_received_stage(&contract, 1, &players, default_account, &stage2, verify_chain, &mut ipfs);
+                        let transaction_hash = transform_and_upload(&mut stage2, 1, privkey.as_ref().expect("Secrets should not be destroyed yet!"), secret_pubkey(&pubkey), &web3, &contract, "stage2_transformed", &mut ipfs);
+                        if PERFORM_MEASUREMENTS {
+                            call_transactions.push(transaction_hash);
+                        }
+                        drop(stage2);
                     }
-                    drop(stage2);
                 }
-                next_stage_filter.await(&poll_interval);
+                await_next_stage(&mut next_stage_filter, &contract, state, &players, coordinator, &poll_interval);
             },
             6 => {
-                if is_coordinator(&contract, default_account) {
-                        println!("Creating stage...");
-                    stage2 = download_stage(&contract, "getLatestTransformation", (), &mut ipfs);
-                    stage3 = Stage3Contents::new(&cs, &stage2);
-                    drop(stage2); 
-                    let transaction_hash = init_stage_and_upload(&mut stage3, &privkey, &pubkey, &contract, "stage3", &mut ipfs);
-                    if PERFORM_MEASUREMENTS {
-                        call_transactions.push(transaction_hash);
+                if contract.get_transformation(2u64, player_index as u64).is_empty() {
+                    if is_coordinator(&contract, default_account) {
+                            println!("Creating stage...");
+                        stage3 = match seed_stage(&web3, &contract, &fork, 2, &mut ipfs) {
+                            Some(seed) => seed,
+                            None => {
+                                stage2 = download_stage(&contract, |contract| contract.get_latest_transformation(), &mut ipfs);
+                                await_beacon(&web3, &contract, 1, 0, &poll_interval);
+                                if let Some(beacon) = stage_beacon(&web3, &contract, 1) {
+                                        println!("Applying random beacon to stage 2...");
+                                    stage2 = beacon.apply(&stage2);
+                                }
+                                let initial = Stage3Contents::new(&cs, &stage2);
+                                drop(stage2);
+                                initial
+                            }
+                        };
+                        let transaction_hash = init_stage_and_upload(&mut stage3, 2, privkey.as_ref().expect("Secrets should not be destroyed yet!"), secret_pubkey(&pubkey), &web3, &contract, "stage3", &mut ipfs);
+                        if PERFORM_MEASUREMENTS {
+                            call_transactions.push(transaction_hash);
+                        }
+                        drop(stage3);
+                    } else {
+                        let stage_hash = await_turn(&mut stage_result_published_filter, &contract, &players, player_index, &poll_interval);
+                        let mut stage3: Stage3Contents;
+                        stage3 = ipfs.download_stage(String::from_utf8(stage_hash).expect("Should be valid IPFS hash").as_str());
+                        {
+                            let recomputed = match seed_stage(&web3, &contract, &fork, 2, &mut ipfs) {
+                                Some(seed) => seed,
+                                None => {
+                                    let beacon = stage_beacon(&web3, &contract, 1);
+                                    let final_stage2: Stage2Contents = download_final_stage(&contract, 1, &players, &beacon, &mut ipfs);
+                                    Stage3Contents::new(&cs, &final_stage2)
+                                }
+                            };
+                            verify_initial_stage(&contract, 2, &recomputed, &mut ipfs);
+                        }
+                        verify_received_stage(&contract, 2, &players, default_account, &stage3, verify_chain, &mut ipfs);
+                        let transaction_hash = transform_and_upload(&mut stage3, 2, privkey.as_ref().expect("Secrets should not be destroyed yet!"), secret_pubkey(&pubkey), &web3, &contract, "stage3_transformed", &mut ipfs);
+                        if PERFORM_MEASUREMENTS {
+                            call_transactions.push(transaction_hash);
+                        }
+                        drop(stage3);
                     }
-                    drop(stage3);
-                } else {
-                    let stage_hash = stage_result_published_filter.await(&poll_interval).unwrap();
-                    let mut stage3: Stage3Contents;
-                    stage3 = ipfs.download_stage(String::from_utf8(stage_hash).expect("Should be valid IPFS hash").as_str());
-                    let transaction_hash = transform_and_upload(&mut stage3, &privkey, &pubkey, &contract, "stage3_transformed", &mut ipfs);
-                    if PERFORM_MEASUREMENTS {
-                        call_transactions.push(transaction_hash);
+                }
+                if let Some(privkey) = privkey.take() {
+                    privkey.destroy();
+                    if let Some(path) = secrets_path {
+                        offline::destroy_file(path);
                     }
-                    drop(stage3);
+                        println!("\u{2714} Your secrets have been destroyed.");
                 }
-                next_stage_filter.await(&poll_interval);
+                await_next_stage(&mut next_stage_filter, &contract, state, &players, coordinator, &poll_interval);
             },
             7 => {
+                await_beacon(&web3, &contract, 2, player_index as u64, &poll_interval);
                     println!("Protocol finished! You can now exit this program and run the verifier to create the keypair.");
                 if PERFORM_MEASUREMENTS {
                     let total_secs: i64 = program_start.elapsed().as_secs() as i64;
@@ -537,38 +1012,27 @@ fn print_for_benchmarks<T: Transport>(total_secs: i64, transactions: Vec<H256>,
  *  CALLBACKS FOR HANDLING FILTER RESULTS 
  */
 
-fn player_joined_cb(result: Vec<Log>, player: Option<Address>) -> Option<bool> {
-    for i in 0..result.len() {
-        let data: &Vec<u8> = &result[i].data.0;
-        let hash: H256 = H256::from(data.as_slice());
-        let joined: Address = Address::from(hash);
-        println!("Player joined: {:?}", joined);
-        if player.unwrap() == joined {
-            return Some(true);
-        }
+fn player_joined_cb(event: PlayerJoined, player: Option<Address>) -> Option<bool> {
+    println!("Player joined: {:?}", event.player);
+    if player.unwrap() == event.player {
+        return Some(true);
     }
     None
 }
 
-fn next_stage_cb(result: Vec<Log>, _: Option<Address>) -> Option<bool> {
-    for i in 0..result.len() {
-        let data: &Vec<u8> = &result[i].data.0;
-        println!("New Stage: {:?}", U256::from(data.as_slice()).low_u64());
-        return Some(true);
-    }
-    None
+fn ceremony_forked_cb(event: CeremonyForked, _: Option<Address>) -> Option<Address> {
+    Some(event.parent)
 }
 
-fn stage_result_cb(result: Vec<Log>, wanted: Option<Address>) -> Option<Vec<u8>> {
-    for i in 0..result.len() {
-        let hash_bytes: &[u8] = &result[i].data.0[0..32];
-        let stage_hash: &[u8] = &result[i].data.0[96..142];
-        let hash: H256 = H256::from(hash_bytes);
-        let publisher: Address = Address::from(hash);
-        println!("Player published results: {:?}", publisher);
-        if publisher == wanted.unwrap() {
-            return Some(stage_hash.to_vec());
-        }
+fn next_stage_cb(event: NextStage, _: Option<Address>) -> Option<bool> {
+    println!("New Stage: {:?}", event.stage);
+    Some(true)
+}
+
+fn stage_result_cb(event: StageResultPublished, wanted: Option<Address>) -> Option<Vec<u8>> {
+    println!("Player published results: {:?}", event.player);
+    if wanted.map_or(true, |wanted| event.player == wanted) {
+        return Some(event.result);
     }
     None
 }
@@ -0,0 +1,129 @@
+use bn::*;
+use bincode::SizeLimit::Infinite;
+use bincode::rustc_serialize::{encode, encode_into, decode};
+
+// The `bn` crate encodes a non-zero group element as the byte 4 followed by
+// its affine coordinates, each as a canonical 32 byte big-endian integer.
+// An element of Fq2 is written as c0 followed by c1. We build on that
+// encoding to convert points from and to the layouts used by other tools.
+
+const UNCOMPRESSED: u8 = 4;
+
+/// Construct a G1 element from big-endian affine coordinates. Returns
+/// `None` if the coordinates are not a point on the curve.
+pub fn g1_from_be_bytes(x: &[u8], y: &[u8]) -> Option<G1> {
+    assert_eq!(x.len(), 32);
+    assert_eq!(y.len(), 32);
+
+    let mut buf = Vec::with_capacity(65);
+    buf.push(UNCOMPRESSED);
+    buf.extend_from_slice(x);
+    buf.extend_from_slice(y);
+
+    decode(&buf).ok()
+}
+
+/// Construct a G2 element from big-endian affine coordinates, given as
+/// `(c0, c1)` of each Fq2 coordinate. Returns `None` if the coordinates
+/// are not a point in the subgroup.
+pub fn g2_from_be_bytes(x: (&[u8], &[u8]), y: (&[u8], &[u8])) -> Option<G2> {
+    let mut buf = Vec::with_capacity(129);
+    buf.push(UNCOMPRESSED);
+    for coordinate in &[x.0, x.1, y.0, y.1] {
+        assert_eq!(coordinate.len(), 32);
+        buf.extend_from_slice(coordinate);
+    }
+
+    decode(&buf).ok()
+}
+
+/// The big-endian affine coordinates `(x, y)` of a G1 element, or `None`
+/// for the point at infinity.
+pub fn g1_to_be_bytes(p: &G1) -> Option<([u8; 32], [u8; 32])> {
+    if p.is_zero() {
+        return None;
+    }
+
+    let buf = encode(p, Infinite).expect("group element should never fail to encode");
+    assert_eq!(buf.len(), 65);
+
+    Some((to_array(&buf[1..33]), to_array(&buf[33..65])))
+}
+
+/// The big-endian affine coordinates `(x, y)` of a G2 element, each as
+/// `[c0, c1]`, or `None` for the point at infinity.
+pub fn g2_to_be_bytes(p: &G2) -> Option<([[u8; 32]; 2], [[u8; 32]; 2])> {
+    if p.is_zero() {
+        return None;
+    }
+
+    let buf = encode(p, Infinite).expect("group element should never fail to encode");
+    assert_eq!(buf.len(), 129);
+
+    Some((
+        [to_array(&buf[1..33]), to_array(&buf[33..65])],
+        [to_array(&buf[65..97]), to_array(&buf[97..129])]
+    ))
+}
+
+/// Construct a scalar from its canonical big-endian representation.
+/// Returns `None` if it is not less than the order of the groups.
+pub fn fr_from_be_bytes(bytes: &[u8]) -> Option<Fr> {
+    assert_eq!(bytes.len(), 32);
+
+    decode(bytes).ok()
+}
+
+/// The canonical big-endian representation of a scalar.
+pub fn fr_to_be_bytes(s: &Fr) -> [u8; 32] {
+    let mut arr = [0; 32];
+    fr_write_be(s, &mut arr);
+    arr
+}
+
+/// Writes the canonical big-endian representation of a scalar to the 32
+/// bytes, without a copy on the heap, so secret scalars can be written to
+/// protected memory.
+pub fn fr_write_be(s: &Fr, out: &mut [u8]) {
+    assert_eq!(out.len(), 32);
+
+    let mut writer = out;
+    encode_into(s, &mut writer, Infinite).expect("scalar should never fail to encode");
+}
+
+fn to_array(bytes: &[u8]) -> [u8; 32] {
+    let mut arr = [0; 32];
+    arr.copy_from_slice(bytes);
+    arr
+}
+
+#[test]
+fn affine_roundtrip() {
+    let rng = &mut ::rand::thread_rng();
+
+    for _ in 0..20 {
+        let p = G1::random(rng);
+        let (x, y) = g1_to_be_bytes(&p).unwrap();
+        assert!(g1_from_be_bytes(&x, &y).unwrap() == p);
+        assert!(g1_from_be_bytes(&y, &x).is_none());
+
+        let q = G2::random(rng);
+        let (x, y) = g2_to_be_bytes(&q).unwrap();
+        assert!(g2_from_be_bytes((&x[0], &x[1]), (&y[0], &y[1])).unwrap() == q);
+        assert!(g2_from_be_bytes((&x[1], &x[0]), (&y[1], &y[0])).is_none());
+
+        let s = Fr::random(rng);
+        assert!(fr_from_be_bytes(&fr_to_be_bytes(&s)).unwrap() == s);
+    }
+
+    assert!(g1_to_be_bytes(&G1::zero()).is_none());
+    assert!(g2_to_be_bytes(&G2::zero()).is_none());
+    assert!(fr_from_be_bytes(&[0xff; 32]).is_none());
+
+    // The generator of G1 is (1, 2).
+    let (x, y) = g1_to_be_bytes(&G1::one()).unwrap();
+    assert_eq!(&x[..31], &[0; 31][..]);
+    assert_eq!(x[31], 1);
+    assert_eq!(&y[..31], &[0; 31][..]);
+    assert_eq!(y[31], 2);
+}
@@ -0,0 +1,99 @@
+//! A random beacon as the final contribution to every stage.
+//!
+//! After the last player, the stages are determined only by the secrets
+//! of the players. The beacon derives a `PrivateKey` deterministically
+//! from a public value that is unpredictable at the start of the protocol
+//! (e.g. the hash of a future block), so that anyone can recompute and
+//! check its contribution. The value is hashed `2^iterations_exp` times
+//! with blake2s before it seeds the random number generator, to make it
+//! expensive to grind through candidate values.
+
+use rand::{SeedableRng};
+use rand::chacha::ChaChaRng;
+use blake2_rfc::blake2s::blake2s;
+use byteorder::{ByteOrder, LittleEndian};
+use super::{Transform, Verify};
+use super::secrets::{PrivateKey, PublicKey};
+
+pub struct Beacon {
+    value: Vec<u8>,
+    iterations_exp: u8
+}
+
+impl Beacon {
+    pub fn new(value: Vec<u8>, iterations_exp: u8) -> Self {
+        assert!(iterations_exp < 64, "Too many beacon iterations!");
+
+        Beacon {
+            value: value,
+            iterations_exp: iterations_exp
+        }
+    }
+
+    fn seed(&self) -> [u32; 8] {
+        let mut cur = [0; 32];
+        cur.copy_from_slice(blake2s(32, &[], &self.value).as_bytes());
+
+        for _ in 0..(1u64 << self.iterations_exp) {
+            let next = blake2s(32, &[], &cur);
+            cur.copy_from_slice(next.as_bytes());
+        }
+
+        let mut seed: [u32; 8] = [0; 8];
+        for i in 0..8 {
+            seed[i] = LittleEndian::read_u32(&cur[(i*4)..]);
+        }
+
+        seed
+    }
+
+    /// The secrets and the public key of the beacon. Both are sampled
+    /// from the same deterministic random number generator, so anyone
+    /// with the beacon value obtains the same keys.
+    pub fn keys(&self) -> (PrivateKey, PublicKey) {
+        let mut rng = ChaChaRng::from_seed(&self.seed());
+        let privkey = PrivateKey::new(&mut rng);
+        let pubkey = privkey.pubkey(&mut rng);
+
+        (privkey, pubkey)
+    }
+
+    /// Transforms the stage with the beacon's secrets and checks the
+    /// transformation against the beacon's public key.
+    pub fn apply<S: Transform + Verify + Clone>(&self, stage: &S) -> S {
+        let (privkey, pubkey) = self.keys();
+        let mut next = stage.clone();
+        next.transform(&privkey);
+        assert!(next.verify_transform(stage, &pubkey), "Invalid beacon transformation!");
+
+        next
+    }
+}
+
+#[test]
+fn beacon_is_deterministic() {
+    use bincode::SizeLimit::Infinite;
+    use bincode::rustc_serialize::encode;
+    use bn::*;
+    use super::Stage1Contents;
+
+    let stage = Stage1Contents {
+        v1: (0..8).map(|_| G1::one()).collect(),
+        v2: (0..8).map(|_| G2::one()).collect()
+    };
+
+    let beacon = Beacon::new(b"0000000000000000000a1b2c3d".to_vec(), 4);
+    let same = Beacon::new(b"0000000000000000000a1b2c3d".to_vec(), 4);
+    let other_value = Beacon::new(b"0000000000000000000a1b2c3e".to_vec(), 4);
+    let other_iterations = Beacon::new(b"0000000000000000000a1b2c3d".to_vec(), 5);
+
+    assert!(beacon.keys().1 == same.keys().1);
+    assert!(beacon.keys().1 != other_value.keys().1);
+    assert!(beacon.keys().1 != other_iterations.keys().1);
+
+    let first = beacon.apply(&stage);
+    let second = same.apply(&stage);
+    assert_eq!(encode(&first, Infinite).unwrap(), encode(&second, Infinite).unwrap());
+    assert!(first.verify_transform(&stage, &same.keys().1));
+    assert!(!first.verify_transform(&stage, &other_value.keys().1));
+}
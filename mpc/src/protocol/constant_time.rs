@@ -0,0 +1,272 @@
+//! Constant-time arithmetic with the secrets. The `Mul` of the `bn` crate
+//! doubles and adds depending on the bits of the scalar, and `Fr::pow`
+//! multiplies depending on the bits of the exponent, so their timing leaks
+//! the secrets. Instead, a point is multiplied with a fixed window: the
+//! scalar plus the order of the groups is recoded into 64 digits in `1..16`,
+//! and for every digit the point is doubled four times and the multiple of
+//! it for the digit is added, which is looked up by reading the whole table.
+//! The sequence of group operations is the same for every scalar, and the
+//! identity is never added.
+//!
+//! The field and group operations themselves are the ones of the `bn` crate.
+//! Its normalization of a point inverts the projective Z coordinate in
+//! variable time, so points that depend on the secrets are blinded with a
+//! random point before they are normalized.
+
+use bn::*;
+use byteorder::{BigEndian, ByteOrder};
+use rand::Rng;
+use std::cell::RefCell;
+use std::mem;
+use super::affine::fr_write_be;
+use super::protected::Protected;
+
+const WINDOW: usize = 4;
+const TABLE_SIZE: usize = 1 << WINDOW;
+const DIGITS: usize = 64;
+
+/// The order of the groups, as little-endian 64 bit limbs.
+const ORDER: [u64; 4] = [0x43e1f593f0000001, 0x2833e84879b97091, 0xb85045b68181585d, 0x30644e72e131a029];
+
+/// The memory a scalar is recoded in: its bytes, its limbs and its digits.
+#[derive(Clone, Copy)]
+struct Recoding {
+    bytes: [u8; 32],
+    limbs: [u64; 4],
+    digits: [u8; DIGITS]
+}
+
+/// A secret scalar, recoded for multiplications. Recoding once is cheaper
+/// when many points are multiplied by the same scalar. The recoding is kept
+/// in protected memory, which is reused for the next scalar by `recode`.
+pub struct SecretScalar {
+    recoding: Protected<Recoding>
+}
+
+thread_local! {
+    /// The recoding of the scalars multiplied by `mul`, so that protected
+    /// memory is not allocated and locked for every multiplication.
+    static SCRATCH: RefCell<SecretScalar> = RefCell::new(SecretScalar::new(&Fr::zero()));
+}
+
+impl SecretScalar {
+    pub fn new(s: &Fr) -> SecretScalar {
+        let mut scalar = SecretScalar {
+            recoding: Protected::new(Recoding {
+                bytes: [0; 32],
+                limbs: [0; 4],
+                digits: [0; DIGITS]
+            })
+        };
+        scalar.recode(s);
+        scalar
+    }
+
+    /// Recodes `s + ORDER` into digits `d_i` in `1..16`, least significant
+    /// first, such that `s + ORDER = sum(d_i * 16^i)`. The sum of the order
+    /// and any scalar has such a representation with exactly 64 digits, and
+    /// multiplying a point in the groups by it is the same as multiplying by
+    /// the scalar.
+    pub fn recode(&mut self, s: &Fr) {
+        let &mut Recoding { ref mut bytes, ref mut limbs, ref mut digits } = &mut *self.recoding;
+        fr_write_be(s, &mut bytes[..]);
+        for i in 0..4 {
+            limbs[i] = BigEndian::read_u64(&bytes[(24 - i * 8)..]);
+        }
+
+        let mut carry = 0;
+        for i in 0..4 {
+            let (sum, c1) = limbs[i].overflowing_add(ORDER[i]);
+            let (sum, c2) = sum.overflowing_add(carry);
+            limbs[i] = sum;
+            carry = (c1 | c2) as u64;
+        }
+
+        for digit in digits.iter_mut() {
+            // A digit of 0 becomes 16, without branching on it.
+            let d = limbs[0] & 0xf;
+            let d = d | (is_zero(d) << WINDOW);
+            *digit = d as u8;
+
+            // limbs = (limbs - d) / 16
+            let mut borrow = d;
+            for i in 0..4 {
+                let (diff, b) = limbs[i].overflowing_sub(borrow);
+                limbs[i] = diff;
+                borrow = b as u64;
+            }
+            for i in 0..3 {
+                limbs[i] = (limbs[i] >> WINDOW) | (limbs[i + 1] << (64 - WINDOW));
+            }
+            limbs[3] >>= WINDOW;
+        }
+    }
+
+    /// `p * s` in constant time.
+    pub fn mul<G: Group>(&self, p: G) -> G {
+        let mut table = [p; TABLE_SIZE];
+        for i in 1..TABLE_SIZE {
+            table[i] = table[i - 1] + p;
+        }
+
+        let digits = &self.recoding.digits;
+        let mut acc = select(&table, digits[DIGITS - 1] as usize - 1);
+        for digit in digits[..DIGITS - 1].iter().rev() {
+            for _ in 0..WINDOW {
+                acc = acc + acc;
+            }
+            acc = acc + select(&table, *digit as usize - 1);
+        }
+
+        acc
+    }
+}
+
+/// `p * s` in constant time. The scalar is recoded in the protected memory
+/// of the thread, which is overwritten with zeros afterwards.
+pub fn mul<G: Group>(p: G, s: &Fr) -> G {
+    SCRATCH.with(|scratch| {
+        let mut scratch = scratch.borrow_mut();
+        scratch.recode(s);
+        let product = scratch.mul(p);
+        scratch.recode(&Fr::zero());
+        product
+    })
+}
+
+/// `p * s` in constant time, normalized with a fresh blinding, for single
+/// points that leave the protocol, e.g. in a public key or a stage.
+pub fn mul_normalized<G: Group>(p: G, s: &Fr) -> G {
+    let mut product = mul(p, s);
+    Blinding::new(&mut ::rand::thread_rng()).normalize(&mut product);
+    product
+}
+
+/// A secret random point that blinds the normalization of points which
+/// depend on the secrets.
+pub struct Blinding<G: Group> {
+    r: G
+}
+
+impl<G: Group> Blinding<G> {
+    pub fn new<R: Rng>(rng: &mut R) -> Blinding<G> {
+        Blinding {
+            r: mul(G::one(), &Protected::new(Fr::random(rng)))
+        }
+    }
+
+    /// Normalizes the point. Adding and subtracting the random point
+    /// multiplies the Z coordinate that is inverted by the unknown Z
+    /// coordinate of the random point and the differences of the additions,
+    /// so the timing of the inversion does not depend on the point. The
+    /// random point is doubled for every normalization.
+    pub fn normalize(&mut self, p: &mut G) {
+        self.r = self.r + self.r;
+        let mut blinded = (*p + self.r) - self.r;
+        blinded.normalize();
+        *p = blinded;
+    }
+}
+
+/// `base^exp` for a secret base, which multiplies in every step.
+pub fn pow(base: &Fr, exp: u64) -> Fr {
+    let mut acc = Fr::one();
+    for i in (0..64).rev() {
+        acc = acc * acc;
+        let product = acc * *base;
+        acc = select(&[acc, product], ((exp >> i) & 1) as usize);
+    }
+
+    acc
+}
+
+/// 1 if `x` is 0, 0 otherwise.
+fn is_zero(x: u64) -> u64 {
+    ((x | x.wrapping_neg()) >> 63) ^ 1
+}
+
+/// `table[index]`, which reads every entry of the table so that the memory
+/// accesses do not depend on the index.
+fn select<T: Copy>(table: &[T], index: usize) -> T {
+    let mut selected = table[0];
+    let dst = &mut selected as *mut T as *mut u8;
+    for (i, entry) in table.iter().enumerate() {
+        // 0xff for the entry at the index, 0 for all others.
+        let mask = (is_zero((i ^ index) as u64) as u8).wrapping_neg();
+        let src = entry as *const T as *const u8;
+        for b in 0..mem::size_of::<T>() {
+            unsafe {
+                let x = *dst.offset(b as isize);
+                *dst.offset(b as isize) = x ^ (mask & (x ^ *src.offset(b as isize)));
+            }
+        }
+    }
+
+    selected
+}
+
+#[test]
+fn constant_time_mul() {
+    fn mul_group<G: Group>() {
+        let rng = &mut ::rand::thread_rng();
+        let edge = vec![Fr::zero(), Fr::one(), Fr::zero() - Fr::one(), Fr::from_str("16").unwrap()];
+        let random: Vec<Fr> = (0..50).map(|_| Fr::random(rng)).collect();
+        for s in edge.into_iter().chain(random) {
+            let p = G::random(rng);
+            assert!(mul(p, &s) == p * s);
+        }
+        assert!(mul(G::zero(), &Fr::random(rng)).is_zero());
+    }
+
+    mul_group::<G1>();
+    mul_group::<G2>();
+}
+
+#[test]
+fn constant_time_pow() {
+    let rng = &mut ::rand::thread_rng();
+    for exp in vec![0, 1, 2, 3, 1000, 1 << 20, u64::max_value()] {
+        let base = Fr::random(rng);
+        assert!(pow(&base, exp) == base.pow(Fr::from_str(&format!("{}", exp)).unwrap()));
+    }
+
+    let table = [1u64, 2, 3, 4];
+    for i in 0..4 {
+        assert_eq!(select(&table, i), table[i]);
+    }
+}
+
+#[test]
+fn recoding_is_reused() {
+    let rng = &mut ::rand::thread_rng();
+    let (a, b) = (Fr::random(rng), Fr::random(rng));
+    let mut scalar = SecretScalar::new(&a);
+    let p = G1::random(rng);
+    assert!(scalar.mul(p) == p * a);
+    scalar.recode(&b);
+    assert!(scalar.mul(p) == p * b);
+}
+
+#[test]
+fn blinded_normalize() {
+    fn normalize_group<G: Group>() {
+        let rng = &mut ::rand::thread_rng();
+        let mut blinding = Blinding::new(rng);
+        for _ in 0..10 {
+            let p = G::random(rng);
+            let mut q = p;
+            blinding.normalize(&mut q);
+            assert!(q == p);
+        }
+        let s = Fr::random(rng);
+        let p = G::random(rng);
+        assert!(mul_normalized(p, &s) == p * s);
+
+        let mut zero = G::zero();
+        blinding.normalize(&mut zero);
+        assert!(zero.is_zero());
+    }
+
+    normalize_group::<G1>();
+    normalize_group::<G2>();
+}
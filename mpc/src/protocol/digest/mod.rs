@@ -4,6 +4,12 @@ use rustc_serialize::{Encodable, Encoder, Decodable, Decoder};
 use bincode::SizeLimit::Infinite;
 use bincode::rustc_serialize::encode;
 use blake2_rfc::blake2b::blake2b;
+use super::sha3::{Digest, Keccak256};
+
+use std::fmt;
+
+pub mod base58;
+use self::base58::{FromBase58, ToBase58};
 
 macro_rules! digest_impl {
     ($name:ident, $bytes:expr, $hash:ident) => {
@@ -15,7 +21,7 @@ macro_rules! digest_impl {
                 match serialized {
                     Ok(ref serialized) => {
                         let mut buf: [u8; $bytes] = [0; $bytes];
-                        buf.copy_from_slice(&$hash($bytes, &[], serialized).as_bytes());
+                        buf.copy_from_slice(&$hash(serialized));
 
                         Some($name(buf))
                     },
@@ -63,7 +69,18 @@ macro_rules! digest_impl {
     }
 }
 
-digest_impl!(Digest512, 64, blake2b);
+fn blake2b_512(data: &[u8]) -> Vec<u8> {
+    blake2b(64, &[], data).as_bytes().to_vec()
+}
+
+fn keccak_256(data: &[u8]) -> Vec<u8> {
+    Keccak256::digest(data).as_slice().to_vec()
+}
+
+digest_impl!(Digest512, 64, blake2b_512);
+// The commitments to the public keys, which are Keccak-256 as the hashes of
+// the contract.
+digest_impl!(Digest256, 32, keccak_256);
 
 impl Digest512 {
     pub fn interpret(&self) -> Fr {
@@ -71,6 +88,31 @@ impl Digest512 {
     }
 }
 
+/// The base58check encoding of the digest.
+impl fmt::Display for Digest256 {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", (&self.0[..]).to_base58check())
+    }
+}
+
+impl Digest256 {
+    /// Parses the base58check encoding of a digest. Returns `None` if the
+    /// checksum or the length is wrong.
+    pub fn from_string(s: &str) -> Option<Digest256> {
+        let decoded: Vec<u8> = match FromBase58::from_base58check(s) {
+            Ok(decoded) => decoded,
+            Err(_) => return None
+        };
+        if decoded.len() != 32 {
+            return None;
+        }
+
+        let mut buf = [0; 32];
+        buf.copy_from_slice(&decoded);
+        Some(Digest256(buf))
+    }
+}
+
 #[test]
 fn digest_string_repr() {
     use super::secrets::*;
@@ -94,3 +136,18 @@ fn digest_string_repr() {
     assert!(Digest256::from_string("2b8c8iK5PGtStZzEz45ycJSQLq1RPXGkjqmWAM2Q8jQ4dqVHkY").is_none());
     assert!(Digest256::from_string("1b8c8iK5PGtStZzEz45ycJSQLq1RPXGkjqmWAM1Q8jQ4dqVHkY").is_none());
 }
+
+#[test]
+fn digest256_is_keccak() {
+    use super::secrets::*;
+
+    // Keccak-256 of the encoding of 0u8, a single zero byte.
+    assert_eq!(&Digest256::from(&0u8).unwrap().0[..], &[
+        0xbc, 0x36, 0x78, 0x9e, 0x7a, 0x1e, 0x28, 0x14, 0x36, 0x46, 0x42, 0x29, 0x82, 0x8f, 0x81, 0x7d,
+        0x66, 0x12, 0xf7, 0xb4, 0x77, 0xd6, 0x65, 0x91, 0xff, 0x96, 0xa9, 0xe0, 0x64, 0xbc, 0xc9, 0x8a
+    ][..]);
+
+    let rng = &mut ::rand::thread_rng();
+    let pubkey = PrivateKey::new(rng).pubkey(rng);
+    assert!(pubkey.hash() == Digest256::from(&pubkey).unwrap());
+}
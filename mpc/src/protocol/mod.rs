@@ -31,21 +31,36 @@
 
 use bn::*;
 
+extern crate libc;
 extern crate sha3;
 
 #[cfg(feature = "snark")]
 use snark::*;
 
 mod secrets;
+mod protected;
+mod constant_time;
 mod spair;
 mod nizk;
 mod multicore;
+pub mod affine;
+mod powersoftau;
+mod beacon;
+mod pghr13;
 #[macro_use]
 mod digest;
 pub use self::secrets::*;
+pub use self::protected::{zeroize_string, zeroize_vec, Protected};
 pub use self::digest::*;
+pub use self::powersoftau::{hash_powers_of_tau, PowersOfTauError};
+pub use self::beacon::Beacon;
+pub use self::nizk::NizkTranscript;
+pub use self::pghr13::{VerifyingKey, Proof};
+#[cfg(test)]
+pub use self::pghr13::forge_proof;
 use self::spair::*;
 use self::multicore::*;
+use self::constant_time::{mul, mul_normalized, pow, Blinding};
 
 #[cfg(feature = "snark")]
 mod qap;
@@ -79,14 +94,16 @@ impl Stage1Contents {
 impl Transform for Stage1Contents {
     fn transform(&mut self, s: &PrivateKey) {
         parallel_two(&mut self.v1, &mut self.v2, |start, v1, v2| {
-            let mut c = s.tau.pow(Fr::from_str(&format!("{}", start)).unwrap());
+            let mut c = Protected::new(pow(&s.tau, start as u64));
+            let rng = &mut ::rand::thread_rng();
+            let (mut blinding1, mut blinding2) = (Blinding::new(rng), Blinding::new(rng));
 
             for (g1, g2) in v1.iter_mut().zip(v2.iter_mut()) {
-                *g1 = *g1 * c;
-                g1.normalize();
-                *g2 = *g2 * c;
-                g2.normalize();
-                c = c * s.tau;
+                *g1 = mul(*g1, &c);
+                blinding1.normalize(g1);
+                *g2 = mul(*g2, &c);
+                blinding2.normalize(g2);
+                *c = *c * s.tau;
             }
         }, ::THREADS);
     }
@@ -156,17 +173,18 @@ impl Stage2Contents {
 
 impl Transform for Stage2Contents {
     fn transform(&mut self, s: &PrivateKey) {
-        self.vk_a = self.vk_a * s.alpha_a;
-        self.vk_b = self.vk_b * s.alpha_b;
-        self.vk_c = self.vk_c * s.alpha_c;
-        self.vk_z = self.vk_z * (s.rho_a * s.rho_b);
-        mul_all_by(&mut self.pk_a, s.rho_a);
-        mul_all_by(&mut self.pk_a_prime, s.rho_a * s.alpha_a);
-        mul_all_by(&mut self.pk_b, s.rho_b);
-        mul_all_by(&mut self.pk_b_temp, s.rho_b);
-        mul_all_by(&mut self.pk_b_prime, s.rho_b * s.alpha_b);
-        mul_all_by(&mut self.pk_c, s.rho_a * s.rho_b);
-        mul_all_by(&mut self.pk_c_prime, s.rho_a * s.rho_b * s.alpha_c);
+        let rho_a_rho_b = Protected::new(s.rho_a * s.rho_b);
+        self.vk_a = mul_normalized(self.vk_a, &s.alpha_a);
+        self.vk_b = mul_normalized(self.vk_b, &s.alpha_b);
+        self.vk_c = mul_normalized(self.vk_c, &s.alpha_c);
+        self.vk_z = mul_normalized(self.vk_z, &rho_a_rho_b);
+        mul_all_by_secret(&mut self.pk_a, &s.rho_a);
+        mul_all_by_secret(&mut self.pk_a_prime, &Protected::new(s.rho_a * s.alpha_a));
+        mul_all_by_secret(&mut self.pk_b, &s.rho_b);
+        mul_all_by_secret(&mut self.pk_b_temp, &s.rho_b);
+        mul_all_by_secret(&mut self.pk_b_prime, &Protected::new(s.rho_b * s.alpha_b));
+        mul_all_by_secret(&mut self.pk_c, &rho_a_rho_b);
+        mul_all_by_secret(&mut self.pk_c_prime, &Protected::new(*rho_a_rho_b * s.alpha_c));
     }
 }
 
@@ -286,11 +304,11 @@ impl Stage3Contents {
 
 impl Transform for Stage3Contents {
     fn transform(&mut self, s: &PrivateKey) {
-        let betagamma = s.beta * s.gamma;
-        self.vk_gamma = self.vk_gamma * s.gamma;
-        self.vk_beta_gamma_one = self.vk_beta_gamma_one * betagamma;
-        self.vk_beta_gamma_two = self.vk_beta_gamma_two * betagamma;
-        mul_all_by(&mut self.pk_k, s.beta);
+        let betagamma = Protected::new(s.beta * s.gamma);
+        self.vk_gamma = mul_normalized(self.vk_gamma, &s.gamma);
+        self.vk_beta_gamma_one = mul_normalized(self.vk_beta_gamma_one, &betagamma);
+        self.vk_beta_gamma_two = mul_normalized(self.vk_beta_gamma_two, &betagamma);
+        mul_all_by_secret(&mut self.pk_k, &s.beta);
     }
 }
 
@@ -354,6 +372,26 @@ pub fn keypair(
     )
 }
 
+/// The verification key of the keypair, as a set of points which can be
+/// exported to other verifiers.
+#[cfg(feature = "snark")]
+pub fn verifying_key(
+    cs: &CS,
+    stage2: &Stage2Contents,
+    stage3: &Stage3Contents
+) -> VerifyingKey {
+    VerifyingKey {
+        a: stage2.vk_a,
+        b: stage2.vk_b,
+        c: stage2.vk_c,
+        gamma: stage3.vk_gamma,
+        gamma_beta_1: stage3.vk_beta_gamma_one,
+        gamma_beta_2: stage3.vk_beta_gamma_two,
+        z: stage2.vk_z,
+        ic: stage2.pk_a[0..cs.num_inputs + 1].to_vec()
+    }
+}
+
 #[test]
 fn compare_to_libsnark_generate() {
     let rng = &mut ::rand::thread_rng();
@@ -1,5 +1,6 @@
 use bn::*;
 use crossbeam;
+use super::constant_time::{Blinding, SecretScalar};
 
 fn calculate_window_size<T>(v: &[T], threads: usize) -> usize
 {
@@ -87,6 +88,20 @@ pub fn mul_all_by<G: Group>(v: &mut [G], c: Fr) {
     }, ::THREADS);
 }
 
+/// Like `mul_all_by`, but in constant time for a secret scalar, and the
+/// products are normalized with a blinding.
+pub fn mul_all_by_secret<G: Group>(v: &mut [G], c: &Fr) {
+    let c = SecretScalar::new(c);
+    let c = &c;
+    parallel(v, |_, v| {
+        let mut blinding = Blinding::new(&mut ::rand::thread_rng());
+        for i in v {
+            *i = c.mul(*i);
+            blinding.normalize(i);
+        }
+    }, ::THREADS);
+}
+
 pub fn add_all_to<G: Group>(v: &mut [G], other: &[G]) {
     assert_eq!(v.len(), other.len());
 
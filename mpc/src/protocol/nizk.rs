@@ -1,6 +1,8 @@
 use bn::*;
 use rand::Rng;
 use super::digest::Digest512;
+use super::constant_time::mul_normalized;
+use super::protected::Protected;
 
 #[derive(PartialEq, Eq, Clone, RustcEncodable, RustcDecodable)]
 pub struct Nizk<G: Group> {
@@ -8,65 +10,110 @@ pub struct Nizk<G: Group> {
     u: Fr
 }
 
+/// Version tag of the transcript, which has to be changed whenever its
+/// contents change.
+const TRANSCRIPT_VERSION: &str = "DistributedMPC/nizk/v1";
+
+/// Everything a nizk is bound to besides the group elements: the ceremony,
+/// given by the chain and the address of the contract, the player and the
+/// commitments of all players. A nizk created for one ceremony or player is
+/// invalid for any other.
+#[derive(Clone, RustcEncodable, RustcDecodable)]
+pub struct NizkTranscript {
+    version: String,
+    chain_id: u64,
+    contract: [u8; 20],
+    player: [u8; 20],
+    player_index: u64,
+    commitments: Digest512
+}
+
+impl NizkTranscript {
+    pub fn new(chain_id: u64, contract: [u8; 20], player: [u8; 20], player_index: u64, commitments: Digest512) -> NizkTranscript {
+        NizkTranscript {
+            version: TRANSCRIPT_VERSION.to_string(),
+            chain_id: chain_id,
+            contract: contract,
+            player: player,
+            player_index: player_index,
+            commitments: commitments
+        }
+    }
+}
+
 #[derive(RustcEncodable)]
 pub struct NizkChallengePreimage<'a, G> {
     r: G,
     f: G,
     fs: G,
-    extra: &'a Digest512
+    transcript: &'a NizkTranscript
 }
 
 impl<G: Group> Nizk<G> {
     /// Constructing the non-interactive schnorr proof for knowledge of log
     /// of s*f in base f, i.e., knowledge of s
-    pub fn new<R: Rng>(rng: &mut R, f: G, s: Fr, extra: &Digest512) -> Nizk<G> {
-        let a = Fr::random(rng);
-        let r = f * a;
+    pub fn new<R: Rng>(rng: &mut R, f: G, s: &Fr, transcript: &NizkTranscript) -> Nizk<G> {
+        let a = Protected::new(Fr::random(rng));
+        let r = mul_normalized(f, &a);
         let c = Digest512::from(&NizkChallengePreimage {
             r: r,
             f: f,
-            fs: f * s,
-            extra: extra
+            fs: mul_normalized(f, s),
+            transcript: transcript
         }).expect("nizk challenge preimage should not fail to encode").interpret();
         Nizk {
             r: r,
-            u: a + c * s
+            u: *a + c * *s
         }
     }
 
     /// Verify the Nizk
-    pub fn verify(&self, f: G, fs: G, extra: &Digest512) -> bool {
+    pub fn verify(&self, f: G, fs: G, transcript: &NizkTranscript) -> bool {
         let c = Digest512::from(&NizkChallengePreimage{
             r: self.r,
             f: f,
             fs: fs,
-            extra: extra
+            transcript: transcript
         }).expect("group element should never fail to encode").interpret();
         
         (f * self.u) == (self.r + fs * c)
     }
 }
 
+/// A transcript for tests, which differs for each player index.
+#[cfg(test)]
+pub fn test_transcript(player_index: u64, commitments: &str) -> NizkTranscript {
+    NizkTranscript::new(1, [1; 20], [2; 20], player_index, Digest512::from(&commitments).unwrap())
+}
+
 #[test]
 fn nizk_test() {
     fn nizk_test_group<G: Group>() {
         let rng = &mut ::rand::thread_rng();
-        let correct_extra = Digest512::from(&"test").unwrap();
-        let incorrect_extra = Digest512::from(&"tesst").unwrap();
+        let correct_transcript = test_transcript(0, "test");
+        let incorrect_transcripts = vec![
+            test_transcript(0, "tesst"),
+            test_transcript(1, "test"),
+            NizkTranscript::new(2, [1; 20], [2; 20], 0, Digest512::from(&"test").unwrap()),
+            NizkTranscript::new(1, [3; 20], [2; 20], 0, Digest512::from(&"test").unwrap()),
+            NizkTranscript::new(1, [1; 20], [3; 20], 0, Digest512::from(&"test").unwrap())
+        ];
         for _  in 0..50 {
             let f = G::random(rng);
             let s = Fr::random(rng);
             let fs = f * s;
 
-            let proof = Nizk::new(rng, f, s, &correct_extra);
-            assert!(proof.verify(f, fs, &correct_extra));
+            let proof = Nizk::new(rng, f, &s, &correct_transcript);
+            assert!(proof.verify(f, fs, &correct_transcript));
             {
                 let r = Fr::random(rng);
-                assert!(!proof.verify(f * r, fs * r, &correct_extra));
+                assert!(!proof.verify(f * r, fs * r, &correct_transcript));
+            }
+            for transcript in &incorrect_transcripts {
+                assert!(!proof.verify(f, fs, transcript));
             }
-            assert!(!proof.verify(f, fs, &incorrect_extra));
-            assert!(!proof.verify(f, f * Fr::random(rng), &correct_extra));
-            assert!(!proof.verify(f * Fr::random(rng), fs, &correct_extra));
+            assert!(!proof.verify(f, f * Fr::random(rng), &correct_transcript));
+            assert!(!proof.verify(f * Fr::random(rng), fs, &correct_transcript));
         }
     }
 
@@ -0,0 +1,165 @@
+//! The verification key and proofs of the PGHR13 proving system, as
+//! implemented by `r1cs_ppzksnark` in libsnark. This mirrors the checks of
+//! libsnark's verifier, so that verification keys can be exported to other
+//! verifiers (e.g. a Solidity contract) and checked against proofs.
+
+use bn::*;
+
+#[derive(Clone, PartialEq, Eq, RustcEncodable, RustcDecodable)]
+pub struct VerifyingKey {
+    pub a: G2, // alpha_a in G2
+    pub b: G1, // alpha_b in G1
+    pub c: G2, // alpha_c in G2
+    pub gamma: G2, // gamma in G2
+    pub gamma_beta_1: G1, // gamma * beta in G1
+    pub gamma_beta_2: G2, // gamma * beta in G2
+    pub z: G2, // rho_a * rho_b * Z(tau) in G2
+    pub ic: Vec<G1> // rho_a * A_i(tau) for the constant and each public input
+}
+
+#[derive(Clone, PartialEq, Eq, RustcEncodable, RustcDecodable)]
+pub struct Proof {
+    pub a: G1,
+    pub a_p: G1,
+    pub b: G2,
+    pub b_p: G1,
+    pub c: G1,
+    pub c_p: G1,
+    pub h: G1,
+    pub k: G1
+}
+
+#[cfg(feature = "snark")]
+impl<'a> From<&'a ::snark::Proof> for Proof {
+    fn from(proof: &'a ::snark::Proof) -> Proof {
+        let (a, a_p, b, b_p, c, c_p, h, k) = proof.points();
+
+        Proof {
+            a: a,
+            a_p: a_p,
+            b: b,
+            b_p: b_p,
+            c: c,
+            c_p: c_p,
+            h: h,
+            k: k
+        }
+    }
+}
+
+impl VerifyingKey {
+    /// The number of public inputs a proof is verified against.
+    pub fn num_inputs(&self) -> usize {
+        self.ic.len() - 1
+    }
+
+    /// Verifies the proof for the given public inputs.
+    pub fn verify(&self, proof: &Proof, inputs: &[Fr]) -> bool {
+        if inputs.len() != self.num_inputs() {
+            return false;
+        }
+
+        let mut acc = self.ic[0];
+        for (ic, input) in self.ic[1..].iter().zip(inputs.iter()) {
+            acc = acc + *ic * *input;
+        }
+
+        // Knowledge commitments
+        pairing(proof.a, self.a) == pairing(proof.a_p, G2::one()) &&
+        pairing(self.b, proof.b) == pairing(proof.b_p, G2::one()) &&
+        pairing(proof.c, self.c) == pairing(proof.c_p, G2::one()) &&
+        // Same coefficients
+        pairing(proof.k, self.gamma) ==
+            pairing(acc + proof.a + proof.c, self.gamma_beta_2) * pairing(self.gamma_beta_1, proof.b) &&
+        // QAP divisibility
+        pairing(acc + proof.a, proof.b) ==
+            pairing(proof.h, self.z) * pairing(proof.c, G2::one())
+    }
+}
+
+/// A proof for the given inputs forged with the (accumulated) secrets of all
+/// players, which lets us test verifiers without a prover.
+#[cfg(test)]
+pub fn forge_proof(
+    vk: &VerifyingKey,
+    secrets: &super::secrets::PrivateKey,
+    inputs: &[Fr]
+) -> Proof {
+    let rng = &mut ::rand::thread_rng();
+
+    let mut acc = vk.ic[0];
+    for (ic, input) in vk.ic[1..].iter().zip(inputs.iter()) {
+        acc = acc + *ic * *input;
+    }
+
+    // We choose A such that acc + A = a and H = 0, so the QAP divisibility
+    // check only requires C = a * b.
+    let a = Fr::random(rng);
+    let b = Fr::random(rng);
+    let c = a * b;
+
+    let proof_a = G1::one() * a - acc;
+    let proof_c = G1::one() * c;
+
+    Proof {
+        a: proof_a,
+        a_p: proof_a * secrets.alpha_a,
+        b: G2::one() * b,
+        b_p: G1::one() * (b * secrets.alpha_b),
+        c: proof_c,
+        c_p: proof_c * secrets.alpha_c,
+        h: G1::zero(),
+        k: G1::one() * (secrets.beta * (a + b + c))
+    }
+}
+
+#[test]
+fn verify_forged_proof() {
+    use snark::CS;
+    use super::{Stage1Contents, Stage2Contents, Stage3Contents, Transform, verifying_key};
+    use super::secrets::PrivateKey;
+
+    let rng = &mut ::rand::thread_rng();
+    let cs = CS::dummy();
+    let privkeys: Vec<_> = (0..2).map(|_| PrivateKey::new(rng)).collect();
+
+    let mut stage1 = Stage1Contents::new(&cs);
+    for privkey in privkeys.iter() {
+        stage1.transform(privkey);
+    }
+    let mut stage2 = Stage2Contents::new(&cs, &stage1);
+    for privkey in privkeys.iter() {
+        stage2.transform(privkey);
+    }
+    let mut stage3 = Stage3Contents::new(&cs, &stage2);
+    for privkey in privkeys.iter() {
+        stage3.transform(privkey);
+    }
+
+    let vk = verifying_key(&cs, &stage2, &stage3);
+    assert_eq!(vk.num_inputs(), cs.num_inputs);
+
+    let mut acc = PrivateKey::new_blank();
+    for privkey in privkeys.iter() {
+        acc.multiply(privkey);
+    }
+
+    let inputs: Vec<Fr> = (0..cs.num_inputs).map(|_| Fr::random(rng)).collect();
+    let proof = forge_proof(&vk, &acc, &inputs);
+    assert!(vk.verify(&proof, &inputs));
+
+    // Wrong inputs
+    let mut wrong_inputs = inputs.clone();
+    wrong_inputs[0] = wrong_inputs[0] + Fr::one();
+    assert!(!vk.verify(&proof, &wrong_inputs));
+    assert!(!vk.verify(&proof, &inputs[1..]));
+
+    // Wrong proof
+    let mut wrong_proof = proof.clone();
+    wrong_proof.k = wrong_proof.k + G1::one();
+    assert!(!vk.verify(&wrong_proof, &inputs));
+
+    // Proof forged with the wrong secrets
+    let wrong_secrets = PrivateKey::new(rng);
+    assert!(!vk.verify(&forge_proof(&vk, &wrong_secrets, &inputs), &inputs));
+}
@@ -0,0 +1,368 @@
+//! Import of the powers of tau from a public BN254 "powers of tau"
+//! ceremony, so that the first stage inherits the security of all
+//! of its contributors.
+//!
+//! We read the uncompressed accumulator written for the next participant
+//! (the `challenge` file) with the following layout, where `n = 2^k` for
+//! the size `k` of the ceremony:
+//!
+//! * the 64 byte BLAKE2b hash of the last response
+//! * `2n - 1` powers of tau in G1
+//! * `n` powers of tau in G2
+//! * `n` powers of tau multiplied by alpha in G1
+//! * `n` powers of tau multiplied by beta in G1
+//! * beta in G2
+//!
+//! Points are written as big-endian affine coordinates, in G2 with the
+//! imaginary part of each coordinate first. The point at infinity sets
+//! the second most significant bit of the first byte. Only the powers of
+//! tau are imported; alpha and beta are not used by this protocol.
+//!
+//! The `response` files of the contributors store the same points
+//! compressed, followed by the public key of the contributor. We cannot
+//! decompress them and reject them with `PowersOfTauError::Compressed`:
+//! the ceremony's `verify_transform` writes the uncompressed accumulator
+//! of each response as `new_challenge`, which is imported instead.
+//!
+//! The contract records the Keccak-256 hash of the file, so that all
+//! players import the same powers of tau.
+
+use bn::*;
+use std::error;
+use std::fmt;
+#[cfg(feature = "snark")]
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+#[cfg(feature = "snark")]
+use snark::*;
+use super::Stage1Contents;
+use super::sha3::{Digest, Keccak256};
+use super::affine::{g1_from_be_bytes, g2_from_be_bytes};
+use super::spair::{Spair, checkseq};
+
+const HASH_SIZE: u64 = 64;
+const G1_SIZE: u64 = 64;
+const G2_SIZE: u64 = 128;
+const G1_COMPRESSED_SIZE: u64 = 32;
+const G2_COMPRESSED_SIZE: u64 = 64;
+const PUBLIC_KEY_SIZE: u64 = 6 * G1_SIZE + 3 * G2_SIZE;
+
+const FLAG_COMPRESSED: u8 = 0x80;
+const FLAG_INFINITY: u8 = 0x40;
+
+#[derive(Debug)]
+pub enum PowersOfTauError {
+    /// The accumulator could not be read
+    Io(io::Error),
+    /// The file size does not correspond to an uncompressed accumulator
+    InvalidLength(u64),
+    /// The file is a compressed response of a contributor
+    Compressed,
+    /// The hash of the file is not the one recorded in the contract
+    HashMismatch,
+    /// The ceremony has fewer powers than the constraint system requires
+    TooSmall(usize, usize),
+    /// A point is compressed, at infinity or not on the curve
+    InvalidPoint(&'static str, usize),
+    /// The powers in G1 and G2 are not powers of the same tau
+    Inconsistent
+}
+
+impl fmt::Display for PowersOfTauError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PowersOfTauError::Io(ref e) => write!(f, "could not read accumulator: {}", e),
+            PowersOfTauError::InvalidLength(len) => write!(f, "length {} is not the length of an uncompressed accumulator", len),
+            PowersOfTauError::Compressed => write!(f, "compressed responses are not supported, import the uncompressed new_challenge that verify_transform writes for the response"),
+            PowersOfTauError::HashMismatch => write!(f, "the hash of the file is not the one recorded in the contract"),
+            PowersOfTauError::TooSmall(available, required) => write!(f, "accumulator has {} powers of tau, but {} are required", available, required),
+            PowersOfTauError::InvalidPoint(group, i) => write!(f, "power {} of tau in {} is not a valid point", i, group),
+            PowersOfTauError::Inconsistent => write!(f, "powers of tau in G1 and G2 are inconsistent")
+        }
+    }
+}
+
+impl error::Error for PowersOfTauError {
+    fn description(&self) -> &str {
+        match *self {
+            PowersOfTauError::Io(_) => "io error",
+            PowersOfTauError::InvalidLength(_) => "invalid accumulator length",
+            PowersOfTauError::Compressed => "compressed accumulator",
+            PowersOfTauError::HashMismatch => "hash mismatch",
+            PowersOfTauError::TooSmall(_, _) => "accumulator too small",
+            PowersOfTauError::InvalidPoint(_, _) => "invalid point",
+            PowersOfTauError::Inconsistent => "inconsistent powers of tau"
+        }
+    }
+}
+
+impl From<io::Error> for PowersOfTauError {
+    fn from(e: io::Error) -> Self {
+        PowersOfTauError::Io(e)
+    }
+}
+
+/// The number `n` of powers in an accumulator of length `len` with `fixed`
+/// bytes besides the `per_power` bytes of each, if it is a power of two.
+fn powers(len: u64, fixed: u64, per_power: u64) -> Option<u64> {
+    if len < fixed + per_power || (len - fixed) % per_power != 0 {
+        return None;
+    }
+
+    let n = (len - fixed) / per_power;
+    if n.is_power_of_two() {
+        Some(n)
+    } else {
+        None
+    }
+}
+
+/// The number of powers of tau in G2 of an accumulator with the given
+/// file size.
+fn accumulator_size(len: u64) -> Result<u64, PowersOfTauError> {
+    // hash + (2n - 1) * G1 + n * G2 + 2n * G1 + G2
+    let fixed = HASH_SIZE - G1_SIZE + G2_SIZE;
+    let per_power = 4 * G1_SIZE + G2_SIZE;
+    if let Some(n) = powers(len, fixed, per_power) {
+        return Ok(n);
+    }
+
+    // the same points compressed, followed by the public key
+    let fixed = HASH_SIZE - G1_COMPRESSED_SIZE + G2_COMPRESSED_SIZE + PUBLIC_KEY_SIZE;
+    let per_power = 4 * G1_COMPRESSED_SIZE + G2_COMPRESSED_SIZE;
+    match powers(len, fixed, per_power) {
+        Some(_) => Err(PowersOfTauError::Compressed),
+        None => Err(PowersOfTauError::InvalidLength(len))
+    }
+}
+
+/// The Keccak-256 hash of a powers of tau file, which the contract records.
+pub fn hash_powers_of_tau<R: Read>(reader: &mut R) -> io::Result<[u8; 32]> {
+    let mut hasher = Keccak256::new();
+    let mut buf = [0; 1 << 16];
+    loop {
+        let read = try!(reader.read(&mut buf));
+        if read == 0 {
+            break;
+        }
+        hasher.input(&buf[..read]);
+    }
+
+    let mut hash = [0; 32];
+    hash.copy_from_slice(hasher.result().as_slice());
+    Ok(hash)
+}
+
+fn read_g1<R: Read>(reader: &mut R, i: usize) -> Result<G1, PowersOfTauError> {
+    let mut buf = [0; G1_SIZE as usize];
+    try!(reader.read_exact(&mut buf));
+
+    if buf[0] & (FLAG_COMPRESSED | FLAG_INFINITY) != 0 {
+        return Err(PowersOfTauError::InvalidPoint("G1", i));
+    }
+
+    g1_from_be_bytes(&buf[0..32], &buf[32..64]).ok_or(PowersOfTauError::InvalidPoint("G1", i))
+}
+
+fn read_g2<R: Read>(reader: &mut R, i: usize) -> Result<G2, PowersOfTauError> {
+    let mut buf = [0; G2_SIZE as usize];
+    try!(reader.read_exact(&mut buf));
+
+    if buf[0] & (FLAG_COMPRESSED | FLAG_INFINITY) != 0 {
+        return Err(PowersOfTauError::InvalidPoint("G2", i));
+    }
+
+    g2_from_be_bytes(
+        (&buf[32..64], &buf[0..32]),
+        (&buf[96..128], &buf[64..96])
+    ).ok_or(PowersOfTauError::InvalidPoint("G2", i))
+}
+
+impl Stage1Contents {
+    /// Constructs the initial stage from the first `cs.d + 1` powers of tau
+    /// of the challenge file at `path`, whose hash has to be `hash`.
+    #[cfg(feature = "snark")]
+    pub fn from_powers_of_tau(cs: &CS, path: &str, hash: &[u8; 32]) -> Result<Self, PowersOfTauError> {
+        let mut file = try!(File::open(path));
+        if try!(hash_powers_of_tau(&mut file)) != *hash {
+            return Err(PowersOfTauError::HashMismatch);
+        }
+        try!(file.seek(SeekFrom::Start(0)));
+        let len = try!(file.metadata()).len();
+
+        Stage1Contents::read_powers_of_tau(&mut file, len, cs.d + 1)
+    }
+
+    /// Reads the first `size` powers of tau from an accumulator of length
+    /// `len` and checks that they are consistent.
+    pub fn read_powers_of_tau<R: Read + Seek>(
+        reader: &mut R,
+        len: u64,
+        size: usize
+    ) -> Result<Self, PowersOfTauError> {
+        let n = try!(accumulator_size(len));
+        if (size as u64) > n {
+            return Err(PowersOfTauError::TooSmall(n as usize, size));
+        }
+
+        try!(reader.seek(SeekFrom::Start(HASH_SIZE)));
+        let mut v1 = Vec::with_capacity(size);
+        for i in 0..size {
+            v1.push(try!(read_g1(reader, i)));
+        }
+
+        try!(reader.seek(SeekFrom::Start(HASH_SIZE + (2 * n - 1) * G1_SIZE)));
+        let mut v2 = Vec::with_capacity(size);
+        for i in 0..size {
+            v2.push(try!(read_g2(reader, i)));
+        }
+
+        let stage = Stage1Contents {
+            v1: v1,
+            v2: v2
+        };
+
+        if stage.is_consistent() {
+            Ok(stage)
+        } else {
+            Err(PowersOfTauError::Inconsistent)
+        }
+    }
+
+    /// Checks that both vectors start at the generators and contain the
+    /// successive powers of the same tau.
+    fn is_consistent(&self) -> bool {
+        self.v1.len() == self.v2.len() &&
+        self.v1.len() >= 2 &&
+        self.v1[0] == G1::one() &&
+        self.v2[0] == G2::one() &&
+        checkseq(&self.v1, &Spair::new(self.v2[0], self.v2[1]).unwrap()) &&
+        checkseq(&self.v2, &Spair::new(self.v1[0], self.v1[1]).unwrap())
+    }
+}
+
+#[test]
+fn import_powers_of_tau() {
+    use super::affine::{g1_to_be_bytes, g2_to_be_bytes};
+    use std::io::Cursor;
+
+    fn write_g1(buf: &mut Vec<u8>, p: &G1) {
+        let (x, y) = g1_to_be_bytes(p).unwrap();
+        buf.extend_from_slice(&x);
+        buf.extend_from_slice(&y);
+    }
+
+    fn write_g2(buf: &mut Vec<u8>, p: &G2) {
+        let (x, y) = g2_to_be_bytes(p).unwrap();
+        buf.extend_from_slice(&x[1]);
+        buf.extend_from_slice(&x[0]);
+        buf.extend_from_slice(&y[1]);
+        buf.extend_from_slice(&y[0]);
+    }
+
+    let rng = &mut ::rand::thread_rng();
+    let tau = Fr::random(rng);
+    let alpha = Fr::random(rng);
+    let beta = Fr::random(rng);
+    let n = 8;
+
+    let mut powers = vec![Fr::one()];
+    for i in 1..(2 * n - 1) {
+        let next = powers[i - 1] * tau;
+        powers.push(next);
+    }
+
+    let mut challenge = vec![0; HASH_SIZE as usize];
+    for p in powers.iter() {
+        write_g1(&mut challenge, &(G1::one() * *p));
+    }
+    for p in powers.iter().take(n) {
+        write_g2(&mut challenge, &(G2::one() * *p));
+    }
+    for p in powers.iter().take(n) {
+        write_g1(&mut challenge, &(G1::one() * (*p * alpha)));
+    }
+    for p in powers.iter().take(n) {
+        write_g1(&mut challenge, &(G1::one() * (*p * beta)));
+    }
+    write_g2(&mut challenge, &(G2::one() * beta));
+    let len = challenge.len() as u64;
+
+    let stage = Stage1Contents::read_powers_of_tau(&mut Cursor::new(&challenge), len, 5).unwrap();
+    assert_eq!(stage.v1.len(), 5);
+    for i in 0..5 {
+        assert!(stage.v1[i] == G1::one() * powers[i]);
+        assert!(stage.v2[i] == G2::one() * powers[i]);
+    }
+
+    // The whole accumulator can be used, but not more.
+    assert!(Stage1Contents::read_powers_of_tau(&mut Cursor::new(&challenge), len, n).is_ok());
+    match Stage1Contents::read_powers_of_tau(&mut Cursor::new(&challenge), len, n + 1) {
+        Err(PowersOfTauError::TooSmall(8, 9)) => {},
+        _ => panic!("expected accumulator to be too small")
+    }
+
+    // Truncated files are rejected.
+    match Stage1Contents::read_powers_of_tau(&mut Cursor::new(&challenge), len - 1, 5) {
+        Err(PowersOfTauError::InvalidLength(_)) => {},
+        _ => panic!("expected invalid length")
+    }
+
+    // A compressed response of the same size: the hash, 2n - 1 + 2n points
+    // in G1, n + 1 in G2 and the public key.
+    {
+        let compressed_len = HASH_SIZE + (4 * n as u64 - 1) * G1_COMPRESSED_SIZE + (n as u64 + 1) * G2_COMPRESSED_SIZE + PUBLIC_KEY_SIZE;
+        let response = vec![0; compressed_len as usize];
+        match Stage1Contents::read_powers_of_tau(&mut Cursor::new(&response), compressed_len, 5) {
+            Err(PowersOfTauError::Compressed) => {},
+            _ => panic!("expected compressed response to be rejected")
+        }
+    }
+
+    // The hash covers the whole file.
+    {
+        let hash = hash_powers_of_tau(&mut Cursor::new(&challenge)).unwrap();
+        assert_eq!(&hash[..], Keccak256::digest(&challenge).as_slice());
+        let mut changed = challenge.clone();
+        changed[0] ^= 1;
+        assert!(hash_powers_of_tau(&mut Cursor::new(&changed)).unwrap() != hash);
+    }
+
+    // A power of tau in G1 that does not fit the sequence.
+    {
+        let mut broken = challenge.clone();
+        let offset = (HASH_SIZE + 3 * G1_SIZE) as usize;
+        let (x, y) = g1_to_be_bytes(&G1::random(rng)).unwrap();
+        broken[offset..offset + 32].copy_from_slice(&x);
+        broken[offset + 32..offset + 64].copy_from_slice(&y);
+        match Stage1Contents::read_powers_of_tau(&mut Cursor::new(&broken), len, 5) {
+            Err(PowersOfTauError::Inconsistent) => {},
+            _ => panic!("expected inconsistent powers of tau")
+        }
+    }
+
+    // A point which is not on the curve.
+    {
+        let mut broken = challenge.clone();
+        let offset = (HASH_SIZE + 2 * G1_SIZE) as usize;
+        broken[offset + 63] ^= 1;
+        match Stage1Contents::read_powers_of_tau(&mut Cursor::new(&broken), len, 5) {
+            Err(PowersOfTauError::InvalidPoint("G1", 2)) => {},
+            _ => panic!("expected invalid point")
+        }
+    }
+
+    // A point in G2 at infinity.
+    {
+        let mut broken = challenge.clone();
+        let offset = (HASH_SIZE + (2 * n as u64 - 1) * G1_SIZE + G2_SIZE) as usize;
+        for b in broken[offset..offset + G2_SIZE as usize].iter_mut() {
+            *b = 0;
+        }
+        broken[offset] = FLAG_INFINITY;
+        match Stage1Contents::read_powers_of_tau(&mut Cursor::new(&broken), len, 5) {
+            Err(PowersOfTauError::InvalidPoint("G2", 1)) => {},
+            _ => panic!("expected invalid point")
+        }
+    }
+}
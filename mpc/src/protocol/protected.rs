@@ -0,0 +1,198 @@
+//! Memory for secrets. A `Protected` value lives in pages of its own, which
+//! are locked into memory with `mlock` where supported so that they are not
+//! swapped to disk, kept out of core dumps, and overwritten with zeros when
+//! the value is dropped.
+//!
+//! Locks are not counted by the kernel: unlocking a page unlocks it for
+//! every value on it. Since no two values share a page, dropping one never
+//! unlocks another.
+//!
+//! This is best effort: copies the compiler makes on the stack before a
+//! value is moved into its `Protected` are not wiped.
+
+use super::libc;
+use std::mem;
+use std::ops::{Deref, DerefMut};
+use std::ptr;
+use std::sync::atomic::{compiler_fence, Ordering};
+
+/// Values that can be kept in protected memory. They have no destructor,
+/// which could observe the zeroized memory. Types that are not `Copy`, so
+/// that they are not copied out of their protected memory by accident,
+/// implement it explicitly.
+pub unsafe trait Plain {}
+
+unsafe impl<T: Copy> Plain for T {}
+
+/// A secret value in locked memory that is zeroized on drop.
+pub struct Protected<T: Plain> {
+    ptr: *mut T,
+    /// The length of the pages of the value.
+    len: usize,
+    locked: bool
+}
+
+unsafe impl<T: Plain + Send> Send for Protected<T> {}
+unsafe impl<T: Plain + Sync> Sync for Protected<T> {}
+
+fn page_size() -> usize {
+    unsafe { libc::sysconf(libc::_SC_PAGESIZE) as usize }
+}
+
+#[cfg(target_os = "linux")]
+fn exclude_from_core_dumps(ptr: *mut libc::c_void, len: usize) {
+    unsafe { libc::madvise(ptr, len, libc::MADV_DONTDUMP) };
+}
+
+#[cfg(not(target_os = "linux"))]
+fn exclude_from_core_dumps(_: *mut libc::c_void, _: usize) {}
+
+impl<T: Plain> Protected<T> {
+    pub fn new(value: T) -> Protected<T> {
+        let page = page_size();
+        let len = ((mem::size_of::<T>() + page - 1) / page).max(1) * page;
+        let ptr = unsafe {
+            libc::mmap(ptr::null_mut(), len, libc::PROT_READ | libc::PROT_WRITE, libc::MAP_PRIVATE | libc::MAP_ANON, -1, 0)
+        };
+        if ptr == libc::MAP_FAILED {
+            panic!("\u{274c} Error allocating memory for secrets!");
+        }
+        exclude_from_core_dumps(ptr, len);
+        // The pages are locked before the value is written to them.
+        let locked = unsafe { libc::mlock(ptr, len) == 0 };
+        unsafe { ptr::write(ptr as *mut T, value) };
+
+        Protected {
+            ptr: ptr as *mut T,
+            len: len,
+            locked: locked
+        }
+    }
+
+    /// Whether the memory is locked, which fails e.g. if the limit of
+    /// locked memory of the process is reached.
+    pub fn is_locked(&self) -> bool {
+        self.locked
+    }
+
+    /// Overwrites the value with zeros.
+    fn zeroize(&mut self) {
+        zeroize_bytes(self.ptr as *mut u8, self.len);
+    }
+}
+
+impl<T: Plain> Deref for Protected<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.ptr }
+    }
+}
+
+impl<T: Plain> DerefMut for Protected<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.ptr }
+    }
+}
+
+/// Overwrites the bytes with zeros.
+fn zeroize_bytes(ptr: *mut u8, len: usize) {
+    for i in 0..len {
+        // Volatile writes are not optimized away even though the memory
+        // is freed right after.
+        unsafe { ptr::write_volatile(ptr.offset(i as isize), 0) };
+    }
+    compiler_fence(Ordering::SeqCst);
+}
+
+/// Overwrites the buffer with zeros and empties it, for secrets that are
+/// only available in a `Vec`, e.g. decoded from hex.
+pub fn zeroize_vec(bytes: &mut Vec<u8>) {
+    let capacity = bytes.capacity();
+    zeroize_bytes(bytes.as_mut_ptr(), capacity);
+    unsafe { bytes.set_len(0) };
+}
+
+/// Overwrites the string with zeros and empties it, for secrets that are
+/// read as text, e.g. passwords.
+pub fn zeroize_string(s: &mut String) {
+    zeroize_vec(unsafe { s.as_mut_vec() });
+}
+
+impl<T: Plain> Drop for Protected<T> {
+    fn drop(&mut self) {
+        self.zeroize();
+
+        unsafe {
+            if self.locked {
+                libc::munlock(self.ptr as *const libc::c_void, self.len);
+            }
+            libc::munmap(self.ptr as *mut libc::c_void, self.len);
+        }
+    }
+}
+
+#[test]
+fn zeroize() {
+    let mut secret = Protected::new([0x42u8; 32]);
+    assert_eq!(*secret, [0x42; 32]);
+    secret[0] = 1;
+    assert_eq!(secret[0], 1);
+
+    secret.zeroize();
+    assert_eq!(*secret, [0; 32]);
+}
+
+#[test]
+fn zeroized_string() {
+    let mut password = String::with_capacity(16);
+    password.push_str("secret");
+    let ptr = password.as_ptr();
+    zeroize_string(&mut password);
+    assert!(password.is_empty());
+    assert_eq!(unsafe { ::std::slice::from_raw_parts(ptr, 6) }, &[0u8; 6]);
+}
+
+/// The kB of the mapping at the address that are locked, from
+/// `/proc/self/smaps`.
+#[cfg(all(test, target_os = "linux"))]
+fn locked_kb(address: usize) -> Option<u64> {
+    use std::fs::File;
+    use std::io::Read;
+
+    let mut smaps = String::new();
+    File::open("/proc/self/smaps").unwrap().read_to_string(&mut smaps).unwrap();
+    let mut in_mapping = false;
+    for line in smaps.lines() {
+        let first = line.split_whitespace().next().unwrap_or("");
+        if let Some(dash) = first.find('-') {
+            if let (Ok(start), Ok(end)) = (usize::from_str_radix(&first[..dash], 16), usize::from_str_radix(&first[dash + 1..], 16)) {
+                in_mapping = start <= address && address < end;
+                continue;
+            }
+        }
+        if in_mapping && line.starts_with("Locked:") {
+            return line.split_whitespace().nth(1).and_then(|kb| kb.parse().ok());
+        }
+    }
+    None
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn drop_keeps_other_secrets_locked() {
+    let first = Protected::new([0x42u8; 32]);
+    let second = Protected::new([0x43u8; 32]);
+    if !first.is_locked() || !second.is_locked() {
+        // The limit of locked memory is too low to test the locks.
+        return;
+    }
+    assert!(first.len >= page_size() && first.ptr as usize % page_size() == 0);
+    let address = second.ptr as usize;
+    assert!(locked_kb(address).unwrap() > 0);
+
+    // Dropping the first secret unlocks and unmaps its own pages only.
+    drop(first);
+    assert!(locked_kb(address).unwrap() > 0);
+    assert_eq!(*second, [0x43; 32]);
+}
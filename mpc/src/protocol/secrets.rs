@@ -1,14 +1,15 @@
-use super::sha3::{Digest, Keccak256};
 use bn::*;
 use rand::Rng;
 use super::spair::{Spair, same_power};
-use super::nizk::Nizk;
-use super::digest::{Digest512};
-use bincode::SizeLimit::Infinite;
-use bincode::rustc_serialize::encode;
+use super::nizk::{Nizk, NizkTranscript};
+use super::digest::Digest256;
+use super::protected::{Plain, Protected};
+use super::affine::{fr_from_be_bytes, fr_write_be};
+use super::constant_time::mul_normalized;
 #[cfg(feature = "snark")]
 use snark::*;
 use rustc_serialize::{Encodable, Encoder, Decodable, Decoder};
+use std::ops::{Deref, DerefMut};
 
 #[derive(Clone, PartialEq, Eq)]
 pub struct PublicKey(PublicKeyInner);
@@ -46,15 +47,15 @@ pub struct PublicKeyNizks {
 }
 
 impl PublicKeyNizks {
-    pub fn is_valid(&self, pubkey: &PublicKey, extra: &Digest512) -> bool {
-        pubkey.tau_g2().verify_nizk(&self.tau, extra) &&
-        pubkey.alpha_a_g1().verify_nizk(&self.alpha_a, extra) &&
-        pubkey.alpha_b_g2().verify_nizk(&self.alpha_b, extra) &&
-        pubkey.alpha_c_g1().verify_nizk(&self.alpha_c, extra) &&
-        pubkey.rho_a_g2().verify_nizk(&self.rho_a, extra) &&
-        pubkey.rho_b_g1().verify_nizk(&self.rho_b, extra) &&
-        pubkey.beta_g2().verify_nizk(&self.beta, extra) &&
-        pubkey.gamma_g1().verify_nizk(&self.gamma, extra)
+    pub fn is_valid(&self, pubkey: &PublicKey, transcript: &NizkTranscript) -> bool {
+        pubkey.tau_g2().verify_nizk(&self.tau, transcript) &&
+        pubkey.alpha_a_g1().verify_nizk(&self.alpha_a, transcript) &&
+        pubkey.alpha_b_g2().verify_nizk(&self.alpha_b, transcript) &&
+        pubkey.alpha_c_g1().verify_nizk(&self.alpha_c, transcript) &&
+        pubkey.rho_a_g2().verify_nizk(&self.rho_a, transcript) &&
+        pubkey.rho_b_g1().verify_nizk(&self.rho_b, transcript) &&
+        pubkey.beta_g2().verify_nizk(&self.beta, transcript) &&
+        pubkey.gamma_g1().verify_nizk(&self.gamma, transcript)
     }
 }
 
@@ -76,20 +77,21 @@ impl PublicKey {
         same_power(&self.0.f8_gamma, &Spair::new(self.0.f2_beta, self.0.f2_beta_gamma).unwrap())
     }
 
-    pub fn hash(&self) -> Vec<u8> {
-        Keccak256::digest(encode(self, Infinite).unwrap().as_slice()).as_slice().to_owned()
+    /// The commitment to the public key.
+    pub fn hash(&self) -> Digest256 {
+        Digest256::from(self).expect("Public key should be serializable!")
     }
 
-    pub fn nizks<R: Rng>(&self, rng: &mut R, privkey: &PrivateKey, extra: &Digest512) -> PublicKeyNizks {
+    pub fn nizks<R: Rng>(&self, rng: &mut R, privkey: &PrivateKey, transcript: &NizkTranscript) -> PublicKeyNizks {
         PublicKeyNizks {
-            tau: self.tau_g2().nizk(rng, privkey.tau, extra),
-            alpha_a: self.alpha_a_g1().nizk(rng, privkey.alpha_a, extra),
-            alpha_b: self.alpha_b_g2().nizk(rng, privkey.alpha_b, extra),
-            alpha_c: self.alpha_c_g1().nizk(rng, privkey.alpha_c, extra),
-            rho_a: self.rho_a_g2().nizk(rng, privkey.rho_a, extra),
-            rho_b: self.rho_b_g1().nizk(rng, privkey.rho_b, extra),
-            beta: self.beta_g2().nizk(rng, privkey.beta, extra),
-            gamma: self.gamma_g1().nizk(rng, privkey.gamma, extra)
+            tau: self.tau_g2().nizk(rng, &privkey.tau, transcript),
+            alpha_a: self.alpha_a_g1().nizk(rng, &privkey.alpha_a, transcript),
+            alpha_b: self.alpha_b_g2().nizk(rng, &privkey.alpha_b, transcript),
+            alpha_c: self.alpha_c_g1().nizk(rng, &privkey.alpha_c, transcript),
+            rho_a: self.rho_a_g2().nizk(rng, &privkey.rho_a, transcript),
+            rho_b: self.rho_b_g1().nizk(rng, &privkey.rho_b, transcript),
+            beta: self.beta_g2().nizk(rng, &privkey.beta, transcript),
+            gamma: self.gamma_g1().nizk(rng, &privkey.gamma, transcript)
         }
     }
 
@@ -174,9 +176,9 @@ impl Decodable for PublicKey {
     }
 }
 
-/// The secrets sampled by the player.
-#[derive(Clone, Copy)]
-pub struct PrivateKey {
+/// The secret scalars of a player. They are neither `Copy` nor `Clone`, so
+/// they are only ever borrowed from their protected memory.
+pub struct Scalars {
     pub tau: Fr,
     pub rho_a: Fr,
     pub rho_b: Fr,
@@ -187,11 +189,36 @@ pub struct PrivateKey {
     pub gamma: Fr
 }
 
+unsafe impl Plain for Scalars {}
+
+/// The number of bytes of the secrets of a player, as written by
+/// `PrivateKey::to_bytes`.
+pub const PRIVATE_KEY_BYTES: usize = 8 * 32;
+
+/// The secrets sampled by the player. They are kept in protected memory,
+/// which is locked and zeroized when the key is dropped, and cannot be
+/// copied.
+pub struct PrivateKey(Protected<Scalars>);
+
+impl Deref for PrivateKey {
+    type Target = Scalars;
+
+    fn deref(&self) -> &Scalars {
+        &self.0
+    }
+}
+
+impl DerefMut for PrivateKey {
+    fn deref_mut(&mut self) -> &mut Scalars {
+        &mut self.0
+    }
+}
+
 impl PrivateKey {
     /// Construct the player's secrets given a random number
     /// generator.
     pub fn new<R: Rng>(rng: &mut R) -> PrivateKey {
-        PrivateKey {
+        PrivateKey(Protected::new(Scalars {
             tau: Fr::random(rng),
             rho_a: Fr::random(rng),
             rho_b: Fr::random(rng),
@@ -200,14 +227,14 @@ impl PrivateKey {
             alpha_c: Fr::random(rng),
             beta: Fr::random(rng),
             gamma: Fr::random(rng)
-        }
+        }))
     }
 
     /// Construct a "blank" private key for accumulating
     /// in tests.
     #[cfg(feature = "snark")]
     pub fn new_blank() -> PrivateKey {
-        PrivateKey {
+        PrivateKey(Protected::new(Scalars {
             tau: Fr::one(),
             rho_a: Fr::one(),
             rho_b: Fr::one(),
@@ -216,7 +243,55 @@ impl PrivateKey {
             alpha_c: Fr::one(),
             beta: Fr::one(),
             gamma: Fr::one()
+        }))
+    }
+
+    /// Whether the secrets are locked into memory.
+    pub fn is_locked(&self) -> bool {
+        self.0.is_locked()
+    }
+
+    /// The secrets as the canonical big-endian representations of the
+    /// scalars, e.g. to store them encrypted. The bytes are protected like
+    /// the secrets.
+    pub fn to_bytes(&self) -> Protected<[u8; PRIVATE_KEY_BYTES]> {
+        let s = &self.0;
+        let mut bytes = Protected::new([0u8; PRIVATE_KEY_BYTES]);
+        for (scalar, chunk) in [&s.tau, &s.rho_a, &s.rho_b, &s.alpha_a, &s.alpha_b, &s.alpha_c, &s.beta, &s.gamma].iter().zip(bytes.chunks_mut(32)) {
+            fr_write_be(scalar, chunk);
         }
+        bytes
+    }
+
+    /// The secrets written by `to_bytes`, or `None` if a scalar is not
+    /// canonical.
+    pub fn from_bytes(bytes: &[u8; PRIVATE_KEY_BYTES]) -> Option<PrivateKey> {
+        let mut secrets = Protected::new(Scalars {
+            tau: Fr::zero(),
+            rho_a: Fr::zero(),
+            rho_b: Fr::zero(),
+            alpha_a: Fr::zero(),
+            alpha_b: Fr::zero(),
+            alpha_c: Fr::zero(),
+            beta: Fr::zero(),
+            gamma: Fr::zero()
+        });
+        {
+            let s = &mut *secrets;
+            let mut scalars = [&mut s.tau, &mut s.rho_a, &mut s.rho_b, &mut s.alpha_a, &mut s.alpha_b, &mut s.alpha_c, &mut s.beta, &mut s.gamma];
+            for (scalar, chunk) in scalars.iter_mut().zip(bytes.chunks(32)) {
+                match fr_from_be_bytes(chunk) {
+                    Some(value) => **scalar = value,
+                    None => return None
+                }
+            }
+        }
+        Some(PrivateKey(secrets))
+    }
+
+    /// Destroys the secrets, which overwrites them with zeros.
+    pub fn destroy(self) {
+        drop(self);
     }
 
     #[cfg(feature = "snark")]
@@ -250,21 +325,21 @@ impl PrivateKey {
     /// is performing their transformations correctly.
     pub fn pubkey<R: Rng>(&self, rng: &mut R) -> PublicKey {
         let f1 = G2::random(rng);
-        let f1_rho_a = f1 * self.rho_a;
-        let f1_rho_a_alpha_a = f1_rho_a * self.alpha_a;
-        let f1_rho_a_rho_b = f1_rho_a * self.rho_b;
-        let f1_rho_a_rho_b_alpha_c = f1_rho_a_rho_b * self.alpha_c;
-        let f1_rho_a_rho_b_alpha_b = f1_rho_a_rho_b * self.alpha_b;
+        let f1_rho_a = mul_normalized(f1, &self.rho_a);
+        let f1_rho_a_alpha_a = mul_normalized(f1_rho_a, &self.alpha_a);
+        let f1_rho_a_rho_b = mul_normalized(f1_rho_a, &self.rho_b);
+        let f1_rho_a_rho_b_alpha_c = mul_normalized(f1_rho_a_rho_b, &self.alpha_c);
+        let f1_rho_a_rho_b_alpha_b = mul_normalized(f1_rho_a_rho_b, &self.alpha_b);
         let f2 = G2::random(rng);
-        let f2_beta = f2 * self.beta;
-        let f2_beta_gamma = f2_beta * self.gamma;
+        let f2_beta = mul_normalized(f2, &self.beta);
+        let f2_beta_gamma = mul_normalized(f2_beta, &self.gamma);
 
-        let f3_tau = Spair::random(rng, self.tau).unwrap();
-        let f4_alpha_a = Spair::random(rng, self.alpha_a).unwrap();
-        let f5_alpha_c = Spair::random(rng, self.alpha_c).unwrap();
-        let f6_rho_b = Spair::random(rng, self.rho_b).unwrap();
-        let f7_rho_a_rho_b = Spair::random(rng, self.rho_a * self.rho_b).unwrap();
-        let f8_gamma = Spair::random(rng, self.gamma).unwrap();
+        let f3_tau = Spair::random(rng, &self.tau).unwrap();
+        let f4_alpha_a = Spair::random(rng, &self.alpha_a).unwrap();
+        let f5_alpha_c = Spair::random(rng, &self.alpha_c).unwrap();
+        let f6_rho_b = Spair::random(rng, &self.rho_b).unwrap();
+        let f7_rho_a_rho_b = Spair::random(rng, &Protected::new(self.rho_a * self.rho_b)).unwrap();
+        let f8_gamma = Spair::random(rng, &self.gamma).unwrap();
 
         let tmp = PublicKey(PublicKeyInner {
             f1: f1,
@@ -293,18 +368,35 @@ impl PrivateKey {
 
 #[test]
 fn pubkey_nizks() {
+    use super::nizk::test_transcript;
+
     let rng = &mut ::rand::thread_rng();
 
     let privkey = PrivateKey::new(rng);
     let pubkey = privkey.pubkey(rng);
 
-    let extra = Digest512::from(&"test").unwrap();
-    let extra_wrong = Digest512::from(&"testt").unwrap();
+    let transcript = test_transcript(0, "test");
+    let transcript_wrong = test_transcript(1, "test");
+
+    let nizks = pubkey.nizks(rng, &privkey, &transcript);
 
-    let nizks = pubkey.nizks(rng, &privkey, &extra);
+    assert!(nizks.is_valid(&pubkey, &transcript));
+    assert!(!nizks.is_valid(&pubkey, &transcript_wrong));
+}
 
-    assert!(nizks.is_valid(&pubkey, &extra));
-    assert!(!nizks.is_valid(&pubkey, &extra_wrong));
+#[test]
+fn privkey_bytes() {
+    let rng = &mut ::rand::thread_rng();
+
+    let privkey = PrivateKey::new(rng);
+    let bytes = privkey.to_bytes();
+    let decoded = PrivateKey::from_bytes(&bytes).unwrap();
+    assert!(decoded.tau == privkey.tau && decoded.rho_b == privkey.rho_b && decoded.gamma == privkey.gamma);
+    assert_eq!(&decoded.to_bytes()[..], &bytes[..]);
+
+    let mut invalid = *bytes;
+    invalid[32..64].copy_from_slice(&[0xff; 32]);
+    assert!(PrivateKey::from_bytes(&invalid).is_none());
 }
 
 #[test]
@@ -1,8 +1,8 @@
 use rand::Rng;
 use bn::*;
 use super::multicore::*;
-use super::digest::Digest512;
-use super::nizk::Nizk;
+use super::constant_time::mul_normalized;
+use super::nizk::{Nizk, NizkTranscript};
 use rustc_serialize::{Encodable, Encoder, Decodable, Decoder};
 
 #[derive(Clone, PartialEq, Eq)]
@@ -41,18 +41,18 @@ impl<G: Group> Spair<G> {
         }
     }
 
-    pub fn random<R: Rng>(rng: &mut R, s: Fr) -> Option<Self> {
+    pub fn random<R: Rng>(rng: &mut R, s: &Fr) -> Option<Self> {
         let f = G::random(rng);
 
-        Spair::new(f, f * s)
+        Spair::new(f, mul_normalized(f, s))
     }
 
-    pub fn nizk<R: Rng>(&self, rng: &mut R, s: Fr, extra: &Digest512) -> Nizk<G> {
-        Nizk::new(rng, self.f, s, extra)
+    pub fn nizk<R: Rng>(&self, rng: &mut R, s: &Fr, transcript: &NizkTranscript) -> Nizk<G> {
+        Nizk::new(rng, self.f, s, transcript)
     }
 
-    pub fn verify_nizk(&self, proof: &Nizk<G>, extra: &Digest512) -> bool {
-        proof.verify(self.f, self.fs, extra)
+    pub fn verify_nizk(&self, proof: &Nizk<G>, transcript: &NizkTranscript) -> bool {
+        proof.verify(self.f, self.fs, transcript)
     }
 }
 
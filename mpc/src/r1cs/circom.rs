@@ -0,0 +1,255 @@
+//! Import of the binary `.r1cs` format of circom (version 1), which is
+//! little-endian throughout:
+//!
+//! * the magic `r1cs`, the version and the number of sections (u32 each)
+//! * each section as its type (u32), size (u64) and contents
+//!
+//! The header section (type 1) contains the size of field elements in
+//! bytes (u32), the prime, the number of wires and the numbers of public
+//! outputs, public inputs and private inputs (u32 each), the number of
+//! labels (u64) and the number of constraints (u32). The constraint section
+//! (type 2) contains the linear combinations A, B and C of each constraint
+//! as the number of terms (u32) followed by the wire (u32) and coefficient
+//! of each term. Other sections are ignored.
+//!
+//! Wire 0 is the constant one, followed by the public outputs and inputs,
+//! which become the primary inputs.
+
+use bn::*;
+use byteorder::{LittleEndian, ReadBytesExt};
+use std::io::{Cursor, Read};
+use protocol::affine::fr_from_be_bytes;
+use super::{R1cs, R1csError, Constraint, LinearCombination, MODULUS};
+
+const MAGIC: &[u8] = b"r1cs";
+const VERSION: u32 = 1;
+const FIELD_SIZE: u32 = 32;
+const HEADER: u32 = 1;
+const CONSTRAINTS: u32 = 2;
+
+struct Header {
+    num_wires: usize,
+    num_public: usize,
+    num_constraints: usize
+}
+
+fn section<'a>(buf: &'a [u8], start: u64, size: u64) -> Result<&'a [u8], R1csError> {
+    let end = try!(start.checked_add(size).ok_or(R1csError::InvalidFormat("section size overflows")));
+    if end > buf.len() as u64 {
+        return Err(R1csError::InvalidFormat("section exceeds the file"));
+    }
+
+    Ok(&buf[start as usize..end as usize])
+}
+
+fn read_header(buf: &[u8]) -> Result<Header, R1csError> {
+    let mut cur = Cursor::new(buf);
+
+    if try!(cur.read_u32::<LittleEndian>()) != FIELD_SIZE {
+        return Err(R1csError::WrongField);
+    }
+    let mut prime = [0u8; 32];
+    try!(cur.read_exact(&mut prime));
+    prime.reverse();
+    if prime != MODULUS {
+        return Err(R1csError::WrongField);
+    }
+
+    let num_wires = try!(cur.read_u32::<LittleEndian>()) as usize;
+    let num_outputs = try!(cur.read_u32::<LittleEndian>()) as usize;
+    let num_public_inputs = try!(cur.read_u32::<LittleEndian>()) as usize;
+    let num_private_inputs = try!(cur.read_u32::<LittleEndian>()) as usize;
+    let _num_labels = try!(cur.read_u64::<LittleEndian>());
+    let num_constraints = try!(cur.read_u32::<LittleEndian>()) as usize;
+
+    if cur.position() != buf.len() as u64 {
+        return Err(R1csError::InvalidFormat("header has trailing bytes"));
+    }
+
+    let num_public = num_outputs + num_public_inputs;
+    if num_wires == 0 || 1 + num_public + num_private_inputs > num_wires {
+        return Err(R1csError::InvalidInputs(num_public + num_private_inputs, num_wires));
+    }
+
+    Ok(Header {
+        num_wires: num_wires,
+        num_public: num_public,
+        num_constraints: num_constraints
+    })
+}
+
+fn read_lc(cur: &mut Cursor<&[u8]>, header: &Header, constraint: usize) -> Result<LinearCombination, R1csError> {
+    let num_terms = try!(cur.read_u32::<LittleEndian>());
+    let mut lc = vec![];
+    for _ in 0..num_terms {
+        let wire = try!(cur.read_u32::<LittleEndian>()) as usize;
+        if wire >= header.num_wires {
+            return Err(R1csError::InvalidVariable(constraint, wire));
+        }
+
+        let mut coeff = [0u8; 32];
+        try!(cur.read_exact(&mut coeff));
+        coeff.reverse();
+        let coeff = try!(fr_from_be_bytes(&coeff).ok_or(R1csError::InvalidCoefficient(constraint)));
+
+        lc.push((wire, coeff));
+    }
+
+    Ok(lc)
+}
+
+fn read_constraints(buf: &[u8], header: &Header) -> Result<Vec<Constraint>, R1csError> {
+    let mut cur = Cursor::new(buf);
+    let mut constraints = Vec::with_capacity(header.num_constraints);
+
+    for i in 0..header.num_constraints {
+        let a = try!(read_lc(&mut cur, header, i));
+        let b = try!(read_lc(&mut cur, header, i));
+        let c = try!(read_lc(&mut cur, header, i));
+        constraints.push(Constraint { a: a, b: b, c: c });
+    }
+
+    if cur.position() != buf.len() as u64 {
+        return Err(R1csError::InvalidFormat("constraint section has trailing bytes"));
+    }
+
+    Ok(constraints)
+}
+
+/// Reads a constraint system in circom's binary `.r1cs` format.
+pub fn read_circom<R: Read>(mut reader: R) -> Result<R1cs, R1csError> {
+    let mut buf = vec![];
+    try!(reader.read_to_end(&mut buf));
+    let mut cur = Cursor::new(&buf[..]);
+
+    let mut magic = [0u8; 4];
+    try!(cur.read_exact(&mut magic));
+    if &magic[..] != MAGIC {
+        return Err(R1csError::InvalidFormat("missing magic"));
+    }
+    let version = try!(cur.read_u32::<LittleEndian>());
+    if version != VERSION {
+        return Err(R1csError::UnsupportedVersion(version));
+    }
+
+    let num_sections = try!(cur.read_u32::<LittleEndian>());
+    let mut header = None;
+    let mut constraints = None;
+    for _ in 0..num_sections {
+        let section_type = try!(cur.read_u32::<LittleEndian>());
+        let size = try!(cur.read_u64::<LittleEndian>());
+        let contents = try!(section(&buf, cur.position(), size));
+
+        match section_type {
+            HEADER if header.is_none() => header = Some(contents),
+            CONSTRAINTS if constraints.is_none() => constraints = Some(contents),
+            HEADER | CONSTRAINTS => return Err(R1csError::InvalidFormat("duplicate section")),
+            _ => {}
+        }
+
+        let next = cur.position() + size;
+        cur.set_position(next);
+    }
+
+    let header = try!(read_header(try!(header.ok_or(R1csError::InvalidFormat("missing header")))));
+    let constraints = try!(read_constraints(try!(constraints.ok_or(R1csError::InvalidFormat("missing constraints"))), &header));
+
+    let r1cs = R1cs {
+        num_inputs: header.num_public,
+        num_aux: header.num_wires - 1 - header.num_public,
+        constraints: constraints
+    };
+    try!(r1cs.validate());
+
+    Ok(r1cs)
+}
+
+/// Writes the constraint system in circom's format, which is the inverse
+/// of `read_circom` for tests.
+#[cfg(test)]
+fn write_circom(r1cs: &R1cs, prime: &[u8; 32]) -> Vec<u8> {
+    use byteorder::WriteBytesExt;
+    use std::io::Write;
+    use protocol::affine::fr_to_be_bytes;
+
+    let mut header = vec![];
+    header.write_u32::<LittleEndian>(FIELD_SIZE).unwrap();
+    let mut prime = *prime;
+    prime.reverse();
+    header.write_all(&prime).unwrap();
+    header.write_u32::<LittleEndian>((r1cs.num_vars() + 1) as u32).unwrap();
+    header.write_u32::<LittleEndian>(0).unwrap();
+    header.write_u32::<LittleEndian>(r1cs.num_inputs as u32).unwrap();
+    header.write_u32::<LittleEndian>(r1cs.num_aux as u32).unwrap();
+    header.write_u64::<LittleEndian>((r1cs.num_vars() + 1) as u64).unwrap();
+    header.write_u32::<LittleEndian>(r1cs.constraints.len() as u32).unwrap();
+
+    let mut constraints = vec![];
+    for constraint in &r1cs.constraints {
+        for lc in &[&constraint.a, &constraint.b, &constraint.c] {
+            constraints.write_u32::<LittleEndian>(lc.len() as u32).unwrap();
+            for &(wire, coeff) in lc.iter() {
+                constraints.write_u32::<LittleEndian>(wire as u32).unwrap();
+                let mut coeff = fr_to_be_bytes(&coeff);
+                coeff.reverse();
+                constraints.write_all(&coeff).unwrap();
+            }
+        }
+    }
+
+    // A wire to label section, which is ignored
+    let labels = vec![0u8; 8 * (r1cs.num_vars() + 1)];
+
+    let mut buf = vec![];
+    buf.write_all(MAGIC).unwrap();
+    buf.write_u32::<LittleEndian>(VERSION).unwrap();
+    buf.write_u32::<LittleEndian>(3).unwrap();
+    for &(section_type, ref contents) in &[(HEADER, header), (3, labels), (CONSTRAINTS, constraints)] {
+        buf.write_u32::<LittleEndian>(section_type).unwrap();
+        buf.write_u64::<LittleEndian>(contents.len() as u64).unwrap();
+        buf.write_all(contents).unwrap();
+    }
+
+    buf
+}
+
+#[test]
+fn import_circom() {
+    let r1cs = super::multiplication();
+    let buf = write_circom(&r1cs, &MODULUS);
+
+    let read = read_circom(&buf[..]).unwrap();
+    assert_eq!(read.num_inputs, 1);
+    assert_eq!(read.num_aux, 2);
+    assert_eq!(read.constraints.len(), 1);
+    assert!(read.constraints[0].a == r1cs.constraints[0].a);
+    assert!(read.constraints[0].b == r1cs.constraints[0].b);
+    assert!(read.constraints[0].c == r1cs.constraints[0].c);
+
+    // Another field
+    let mut prime = MODULUS;
+    prime[31] = 0x03;
+    match read_circom(&write_circom(&r1cs, &prime)[..]) {
+        Err(R1csError::WrongField) => {},
+        _ => panic!("expected wrong field")
+    }
+
+    // Truncated file
+    assert!(read_circom(&buf[..buf.len() - 1]).is_err());
+
+    // Unknown wire
+    let mut invalid = super::multiplication();
+    invalid.constraints[0].c = vec![(4, Fr::one())];
+    match read_circom(&write_circom(&invalid, &MODULUS)[..]) {
+        Err(R1csError::InvalidVariable(0, 4)) => {},
+        _ => panic!("expected invalid variable")
+    }
+
+    // Wrong version
+    let mut wrong_version = buf.clone();
+    wrong_version[4] = 2;
+    match read_circom(&wrong_version[..]) {
+        Err(R1csError::UnsupportedVersion(2)) => {},
+        _ => panic!("expected unsupported version")
+    }
+}
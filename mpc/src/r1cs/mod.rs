@@ -0,0 +1,179 @@
+//! Import of constraint systems produced by other tools.
+//!
+//! The protocol reads the constraint system from a file in libsnark's
+//! format (see `CS::from_file`). The importer parses circom's binary
+//! `.r1cs` format into an `R1cs`, which is written in that format. ZoKrates
+//! writes its constraints in the same format with `zokrates compile`, with
+//! the outputs and public arguments of the program as the public wires, so
+//! they are imported with `read_circom` as well.
+//!
+//! Variable 0 is the constant one, followed by the primary (public) inputs
+//! and the auxiliary inputs, as in libsnark.
+
+use bn::*;
+use std::error;
+use std::fmt;
+use std::io;
+#[cfg(feature = "snark")]
+use snark::CS;
+
+mod circom;
+pub use self::circom::read_circom;
+
+/// The order of the groups, i.e. the modulus of the scalar field, in
+/// big-endian.
+const MODULUS: [u8; 32] = [
+    0x30, 0x64, 0x4e, 0x72, 0xe1, 0x31, 0xa0, 0x29, 0xb8, 0x50, 0x45, 0xb6, 0x81, 0x81, 0x58, 0x5d,
+    0x28, 0x33, 0xe8, 0x48, 0x79, 0xb9, 0x70, 0x91, 0x43, 0xe1, 0xf5, 0x93, 0xf0, 0x00, 0x00, 0x01
+];
+
+pub type LinearCombination = Vec<(usize, Fr)>;
+
+pub struct Constraint {
+    pub a: LinearCombination,
+    pub b: LinearCombination,
+    pub c: LinearCombination
+}
+
+pub struct R1cs {
+    pub num_inputs: usize,
+    pub num_aux: usize,
+    pub constraints: Vec<Constraint>
+}
+
+#[derive(Debug)]
+pub enum R1csError {
+    /// The file could not be read
+    Io(io::Error),
+    /// The file does not have the expected structure
+    InvalidFormat(&'static str),
+    /// The version of the file format is not supported
+    UnsupportedVersion(u32),
+    /// The constraint system is not defined over the scalar field of BN254
+    WrongField,
+    /// The number of public inputs exceeds the number of variables
+    InvalidInputs(usize, usize),
+    /// A constraint refers to a variable that does not exist
+    InvalidVariable(usize, usize),
+    /// A coefficient of a constraint is not a field element
+    InvalidCoefficient(usize),
+    /// The constraint system has no constraints
+    Empty
+}
+
+impl fmt::Display for R1csError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            R1csError::Io(ref e) => write!(f, "could not read constraint system: {}", e),
+            R1csError::InvalidFormat(reason) => write!(f, "invalid constraint system: {}", reason),
+            R1csError::UnsupportedVersion(version) => write!(f, "version {} of the format is not supported", version),
+            R1csError::WrongField => write!(f, "constraint system is not defined over the scalar field of BN254"),
+            R1csError::InvalidInputs(inputs, vars) => write!(f, "{} public inputs, but only {} variables", inputs, vars),
+            R1csError::InvalidVariable(constraint, var) => write!(f, "constraint {} refers to unknown variable {}", constraint, var),
+            R1csError::InvalidCoefficient(constraint) => write!(f, "constraint {} has a coefficient which is not a field element", constraint),
+            R1csError::Empty => write!(f, "constraint system has no constraints")
+        }
+    }
+}
+
+impl error::Error for R1csError {
+    fn description(&self) -> &str {
+        "invalid constraint system"
+    }
+}
+
+impl From<io::Error> for R1csError {
+    fn from(e: io::Error) -> R1csError {
+        R1csError::Io(e)
+    }
+}
+
+impl R1cs {
+    pub fn num_vars(&self) -> usize {
+        self.num_inputs + self.num_aux
+    }
+
+    /// Checks that the constraints only refer to existing variables.
+    pub fn validate(&self) -> Result<(), R1csError> {
+        if self.constraints.is_empty() {
+            return Err(R1csError::Empty);
+        }
+
+        for (i, constraint) in self.constraints.iter().enumerate() {
+            for lc in &[&constraint.a, &constraint.b, &constraint.c] {
+                for &(var, _) in lc.iter() {
+                    if var > self.num_vars() {
+                        return Err(R1csError::InvalidVariable(i, var));
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Checks whether the assignment of the variables (excluding the
+    /// constant one) satisfies all constraints.
+    pub fn is_satisfied(&self, assignment: &[Fr]) -> bool {
+        assert_eq!(assignment.len(), self.num_vars());
+
+        let eval = |lc: &LinearCombination| {
+            lc.iter().fold(Fr::zero(), |acc, &(var, coeff)| {
+                acc + coeff * if var == 0 { Fr::one() } else { assignment[var - 1] }
+            })
+        };
+
+        self.constraints.iter().all(|c| eval(&c.a) * eval(&c.b) == eval(&c.c))
+    }
+
+    /// Writes the constraint system in libsnark's format.
+    #[cfg(feature = "snark")]
+    pub fn write(&self, path: &str) {
+        let a: Vec<_> = self.constraints.iter().map(|c| c.a.clone()).collect();
+        let b: Vec<_> = self.constraints.iter().map(|c| c.b.clone()).collect();
+        let c: Vec<_> = self.constraints.iter().map(|c| c.c.clone()).collect();
+
+        CS::write(path, self.num_inputs, self.num_aux, &a, &b, &c);
+    }
+}
+
+/// The constraint system `x * y = z` with the public input `z` and the
+/// auxiliary inputs `x` and `y`.
+#[cfg(test)]
+pub fn multiplication() -> R1cs {
+    R1cs {
+        num_inputs: 1,
+        num_aux: 2,
+        constraints: vec![Constraint {
+            a: vec![(2, Fr::one())],
+            b: vec![(3, Fr::one())],
+            c: vec![(1, Fr::one())]
+        }]
+    }
+}
+
+#[test]
+fn write_constraint_system() {
+    use std::env;
+    use std::fs;
+
+    let r1cs = multiplication();
+    r1cs.validate().unwrap();
+
+    let five = Fr::from_str("5").unwrap();
+    let three = Fr::from_str("3").unwrap();
+    assert!(r1cs.is_satisfied(&[five * three, three, five]));
+    assert!(!r1cs.is_satisfied(&[five, three, five]));
+
+    let path = env::temp_dir().join(format!("r1cs-{}", ::rand::random::<u64>()));
+    let path = path.to_str().unwrap();
+    r1cs.write(path);
+
+    let cs = CS::from_path(path);
+    assert_eq!(cs.num_inputs, 1);
+    assert_eq!(cs.num_vars, 4);
+    assert!(cs.is_satisfied(&[five * three], &[three, five]));
+    assert!(!cs.is_satisfied(&[five], &[three, five]));
+
+    fs::remove_file(path).unwrap();
+}
@@ -0,0 +1,134 @@
+//! Receipts of a player's contributions. For every stage a player transforms
+//! it appends a receipt to a local file, which links to the previous receipt
+//! by its hash, so the receipts form a chain that cannot be reordered or
+//! truncated in the middle unnoticed.
+//!
+//! Each contribution has a short fingerprint, the base58check encoding of a
+//! hash of its signed statement. The verifier prints the fingerprints of all
+//! contributions it verified, so every player can publicly confirm that its
+//! contribution was included.
+
+use blake2_rfc::blake2b::blake2b;
+use contribution::Statement;
+use hex;
+use protocol::Digest512;
+use protocol::base58::ToBase58;
+use serde_json;
+use web3::types::H256;
+
+use std::fs::File;
+use std::path::Path;
+
+pub const RECEIPTS_FILE: &str = "receipts.json";
+
+/// The number of bytes of the hash of the statement in a fingerprint.
+const FINGERPRINT_BYTES: usize = 16;
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Receipt {
+    pub contract: String,
+    pub stage: u64,
+    pub previous_stage: String,
+    pub transformed_stage: String,
+    pub public_key: String,
+    pub stage_ipfs: String,
+    pub contribution_ipfs: String,
+    pub transaction: String,
+    pub previous_receipt: String,
+    pub fingerprint: String
+}
+
+/// The fingerprint of the contribution with the statement.
+pub fn fingerprint(statement: &Statement) -> String {
+    let digest = Digest512::from(statement).expect("Statement should be serializable!");
+    (&digest.0[..FINGERPRINT_BYTES]).to_base58check()
+}
+
+impl Receipt {
+    pub fn new(statement: &Statement, stage_ipfs: &str, contribution_ipfs: &str, transaction: H256, previous: Option<&Receipt>) -> Receipt {
+        Receipt {
+            contract: format!("0x{}", hex::encode(&statement.contract)),
+            stage: statement.stage,
+            previous_stage: hex::encode(&statement.previous.0[..]),
+            transformed_stage: hex::encode(&statement.transformed.0[..]),
+            public_key: statement.public_key.to_string(),
+            stage_ipfs: stage_ipfs.to_string(),
+            contribution_ipfs: contribution_ipfs.to_string(),
+            transaction: format!("{:?}", transaction),
+            previous_receipt: previous.map(|r| r.hash()).unwrap_or_default(),
+            fingerprint: fingerprint(statement)
+        }
+    }
+
+    /// The hash of the receipt, which the next receipt links to.
+    pub fn hash(&self) -> String {
+        let json = serde_json::to_vec(self).expect("Receipt should be serializable!");
+        hex::encode(blake2b(32, &[], &json).as_bytes())
+    }
+}
+
+/// Checks that each receipt links to the one before it.
+pub fn is_chain_valid(receipts: &[Receipt]) -> bool {
+    receipts.iter().enumerate().all(|(i, receipt)| {
+        let expected = if i == 0 { String::new() } else { receipts[i - 1].hash() };
+        receipt.previous_receipt == expected
+    })
+}
+
+pub fn read_receipts(path: &str) -> Vec<Receipt> {
+    if !Path::new(path).is_file() {
+        return vec![];
+    }
+    let file = File::open(path).unwrap_or_else(|e| panic!("\u{274c} Error opening receipts {:?}: {}", path, e));
+    let receipts: Vec<Receipt> = serde_json::from_reader(file).unwrap_or_else(|e| panic!("\u{274c} Error parsing receipts {:?}: {}", path, e));
+    if !is_chain_valid(&receipts) {
+        panic!("\u{274c} The receipts in {:?} do not form a chain!", path);
+    }
+    receipts
+}
+
+/// Appends a receipt for the contribution to the receipts in the file and
+/// returns it.
+pub fn append_receipt(path: &str, statement: &Statement, stage_ipfs: &str, contribution_ipfs: &str, transaction: H256) -> Receipt {
+    let mut receipts = read_receipts(path);
+    let receipt = Receipt::new(statement, stage_ipfs, contribution_ipfs, transaction, receipts.last());
+    receipts.push(receipt.clone());
+
+    let file = File::create(path).unwrap_or_else(|e| panic!("\u{274c} Error creating receipts {:?}: {}", path, e));
+    serde_json::to_writer_pretty(file, &receipts).expect("Error writing receipts!");
+    receipt
+}
+
+#[test]
+fn receipt_chain() {
+    use protocol::PrivateKey;
+    use protocol::base58::FromBase58;
+    use web3::types::Address;
+
+    let rng = &mut ::rand::thread_rng();
+    let pubkey = PrivateKey::new(rng).pubkey(rng);
+    let contract = Address::from(&[1u8; 20][..]);
+    let statements: Vec<Statement> = (0..3).map(|stage| Statement::new(contract, stage, &stage, &(stage + 1), &pubkey)).collect();
+
+    let mut receipts: Vec<Receipt> = vec![];
+    for statement in &statements {
+        let receipt = Receipt::new(statement, "stage", "contribution", H256::zero(), receipts.last());
+        receipts.push(receipt);
+    }
+    assert!(is_chain_valid(&receipts));
+    assert_eq!(receipts[0].previous_receipt, "");
+    assert_eq!(receipts[2].previous_receipt, receipts[1].hash());
+
+    // The fingerprints differ and can be checked.
+    assert_eq!(receipts[0].fingerprint, fingerprint(&statements[0]));
+    assert!(receipts[0].fingerprint != receipts[1].fingerprint);
+    let decoded: Vec<u8> = FromBase58::from_base58check(&receipts[0].fingerprint).unwrap();
+    assert_eq!(decoded.len(), FINGERPRINT_BYTES);
+
+    // Changing or dropping a receipt breaks the chain.
+    let mut tampered = receipts.clone();
+    tampered[1].transaction = format!("{:?}", H256::from(1));
+    assert!(!is_chain_valid(&tampered));
+    receipts.remove(1);
+    assert!(!is_chain_valid(&receipts));
+}
@@ -2,15 +2,18 @@
 
 extern crate bn;
 extern crate rand;
+extern crate rpassword;
 extern crate snark;
 extern crate crossbeam;
+extern crate crypto;
 extern crate rustc_serialize;
+extern crate secp256k1;
 extern crate blake2_rfc;
 extern crate bincode;
 extern crate byteorder;
 extern crate web3;
+extern crate ethabi;
 extern crate hex;
-extern crate json;
 extern crate serde_json;
 extern crate ipfs_api;
 extern crate sha3;
@@ -19,7 +22,7 @@ extern crate time;
 
 #[macro_use]
 extern crate clap;
-use clap::{App};
+use clap::{App, ArgMatches};
 
 #[macro_use]
 extern crate serde_derive; 
@@ -36,42 +39,60 @@ use self::manager::*;
 mod dist_files;
 use self::dist_files::*;
 
+#[macro_use]
 mod blockchain;
 use blockchain::*;
 
+mod contribution;
+
+mod receipt;
+
+mod fork;
+use self::fork::*;
+
+mod export;
+
+mod witness;
+use self::witness::*;
+
 use bincode::rustc_serialize::{decode};
 
 use protocol::*;
 use snark::*;
 use std::env::var;
+use std::fs::File;
+use std::io::Write;
 use std::time::Duration;
 use spinner::SpinnerBuilder;
 
 use web3::{Transport, Web3};
-use web3::transports::Http;
-use web3::types::{Address};
-
-pub struct PlayerResult {
-    player: Address,
-    pubkey: PublicKey,
-    nizks: PublicKeyNizks,
-    stage1: Stage1Contents,
-    stage2: Stage2Contents,
-    stage3: Stage3Contents    
-}
-
 
-fn download_r1cs<T>(contract: &ContractWrapper<T>, ipfs: &mut IPFSWrapper) -> CS where 
+fn download_r1cs<T>(contract: &DistributedMpc<T>, ipfs: &mut IPFSWrapper) -> CS where 
     T: Transport
 {
     let spinner = SpinnerBuilder::new("Querying constraint system hash from Ethereum...".into()).spinner(spinner::DANCING_KIRBY.to_vec()).step(Duration::from_millis(500)).start();
-    let hash: Vec<u8> = contract.query("getConstraintSystem", ());
+    let hash = contract.get_constraint_system();
     spinner.message(format!("Downloading constraint system from ipfs (hash: {:?})...", String::from_utf8(hash.clone()).unwrap()));
     let cs = ipfs.download_cs(String::from_utf8(hash).unwrap().as_str());
     spinner.close();
     cs
 }
 
+/// Creates stage 1, from the powers of tau file if the ceremony imports one.
+/// The file has to have the hash recorded in the contract.
+fn create_stage1<T: Transport>(contract: &DistributedMpc<T>, cs: &CS, powers_of_tau: Option<&str>) -> Stage1Contents {
+    let hash = contract.powers_of_tau();
+    match (powers_of_tau, hash == [0; 32]) {
+        (Some(path), false) => {
+            println!("Importing powers of tau from {:?}...", path);
+            Stage1Contents::from_powers_of_tau(cs, path, &hash).unwrap_or_else(|e| panic!("\u{274c} Error importing powers of tau from {:?}: {}", path, e))
+        },
+        (None, true) => Stage1Contents::new(cs),
+        (Some(_), true) => panic!("\u{274c} The ceremony does not import powers of tau, run it without -t!"),
+        (None, false) => panic!("\u{274c} The ceremony imports stage 1 from the powers of tau file with hash 0x{}, pass it with -t!", hex::encode(&hash[..]))
+    }
+}
+
 fn main() {
     let host_opt = var(HOST_ENV_KEY);
     let mut host = String::from(DEFAULT_HOST);
@@ -82,87 +103,65 @@ fn main() {
 
     let yaml = load_yaml!("../verifier.yml");
     let matches = App::from_yaml(yaml).get_matches();
-    let contract_address = matches.value_of("contract");
 
     println!("Initializing Web3 and IPFS...");
-    let (_eloop, transport) = Http::new(format!("http://{}:8545", host).as_str()).expect("Error connecting to web3 instance!");
-    let manager: Manager<Http> = Manager::new(Web3::new(transport), format!("http://{}", host).as_str(), 5001);
+    with_web3!(web3_endpoint(&host), web3 => verify(&matches, &host, web3))
+}
+
+fn verify<T: Transport>(matches: &ArgMatches, host: &str, web3: Web3<T>) {
+    let contract_address = matches.value_of("contract");
+    let powers_of_tau = matches.value_of("powersoftau");
+    let solidity_path = matches.value_of("solidity");
+    let witness_path = matches.value_of("witness");
+    let zokrates_dir = matches.value_of("zokrates");
+
+    let manager: Manager<T> = Manager::new(web3.clone(), format!("http://{}", host).as_str(), 5001);
     let mut ipfs: IPFSWrapper = IPFSWrapper::new(format!("http://{}", host).as_str(), 5001);
     println!("Successfully initialized.");
 
-    let contract = manager.init_contract(None, contract_address);
+    let contract = manager.init_contract(None, contract_address.unwrap());
 
     let cs = download_r1cs(&contract, &mut ipfs);
-
-    let mut commitments = vec![];
-    let number_of_players: u64 = contract.query("getNumberOfPlayers", ());
-    let mut players: Vec<PlayerResult> = vec![];
-    let spinner = SpinnerBuilder::new("Collecting player information from Ethereum and IPFS...".into()).spinner(spinner::DANCING_KIRBY.to_vec()).step(Duration::from_millis(500)).start();            
-    for i in 0..number_of_players { 
-        let player: Address = contract.query("players", i);
-        let commitment: [u8; 32] = contract.query("getCommitment", player);
-        commitments.push(commitment);
-        let publickey_hash: Vec<u8> = contract.query("getPublicKey", i);
-        let nizks_hash: Vec<u8> = contract.query("getNizks", i);
-        let stage1_hash: Vec<u8> = contract.query("getTransformation", (0, i));
-        let stage2_hash: Vec<u8> = contract.query("getTransformation", (1, i));
-        let stage3_hash: Vec<u8> = contract.query("getTransformation", (2, i));
-        players.push(PlayerResult {
-            player: player,
-            pubkey: ipfs.download_object(String::from_utf8(publickey_hash).expect("Error decoding public key to object").as_str()),
-            nizks: ipfs.download_object(String::from_utf8(nizks_hash).expect("Error decoding nizks to object").as_str()),
-            stage1: ipfs.download_stage(String::from_utf8(stage1_hash).expect("Error decoding stage 1 to object").as_str()),
-            stage2: ipfs.download_stage(String::from_utf8(stage2_hash).expect("Error decoding stage 2 to object").as_str()),
-            stage3: ipfs.download_stage(String::from_utf8(stage3_hash).expect("Error decoding stage 3 to object").as_str())
-        });
+    if contract.current_state().low_u64() == 7 {
+        // the players record the beacon of stage 3 after the protocol, the
+        // verifier only if none of them has
+        let players = contract.get_number_of_players().low_u64();
+        await_beacon(&web3, &contract, 2, players, &Duration::new(1, 0));
     }
-    spinner.close();
-
-    // Hash of all the commitments.
-    let hash_of_commitments = Digest512::from(&commitments).unwrap();
 
-    // Hash of the last message
-    let mut stage1 = &Stage1Contents::new(&cs);
-    for i in 0..players.len() {
-        let pubkey: &PublicKey = &players[i].pubkey;
-        if pubkey.hash() != commitments[i] {
-            panic!("\u{274c} Invalid commitment from player {}", i);
+    let prefix = verify_prefix(&web3, &contract, &cs, powers_of_tau, None, &mut ipfs);
+    if !prefix.complete {
+        match prefix.contributions.last() {
+            Some(last) => panic!("\u{274c} The transcript is only valid up to the stage {} transformation of player {}. The ceremony can be continued from it with `player fork-ceremony`.", last.stage + 1, last.player),
+            None => panic!("\u{274c} The transcript has no valid transformation!")
         }
-        println!("\u{2714} Commitment of player {} matches public key hash", i);
-        let nizks: &PublicKeyNizks = &players[i].nizks;
-        if !nizks.is_valid(&pubkey, &hash_of_commitments) {
-            panic!("\u{274c} Invalid nizks from player {}", i);
-        }
-        println!("\u{2714} Nizks of player {} is valid", i);
-        let new_stage: &Stage1Contents = &players[i].stage1;
-        if !new_stage.verify_transform(&stage1, &pubkey) {
-            panic!("\u{274c} Invalid stage1 transformation from player {}", i);
-        }
-        println!("\u{2714} Stage 1 has been transformed correctly by player {}", i);
-        stage1 = new_stage;
     }
+    let stage1 = prefix.stage1.as_ref().unwrap();
+    let stage2 = prefix.stage2.as_ref().unwrap();
+    let stage3 = prefix.stage3.as_ref().unwrap();
 
-    let mut stage2 = &Stage2Contents::new(&cs, stage1);
-    for i in 0..players.len() {
-        let new_stage: &Stage2Contents = &players[i].stage2;
-        if !new_stage.verify_transform(stage2, &players[i].pubkey) {
-            panic!("\u{274c} Invalid stage2 transformation from player {}", i);
+    let kp = keypair(&cs, stage1, stage2, stage3);
+    kp.write_to_disk();
+    println!("\u{2714} Verification successful. Wrote keypair to disk as (pk, vk).");
+
+    if let Some(path) = witness_path {
+        let witness = Witness::read(path).unwrap_or_else(|e| panic!("\u{274c} Error reading witness {:?}: {}", path, e));
+        witness.check(&cs);
+        let proof = kp.prove(&cs, &witness.primary, &witness.auxiliary);
+        if !kp.verify(&witness.primary, &proof) {
+            panic!("\u{274c} The keypair does not verify a proof for the witness {:?}", path);
         }
-        println!("\u{2714} Stage 2 has been transformed correctly by player {}", i);
-        stage2 = new_stage;
+        println!("\u{2714} The keypair proves and verifies the witness {:?}", path);
     }
 
-    let mut stage3 = &Stage3Contents::new(&cs, stage2);
-    for i in 0..players.len() {
-        let new_stage: &Stage3Contents = &players[i].stage3;
-        if !new_stage.verify_transform(stage3, &players[i].pubkey) {
-            panic!("\u{274c} Invalid stage3 transformation from player {}", i);
-        }
-        println!("\u{2714} Stage 3 has been transformed correctly by player {}", i);
-        stage3 = new_stage;
+    if let Some(dir) = zokrates_dir {
+        export::zokrates::write_keypair(dir, &cs, &kp, &verifying_key(&cs, stage2, stage3)).expect("Error writing the ZoKrates keypair!");
+        println!("\u{2714} Wrote keypair for ZoKrates to {:?} as (proving.key, verification.key)", dir);
     }
 
-    let kp = keypair(&cs, stage1, stage2, stage3);
-    kp.write_to_disk();
-    println!("\u{2714} Verification successful. Wrote keypair to disk as (pk, vk).");
+    if let Some(path) = solidity_path {
+        let contract = export::solidity::verifier_contract(&verifying_key(&cs, stage2, stage3));
+        File::create(path).and_then(|mut f| f.write_all(contract.as_bytes())).expect("Error writing the verifier contract!");
+        println!("\u{2714} Wrote Solidity verifier contract to {:?}", path);
+    }
 }
@@ -0,0 +1,53 @@
+//! Witnesses for the constraint system. A witness is read from a JSON file
+//! of the form `{"primary": ["1", ...], "auxiliary": ["2", ...]}`, with the
+//! field elements given in decimal. The primary input is public, the
+//! auxiliary input is only known to the prover.
+
+use bn::*;
+use serde_json;
+use snark::CS;
+
+use std::fs::File;
+use std::io;
+
+#[derive(Deserialize)]
+struct WitnessFile {
+    primary: Vec<String>,
+    auxiliary: Vec<String>
+}
+
+pub struct Witness {
+    pub primary: Vec<Fr>,
+    pub auxiliary: Vec<Fr>
+}
+
+fn read_frs(values: &[String]) -> io::Result<Vec<Fr>> {
+    values.iter().map(|s| {
+        Fr::from_str(s).ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, format!("invalid field element {:?}", s)))
+    }).collect()
+}
+
+impl Witness {
+    pub fn read(path: &str) -> io::Result<Witness> {
+        let file = try!(File::open(path));
+        let witness: WitnessFile = try!(serde_json::from_reader(file));
+
+        Ok(Witness {
+            primary: try!(read_frs(&witness.primary)),
+            auxiliary: try!(read_frs(&witness.auxiliary))
+        })
+    }
+
+    /// Panics unless the witness satisfies the constraint system.
+    pub fn check(&self, cs: &CS) {
+        if self.primary.len() != cs.num_inputs {
+            panic!("\u{274c} The constraint system has {} primary inputs, the witness {}", cs.num_inputs, self.primary.len());
+        }
+        if self.primary.len() + self.auxiliary.len() + 1 != cs.num_vars {
+            panic!("\u{274c} The constraint system has {} auxiliary inputs, the witness {}", cs.num_vars - cs.num_inputs - 1, self.auxiliary.len());
+        }
+        if !cs.is_satisfied(&self.primary, &self.auxiliary) {
+            panic!("\u{274c} The witness does not satisfy the constraint system");
+        }
+    }
+}